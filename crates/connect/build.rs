@@ -15,10 +15,18 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use std::env;
 use std::fs;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let files = fs::read_dir("./protobuf/spark-3.5/spark/connect/")?;
+    // the `spark-4` feature compiles the Spark 4 protocol instead of Spark 3.5
+    let proto_dir = if env::var_os("CARGO_FEATURE_SPARK_4").is_some() {
+        "./protobuf/spark-4.2/"
+    } else {
+        "./protobuf/spark-3.5/"
+    };
+
+    let files = fs::read_dir(format!("{}spark/connect/", proto_dir))?;
 
     let mut file_paths: Vec<String> = vec![];
 
@@ -29,10 +37,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     tonic_build::configure()
         .protoc_arg("--experimental_allow_proto3_optional")
-        .build_server(false)
+        .build_server(true)
+        .server_mod_attribute(
+            "spark.connect",
+            r#"#[cfg(any(test, feature = "testing", feature = "local"))]"#,
+        )
         .build_client(true)
         .build_transport(true)
-        .compile(file_paths.as_ref(), &["./protobuf/spark-3.5/"])?;
+        .compile(file_paths.as_ref(), &[proto_dir])?;
 
     Ok(())
 }
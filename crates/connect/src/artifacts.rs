@@ -0,0 +1,413 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Upload local jars, files and archives to the Spark Connect server
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::spark;
+use spark::add_artifacts_request::{
+    ArtifactChunk, Batch, BeginChunkedArtifact, Payload, SingleChunkArtifact,
+};
+
+use crate::client::SparkClient;
+use crate::errors::SparkError;
+
+/// Maximum size of a single artifact chunk sent to the server
+pub(crate) const CHUNK_SIZE: usize = 32 * 1024;
+
+/// Prefix of the artifacts that hold cached blobs, e.g. cached local relations
+pub(crate) const CACHE_PREFIX: &str = "cache";
+
+/// A local artifact to be added to the remote Spark Session
+///
+/// The artifact name is the relative path the server stores the artifact under,
+/// e.g. `jars/udfs.jar` or `files/lookup.csv`.
+#[derive(Clone, Debug)]
+pub struct Artifact {
+    name: String,
+    data: Vec<u8>,
+}
+
+impl Artifact {
+    fn from_path(prefix: &str, path: impl AsRef<Path>) -> Result<Artifact, SparkError> {
+        let path = path.as_ref();
+
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| {
+                SparkError::InvalidArgument(format!(
+                    "Artifact path does not contain a file name: {}",
+                    path.display()
+                ))
+            })?;
+
+        Ok(Artifact {
+            name: format!("{}/{}", prefix, file_name),
+            data: std::fs::read(path)?,
+        })
+    }
+
+    /// A jar to be added to the session classpath
+    pub fn jar(path: impl AsRef<Path>) -> Result<Artifact, SparkError> {
+        let path = path.as_ref();
+
+        if path.extension().and_then(|ext| ext.to_str()) != Some("jar") {
+            return Err(SparkError::InvalidArgument(format!(
+                "Jar artifacts must have a '.jar' extension: {}",
+                path.display()
+            )));
+        }
+
+        Artifact::from_path("jars", path)
+    }
+
+    /// A file to be made available to the executors working directory
+    pub fn file(path: impl AsRef<Path>) -> Result<Artifact, SparkError> {
+        Artifact::from_path("files", path)
+    }
+
+    /// An archive (`.zip`, `.jar`, `.tar.gz`, `.tgz`, or `.tar`) to be extracted on the executors
+    pub fn archive(path: impl AsRef<Path>) -> Result<Artifact, SparkError> {
+        Artifact::from_path("archives", path)
+    }
+
+    /// A python file (`.py`, `.zip` or `.egg`) to be added to the python path of the executors
+    pub fn pyfile(path: impl AsRef<Path>) -> Result<Artifact, SparkError> {
+        Artifact::from_path("pyfiles", path)
+    }
+
+    /// A blob stored in the session cache under `cache/<hash>`
+    pub(crate) fn cache(hash: &str, data: Vec<u8>) -> Artifact {
+        Artifact {
+            name: format!("{}/{}", CACHE_PREFIX, hash),
+            data,
+        }
+    }
+
+    /// Name the artifact is stored under on the server
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Size of the artifact in bytes
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// Hex encoded sha-256 hash of a blob, used as the name of cached artifacts
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn artifact_chunk(data: &[u8]) -> ArtifactChunk {
+    ArtifactChunk {
+        data: data.to_vec(),
+        crc: crc32fast::hash(data) as i64,
+    }
+}
+
+/// Convert a set of artifacts into the payloads of an `AddArtifacts` request stream.
+///
+/// Small artifacts are grouped into batches of at most [CHUNK_SIZE] bytes, while any artifact
+/// larger than [CHUNK_SIZE] is sent as a `BeginChunkedArtifact` followed by its remaining chunks.
+pub(crate) fn artifact_payloads(artifacts: Vec<Artifact>) -> Vec<Payload> {
+    let mut payloads = vec![];
+    let mut batch: Vec<SingleChunkArtifact> = vec![];
+    let mut batch_size = 0;
+
+    for artifact in artifacts {
+        if artifact.size() > CHUNK_SIZE {
+            if !batch.is_empty() {
+                payloads.push(Payload::Batch(Batch {
+                    artifacts: std::mem::take(&mut batch),
+                }));
+                batch_size = 0;
+            }
+
+            let mut chunks = artifact.data.chunks(CHUNK_SIZE);
+
+            let initial_chunk = chunks.next().map(artifact_chunk);
+
+            payloads.push(Payload::BeginChunk(BeginChunkedArtifact {
+                name: artifact.name,
+                total_bytes: artifact.data.len() as i64,
+                num_chunks: artifact.data.len().div_ceil(CHUNK_SIZE) as i64,
+                initial_chunk,
+            }));
+
+            payloads.extend(chunks.map(|chunk| Payload::Chunk(artifact_chunk(chunk))));
+        } else {
+            if batch_size + artifact.size() > CHUNK_SIZE {
+                payloads.push(Payload::Batch(Batch {
+                    artifacts: std::mem::take(&mut batch),
+                }));
+                batch_size = 0;
+            }
+
+            batch_size += artifact.size();
+
+            batch.push(SingleChunkArtifact {
+                data: Some(artifact_chunk(&artifact.data)),
+                name: artifact.name,
+            });
+        }
+    }
+
+    if !batch.is_empty() {
+        payloads.push(Payload::Batch(Batch { artifacts: batch }));
+    }
+
+    payloads
+}
+
+/// Manages the artifacts added to a remote Spark Session, accessible through `SparkSession::artifacts`.
+///
+/// # Example
+/// ```rust
+/// spark
+///    .artifacts()
+///    .add_artifacts([Artifact::jar("/opt/udfs/my-udfs.jar")?])
+///    .await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct ArtifactManager {
+    pub(crate) client: SparkClient,
+}
+
+impl ArtifactManager {
+    pub fn new(client: &SparkClient) -> ArtifactManager {
+        ArtifactManager {
+            client: client.clone(),
+        }
+    }
+
+    /// Upload the artifacts to the server.
+    ///
+    /// Returns the names of the artifacts the server received
+    pub async fn add_artifacts<I>(&self, artifacts: I) -> Result<Vec<String>, SparkError>
+    where
+        I: IntoIterator<Item = Artifact>,
+    {
+        let payloads = artifact_payloads(artifacts.into_iter().collect());
+
+        if payloads.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let resp = self.client.add_artifacts_request(payloads).await?;
+
+        if let Some(failed) = resp.artifacts.iter().find(|a| !a.is_crc_successful) {
+            return Err(SparkError::DataLoss(format!(
+                "CRC check failed on the server for artifact '{}'",
+                failed.name
+            )));
+        }
+
+        Ok(resp.artifacts.into_iter().map(|a| a.name).collect())
+    }
+
+    /// Check which artifacts already exist on the server.
+    ///
+    /// Returns a map of the artifact name to whether it exists. Spark 3.5 only reports the
+    /// status of the `cache/` artifacts of the session cache, and returns `false` for any
+    /// jar, file or archive, even once it has been added.
+    pub async fn artifact_statuses<I>(&self, names: I) -> Result<HashMap<String, bool>, SparkError>
+    where
+        I: IntoIterator<Item: AsRef<str>>,
+    {
+        let names = names
+            .into_iter()
+            .map(|name| name.as_ref().to_string())
+            .collect();
+
+        let resp = self.client.artifact_status_request(names).await?;
+
+        Ok(resp
+            .statuses
+            .into_iter()
+            .map(|(name, status)| (name, status.exists))
+            .collect())
+    }
+
+    /// Check if a blob with the given hash is already in the session cache
+    pub(crate) async fn is_cached_artifact(&self, hash: &str) -> Result<bool, SparkError> {
+        let name = format!("{}/{}", CACHE_PREFIX, hash);
+
+        let statuses = self.artifact_statuses([&name]).await?;
+
+        Ok(statuses.get(&name).copied().unwrap_or(false))
+    }
+
+    /// Add a blob to the session cache and return its sha-256 hash.
+    ///
+    /// The upload is skipped when the server already holds a blob with the same hash.
+    pub(crate) async fn cache_artifact(&self, blob: Vec<u8>) -> Result<String, SparkError> {
+        let hash = sha256_hex(&blob);
+
+        if !self.is_cached_artifact(&hash).await? {
+            self.add_artifacts([Artifact::cache(&hash, blob)]).await?;
+        }
+
+        Ok(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artifact(name: &str, size: usize) -> Artifact {
+        Artifact {
+            name: name.to_string(),
+            data: (0..size).map(|i| (i % 251) as u8).collect(),
+        }
+    }
+
+    #[test]
+    fn test_artifact_names() -> Result<(), SparkError> {
+        let path = "../../datasets/people.csv";
+
+        assert_eq!("files/people.csv", Artifact::file(path)?.name());
+        assert_eq!("archives/people.csv", Artifact::archive(path)?.name());
+        assert!(Artifact::jar(path).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_artifact_name() {
+        let hash = sha256_hex(b"abc");
+
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            hash
+        );
+        assert_eq!(
+            "cache/ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            Artifact::cache(&hash, vec![]).name()
+        );
+    }
+
+    #[test]
+    fn test_small_artifacts_are_batched() {
+        let payloads = artifact_payloads(vec![
+            artifact("jars/a.jar", 10),
+            artifact("jars/b.jar", 20),
+            artifact("jars/c.jar", CHUNK_SIZE),
+        ]);
+
+        assert_eq!(2, payloads.len());
+
+        match &payloads[0] {
+            Payload::Batch(batch) => {
+                assert_eq!(2, batch.artifacts.len());
+                let chunk = batch.artifacts[1].data.as_ref().unwrap();
+                assert_eq!(crc32fast::hash(&chunk.data) as i64, chunk.crc);
+            }
+            _ => panic!("expected a batch payload"),
+        }
+    }
+
+    #[test]
+    fn test_large_artifacts_are_chunked() {
+        let size = CHUNK_SIZE * 2 + 100;
+
+        let payloads = artifact_payloads(vec![
+            artifact("files/small.txt", 10),
+            artifact("jars/large.jar", size),
+        ]);
+
+        assert_eq!(4, payloads.len());
+
+        match &payloads[1] {
+            Payload::BeginChunk(begin) => {
+                assert_eq!("jars/large.jar", begin.name);
+                assert_eq!(size as i64, begin.total_bytes);
+                assert_eq!(3, begin.num_chunks);
+                assert_eq!(CHUNK_SIZE, begin.initial_chunk.as_ref().unwrap().data.len());
+            }
+            _ => panic!("expected a begin chunk payload"),
+        }
+
+        match &payloads[3] {
+            Payload::Chunk(chunk) => {
+                assert_eq!(100, chunk.data.len());
+                assert_eq!(crc32fast::hash(&chunk.data) as i64, chunk.crc);
+            }
+            _ => panic!("expected a chunk payload"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_artifacts_mock() -> Result<(), SparkError> {
+        use crate::testing::MockSparkServer;
+
+        let server = MockSparkServer::start().await?;
+        let spark = server.session().await?;
+
+        let small = artifact("files/small.txt", 10);
+        let large = artifact("jars/large.jar", CHUNK_SIZE * 2 + 100);
+
+        let names = spark
+            .artifacts()
+            .add_artifacts([small.clone(), large.clone()])
+            .await?;
+
+        assert_eq!(vec!["files/small.txt", "jars/large.jar"], names);
+
+        let artifacts = server.artifacts();
+
+        assert_eq!(small.data, artifacts["files/small.txt"]);
+        assert_eq!(large.data, artifacts["jars/large.jar"]);
+        assert_eq!(4, server.requests().add_artifacts.len());
+
+        // only the artifacts of the session cache are reported
+        let statuses = spark
+            .artifacts()
+            .artifact_statuses(["jars/large.jar", "cache/missing"])
+            .await?;
+
+        assert_eq!(Some(&false), statuses.get("jars/large.jar"));
+        assert_eq!(Some(&false), statuses.get("cache/missing"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_artifacts_crc_failure_mock() -> Result<(), SparkError> {
+        use crate::testing::MockSparkServer;
+
+        let server = MockSparkServer::start().await?;
+        let spark = server.session().await?;
+
+        server.fail_artifact_crc("jars/large.jar");
+
+        let result = spark
+            .artifacts()
+            .add_artifacts([artifact("jars/large.jar", CHUNK_SIZE + 1)])
+            .await;
+
+        assert!(matches!(result, Err(SparkError::DataLoss(_))));
+        assert!(server.artifacts().is_empty());
+        Ok(())
+    }
+}
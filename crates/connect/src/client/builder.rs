@@ -19,10 +19,22 @@
 
 use std::collections::HashMap;
 use std::env;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
+use crate::client::{CredentialProvider, StaticTokenProvider};
 use crate::errors::SparkError;
 
+use tonic::codegen::{Body, Bytes, StdError};
+use tonic::transport::Endpoint;
+
+#[cfg(feature = "tls")]
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+
+use crate::spark::spark_connect_service_client::SparkConnectServiceClient;
+
 use url::Url;
 
 use uuid::Uuid;
@@ -31,6 +43,33 @@ pub(crate) type Host = String;
 pub(crate) type Port = u16;
 pub(crate) type UrlParse = (Host, Port, Option<HashMap<String, String>>);
 
+/// Default limit for the size of a gRPC message, matching the PySpark client
+pub(crate) const DEFAULT_MAX_MESSAGE_SIZE: usize = 128 * 1024 * 1024;
+
+/// Compression applied to gRPC messages sent to and received from the server
+///
+/// Requires the `gzip` or `zstd` feature respectively
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = SparkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            other => Err(SparkError::InvalidConnectionUrl(format!(
+                "Unsupported compression '{}', expected 'gzip' or 'zstd'",
+                other
+            ))),
+        }
+    }
+}
+
 /// ChannelBuilder validates a connection string
 /// based on the requirements from [Spark Documentation](https://github.com/apache/spark/blob/master/connector/connect/docs/client-connection-string.md)
 #[derive(Clone, Debug)]
@@ -43,6 +82,19 @@ pub struct ChannelBuilder {
     pub(super) user_agent: Option<String>,
     pub(super) use_ssl: bool,
     pub(super) headers: Option<HashMap<String, String>>,
+    pub(super) credentials: Option<Arc<dyn CredentialProvider>>,
+    pub(super) ca_cert_paths: Vec<PathBuf>,
+    pub(super) client_cert_path: Option<PathBuf>,
+    pub(super) client_key_path: Option<PathBuf>,
+    pub(super) tls_domain_name: Option<String>,
+    pub(super) max_message_size: Option<usize>,
+    pub(super) compression: Option<Compression>,
+    pub(super) connect_timeout: Option<Duration>,
+    pub(super) keep_alive_interval: Option<Duration>,
+    pub(super) keep_alive_timeout: Option<Duration>,
+    pub(super) keep_alive_while_idle: bool,
+    pub(super) initial_stream_window_size: Option<u32>,
+    pub(super) initial_connection_window_size: Option<u32>,
 }
 
 impl Default for ChannelBuilder {
@@ -62,11 +114,7 @@ impl ChannelBuilder {
     }
 
     pub(crate) fn endpoint(&self) -> String {
-        let scheme = if cfg!(feature = "tls") {
-            "https"
-        } else {
-            "http"
-        };
+        let scheme = if self.use_ssl { "https" } else { "http" };
 
         format!("{}://{}:{}", scheme, self.host, self.port)
     }
@@ -75,6 +123,177 @@ impl ChannelBuilder {
         self.headers.to_owned()
     }
 
+    pub(crate) fn credentials(&self) -> Option<Arc<dyn CredentialProvider>> {
+        self.credentials.clone()
+    }
+
+    /// Set the [CredentialProvider] used to authenticate each request,
+    /// replacing any `token` from the connection string
+    pub(crate) fn set_credentials(&mut self, credentials: Arc<dyn CredentialProvider>) {
+        self.credentials = Some(credentials);
+    }
+
+    /// Apply the timeout, keepalive, and HTTP/2 flow control settings to the endpoint
+    pub(crate) fn configure_endpoint(&self, mut endpoint: Endpoint) -> Endpoint {
+        if let Some(timeout) = self.connect_timeout {
+            endpoint = endpoint.connect_timeout(timeout);
+        }
+
+        if let Some(interval) = self.keep_alive_interval {
+            endpoint = endpoint
+                .http2_keep_alive_interval(interval)
+                .keep_alive_while_idle(self.keep_alive_while_idle);
+        }
+
+        if let Some(timeout) = self.keep_alive_timeout {
+            endpoint = endpoint.keep_alive_timeout(timeout);
+        }
+
+        endpoint
+            .initial_stream_window_size(self.initial_stream_window_size)
+            .initial_connection_window_size(self.initial_connection_window_size)
+    }
+
+    /// Apply the message size limit and compression to the generated client
+    pub(crate) fn configure_client<T>(
+        &self,
+        client: SparkConnectServiceClient<T>,
+    ) -> Result<SparkConnectServiceClient<T>, SparkError>
+    where
+        T: tonic::client::GrpcService<tonic::body::BoxBody>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data = Bytes> + Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+    {
+        let max_message_size = self.max_message_size.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE);
+
+        let client = client
+            .max_decoding_message_size(max_message_size)
+            .max_encoding_message_size(max_message_size);
+
+        match self.compression {
+            None => Ok(client),
+            #[cfg(feature = "gzip")]
+            Some(Compression::Gzip) => {
+                let encoding = tonic::codec::CompressionEncoding::Gzip;
+
+                Ok(client.send_compressed(encoding).accept_compressed(encoding))
+            }
+            #[cfg(feature = "zstd")]
+            Some(Compression::Zstd) => {
+                let encoding = tonic::codec::CompressionEncoding::Zstd;
+
+                Ok(client.send_compressed(encoding).accept_compressed(encoding))
+            }
+            #[allow(unreachable_patterns)]
+            Some(compression) => {
+                let feature = format!("{:?}", compression).to_lowercase();
+
+                Err(SparkError::InvalidArgument(format!(
+                    "{:?} compression requires the '{}' feature, but it's not enabled!",
+                    compression, feature
+                )))
+            }
+        }
+    }
+
+    /// Build the [ClientTlsConfig] for the channel from the configured CA certificates,
+    /// client certificate and key, and domain name override.
+    ///
+    /// Returns None when no TLS options are set, so the default roots are used, or when
+    /// `use_ssl` is not set and the channel is plaintext
+    #[cfg(feature = "tls")]
+    pub(crate) fn tls_config(&self) -> Result<Option<ClientTlsConfig>, SparkError> {
+        let has_tls_options = !self.ca_cert_paths.is_empty()
+            || self.client_cert_path.is_some()
+            || self.client_key_path.is_some()
+            || self.tls_domain_name.is_some();
+
+        if !self.use_ssl {
+            if has_tls_options {
+                return Err(SparkError::InvalidArgument(
+                    "TLS options require 'use_ssl=true'".to_string(),
+                ));
+            }
+
+            return Ok(None);
+        }
+
+        if !has_tls_options {
+            return Ok(None);
+        }
+
+        let mut tls = ClientTlsConfig::new();
+
+        if let Some(ca_certificate) = self.ca_certificate()? {
+            tls = tls.ca_certificate(ca_certificate);
+        }
+
+        match (&self.client_cert_path, &self.client_key_path) {
+            (Some(cert), Some(key)) => {
+                tls = tls.identity(Identity::from_pem(
+                    std::fs::read(cert)?,
+                    std::fs::read(key)?,
+                ));
+            }
+            (None, None) => {}
+            _ => {
+                return Err(SparkError::InvalidArgument(
+                    "Mutual TLS requires both a client certificate and a client key".to_string(),
+                ))
+            }
+        }
+
+        if let Some(domain_name) = &self.tls_domain_name {
+            tls = tls.domain_name(domain_name);
+        }
+
+        Ok(Some(tls))
+    }
+
+    /// All of the configured CA certificates as a single PEM bundle, since the
+    /// [ClientTlsConfig] only keeps the last CA certificate it is given
+    #[cfg(feature = "tls")]
+    fn ca_certificate(&self) -> Result<Option<Certificate>, SparkError> {
+        if self.ca_cert_paths.is_empty() {
+            return Ok(None);
+        }
+
+        let mut pem = vec![];
+
+        for path in &self.ca_cert_paths {
+            pem.extend(std::fs::read(path)?);
+
+            if !pem.ends_with(b"\n") {
+                pem.push(b'\n');
+            }
+        }
+
+        Ok(Some(Certificate::from_pem(pem)))
+    }
+
+    /// TLS options can not be applied without the `tls` feature
+    #[cfg(not(feature = "tls"))]
+    pub(crate) fn tls_config(&self) -> Result<Option<()>, SparkError> {
+        let has_tls_options = !self.ca_cert_paths.is_empty()
+            || self.client_cert_path.is_some()
+            || self.client_key_path.is_some()
+            || self.tls_domain_name.is_some();
+
+        if self.use_ssl || has_tls_options {
+            return Err(ChannelBuilder::tls_feature_error());
+        }
+
+        Ok(None)
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn tls_feature_error() -> SparkError {
+        SparkError::InvalidConnectionUrl(
+            "The 'use_ssl' option requires the 'tls' feature, but it's not enabled!".to_string(),
+        )
+    }
+
     pub(crate) fn create_user_agent(user_agent: Option<&str>) -> Option<String> {
         let user_agent = user_agent.unwrap_or("_SPARK_CONNECT_RUST");
         let pkg_version = env!("CARGO_PKG_VERSION");
@@ -158,8 +377,21 @@ impl ChannelBuilder {
         Some(headers)
     }
 
+    fn parse_seconds(name: &str, value: &str) -> Result<Duration, SparkError> {
+        value.parse().map(Duration::from_secs).map_err(|_| {
+            SparkError::InvalidConnectionUrl(format!(
+                "'{}' must be a number of seconds, got '{}'",
+                name, value
+            ))
+        })
+    }
+
     /// Create and validate a connnection string
-    #[allow(unreachable_code)]
+    ///
+    /// Besides the parameters defined by the Spark connection string spec, the client
+    /// specific parameters `max_message_size` (bytes), `compression` (`gzip`),
+    /// `connect_timeout` (seconds), and `keep_alive_interval` (seconds) are parsed.
+    /// All other parameters are sent as headers.
     pub fn create(connection: &str) -> Result<ChannelBuilder, SparkError> {
         let (host, port, headers) = ChannelBuilder::parse_connection_string(connection)?;
 
@@ -172,6 +404,19 @@ impl ChannelBuilder {
             user_agent: ChannelBuilder::create_user_agent(None),
             use_ssl: false,
             headers: None,
+            credentials: None,
+            ca_cert_paths: vec![],
+            client_cert_path: None,
+            client_key_path: None,
+            tls_domain_name: None,
+            max_message_size: None,
+            compression: None,
+            connect_timeout: None,
+            keep_alive_interval: None,
+            keep_alive_timeout: None,
+            keep_alive_while_idle: false,
+            initial_stream_window_size: None,
+            initial_connection_window_size: None,
         };
 
         if let Some(mut headers) = headers {
@@ -186,9 +431,8 @@ impl ChannelBuilder {
                 .unwrap_or_else(|| ChannelBuilder::create_user_agent(None));
 
             if let Some(token) = headers.remove("token") {
-                let token = format!("Bearer {token}");
-                channel_builder.token = Some(token.clone());
-                headers.insert("authorization".to_string(), token);
+                channel_builder.credentials = Some(Arc::new(StaticTokenProvider::new(&token)));
+                channel_builder.token = Some(format!("Bearer {token}"));
             }
 
             if let Some(session_id) = headers.remove("session_id") {
@@ -198,15 +442,41 @@ impl ChannelBuilder {
             if let Some(use_ssl) = headers.remove("use_ssl") {
                 if use_ssl.to_lowercase() == "true" {
                     #[cfg(not(feature = "tls"))]
+                    return Err(ChannelBuilder::tls_feature_error());
+
+                    #[cfg(feature = "tls")]
                     {
-                        panic!(
-                        "The 'use_ssl' option requires the 'tls' feature, but it's not enabled!"
-                    );
-                    };
-                    channel_builder.use_ssl = true
+                        channel_builder.use_ssl = true
+                    }
                 }
             };
 
+            // transport settings only apply to the client, and are not sent as headers
+            if let Some(size) = headers.remove("max_message_size") {
+                channel_builder.max_message_size = Some(size.parse().map_err(|_| {
+                    SparkError::InvalidConnectionUrl(format!(
+                        "'max_message_size' must be a number of bytes, got '{}'",
+                        size
+                    ))
+                })?);
+            }
+
+            if let Some(compression) = headers.remove("compression") {
+                channel_builder.compression = Some(compression.parse()?);
+            }
+
+            if let Some(timeout) = headers.remove("connect_timeout") {
+                channel_builder.connect_timeout =
+                    Some(ChannelBuilder::parse_seconds("connect_timeout", &timeout)?);
+            }
+
+            if let Some(interval) = headers.remove("keep_alive_interval") {
+                channel_builder.keep_alive_interval = Some(ChannelBuilder::parse_seconds(
+                    "keep_alive_interval",
+                    &interval,
+                )?);
+            }
+
             if !headers.is_empty() {
                 channel_builder.headers = Some(headers);
             }
@@ -261,13 +531,120 @@ mod tests {
         assert_eq!("user123".to_string(), builder.user_id.unwrap());
     }
 
+    #[tokio::test]
+    async fn test_token_credentials() -> Result<(), SparkError> {
+        let connection = "sc://myhost.com:443/;token=ABCDEFG";
+
+        let builder = ChannelBuilder::create(connection)?;
+
+        let token = builder.credentials().unwrap().token().await?;
+
+        assert_eq!("ABCDEFG".to_string(), token);
+        assert!(builder.headers().is_none());
+        Ok(())
+    }
+
     #[test]
-    #[should_panic(
-        expected = "The 'use_ssl' option requires the 'tls' feature, but it's not enabled!"
-    )]
-    fn test_panic_ssl() {
+    fn test_transport_settings() {
+        let connection = "sc://myhost.com:443/;max_message_size=268435456;compression=gzip;connect_timeout=10;keep_alive_interval=30;x-custom=value";
+
+        let builder = ChannelBuilder::create(connection).unwrap();
+
+        assert_eq!(Some(268435456), builder.max_message_size);
+        assert_eq!(Some(Compression::Gzip), builder.compression);
+        assert_eq!(Some(Duration::from_secs(10)), builder.connect_timeout);
+        assert_eq!(Some(Duration::from_secs(30)), builder.keep_alive_interval);
+        assert_eq!(
+            HashMap::from([("x-custom".to_string(), "value".to_string())]),
+            builder.headers().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid_transport_settings() {
+        assert!(ChannelBuilder::create("sc://127.0.0.1:15002/;compression=brotli").is_err());
+        assert!(ChannelBuilder::create("sc://127.0.0.1:15002/;connect_timeout=soon").is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "tls"))]
+    fn test_ssl_requires_tls_feature() {
         let connection = "sc://127.0.0.1:443/;use_ssl=true";
 
-        ChannelBuilder::create(connection).unwrap();
+        assert!(matches!(
+            ChannelBuilder::create(connection),
+            Err(SparkError::InvalidConnectionUrl(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "tls")]
+    fn test_plaintext_with_tls_feature() {
+        let mut builder = ChannelBuilder::create("sc://127.0.0.1:15002/").unwrap();
+
+        assert_eq!("http://127.0.0.1:15002", builder.endpoint());
+        assert!(builder.tls_config().unwrap().is_none());
+
+        builder.tls_domain_name = Some("spark.example.com".to_string());
+
+        assert!(matches!(
+            builder.tls_config(),
+            Err(SparkError::InvalidArgument(_))
+        ));
+
+        let builder = ChannelBuilder::create("sc://127.0.0.1:443/;use_ssl=true").unwrap();
+
+        assert_eq!("https://127.0.0.1:443", builder.endpoint());
+    }
+
+    #[test]
+    #[cfg(feature = "tls")]
+    fn test_multiple_ca_certs() -> Result<(), SparkError> {
+        let dir = std::env::temp_dir();
+
+        let first = dir.join(format!("spark-connect-ca-{}.pem", uuid::Uuid::new_v4()));
+        let second = dir.join(format!("spark-connect-ca-{}.pem", uuid::Uuid::new_v4()));
+
+        std::fs::write(
+            &first,
+            "-----BEGIN CERTIFICATE-----\nfirst\n-----END CERTIFICATE-----",
+        )?;
+        std::fs::write(
+            &second,
+            "-----BEGIN CERTIFICATE-----\nsecond\n-----END CERTIFICATE-----\n",
+        )?;
+
+        let mut builder = ChannelBuilder::create("sc://127.0.0.1:443/;use_ssl=true").unwrap();
+
+        builder.ca_cert_paths = vec![first.clone(), second.clone()];
+
+        let ca_certificate = builder.ca_certificate()?.unwrap();
+
+        assert_eq!(
+            "-----BEGIN CERTIFICATE-----\nfirst\n-----END CERTIFICATE-----\n\
+            -----BEGIN CERTIFICATE-----\nsecond\n-----END CERTIFICATE-----\n"
+                .as_bytes(),
+            ca_certificate.get_ref()
+        );
+        assert!(builder.tls_config()?.is_some());
+
+        std::fs::remove_file(first)?;
+        std::fs::remove_file(second)?;
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "tls")]
+    fn test_tls_config_requires_client_key() {
+        let mut builder = ChannelBuilder::create("sc://127.0.0.1:443/;use_ssl=true").unwrap();
+
+        assert!(builder.tls_config().unwrap().is_none());
+
+        builder.client_cert_path = Some(PathBuf::from("client.pem"));
+
+        assert!(matches!(
+            builder.tls_config(),
+            Err(SparkError::InvalidArgument(_))
+        ));
     }
 }
@@ -16,10 +16,13 @@
 // under the License.
 
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
-use crate::client::builder::{Host, Port};
-use crate::client::ChannelBuilder;
+use crate::client::builder::{Compression, Host, Port};
+use crate::client::{ChannelBuilder, CredentialProvider, StaticTokenProvider};
 
 /// Config handler to set custom SparkSessionBuilder options
 #[derive(Clone, Debug, Default)]
@@ -32,6 +35,19 @@ pub struct Config {
     pub user_agent: Option<String>,
     pub use_ssl: bool,
     pub headers: Option<HashMap<String, String>>,
+    pub credentials: Option<Arc<dyn CredentialProvider>>,
+    pub ca_cert_paths: Vec<PathBuf>,
+    pub client_cert_path: Option<PathBuf>,
+    pub client_key_path: Option<PathBuf>,
+    pub tls_domain_name: Option<String>,
+    pub max_message_size: Option<usize>,
+    pub compression: Option<Compression>,
+    pub connect_timeout: Option<Duration>,
+    pub keep_alive_interval: Option<Duration>,
+    pub keep_alive_timeout: Option<Duration>,
+    pub keep_alive_while_idle: bool,
+    pub initial_stream_window_size: Option<u32>,
+    pub initial_connection_window_size: Option<u32>,
 }
 
 impl Config {
@@ -45,6 +61,19 @@ impl Config {
             user_agent: ChannelBuilder::create_user_agent(None),
             use_ssl: false,
             headers: None,
+            credentials: None,
+            ca_cert_paths: vec![],
+            client_cert_path: None,
+            client_key_path: None,
+            tls_domain_name: None,
+            max_message_size: None,
+            compression: None,
+            connect_timeout: None,
+            keep_alive_interval: None,
+            keep_alive_timeout: None,
+            keep_alive_while_idle: false,
+            initial_stream_window_size: None,
+            initial_connection_window_size: None,
         }
     }
 
@@ -87,20 +116,96 @@ impl Config {
         self.headers = Some(val);
         self
     }
+
+    pub fn credentials(mut self, val: impl CredentialProvider + 'static) -> Self {
+        self.credentials = Some(Arc::new(val));
+        self
+    }
+
+    /// Trust the CA certificates in a PEM file, in addition to the system roots.
+    ///
+    /// Can be called multiple times to add several CA bundles
+    pub fn ca_cert(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_cert_paths.push(path.into());
+        self
+    }
+
+    /// Authenticate with a client certificate and private key in PEM files (mutual TLS)
+    pub fn client_cert(mut self, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        self.client_cert_path = Some(cert.into());
+        self.client_key_path = Some(key.into());
+        self
+    }
+
+    /// Override the domain name used to verify the server certificate (SNI)
+    pub fn tls_domain_name(mut self, val: &str) -> Self {
+        self.tls_domain_name = Some(val.to_string());
+        self
+    }
+
+    /// Maximum size in bytes of a message sent or received, defaults to 128 MiB
+    pub fn max_message_size(mut self, val: usize) -> Self {
+        self.max_message_size = Some(val);
+        self
+    }
+
+    /// Compress requests, and accept compressed responses
+    pub fn compression(mut self, val: Compression) -> Self {
+        self.compression = Some(val);
+        self
+    }
+
+    pub fn connect_timeout(mut self, val: Duration) -> Self {
+        self.connect_timeout = Some(val);
+        self
+    }
+
+    /// Interval between HTTP/2 keepalive pings, which keep long running queries
+    /// from being dropped by load balancers
+    pub fn keep_alive_interval(mut self, val: Duration) -> Self {
+        self.keep_alive_interval = Some(val);
+        self
+    }
+
+    /// Time to wait for a keepalive ping to be acknowledged before closing the connection
+    pub fn keep_alive_timeout(mut self, val: Duration) -> Self {
+        self.keep_alive_timeout = Some(val);
+        self
+    }
+
+    /// Send keepalive pings while there are no active requests
+    pub fn keep_alive_while_idle(mut self, val: bool) -> Self {
+        self.keep_alive_while_idle = val;
+        self
+    }
+
+    /// HTTP/2 flow control window of each stream in bytes
+    pub fn initial_stream_window_size(mut self, val: u32) -> Self {
+        self.initial_stream_window_size = Some(val);
+        self
+    }
+
+    /// HTTP/2 flow control window of the connection in bytes
+    pub fn initial_connection_window_size(mut self, val: u32) -> Self {
+        self.initial_connection_window_size = Some(val);
+        self
+    }
 }
 
 impl From<Config> for ChannelBuilder {
     fn from(config: Config) -> Self {
-        // if there is a token, then it needs to be added to the headers
+        // if there is a token without a credential provider, then it is used as a static token
         // do not overwrite any existing authentication header
 
-        let mut headers = config.headers.unwrap_or_default();
+        let headers = config.headers.unwrap_or_default();
 
-        if let Some(token) = &config.token {
-            headers
-                .entry("authorization".to_string())
-                .or_insert_with(|| format!("Bearer {}", token));
-        }
+        let credentials = match (&config.credentials, &config.token) {
+            (Some(credentials), _) => Some(credentials.clone()),
+            (None, Some(token)) if !headers.contains_key("authorization") => {
+                Some(Arc::new(StaticTokenProvider::new(token)) as Arc<dyn CredentialProvider>)
+            }
+            _ => None,
+        };
 
         Self {
             host: config.host,
@@ -115,6 +220,19 @@ impl From<Config> for ChannelBuilder {
             } else {
                 Some(headers)
             },
+            credentials,
+            ca_cert_paths: config.ca_cert_paths,
+            client_cert_path: config.client_cert_path,
+            client_key_path: config.client_key_path,
+            tls_domain_name: config.tls_domain_name,
+            max_message_size: config.max_message_size,
+            compression: config.compression,
+            connect_timeout: config.connect_timeout,
+            keep_alive_interval: config.keep_alive_interval,
+            keep_alive_timeout: config.keep_alive_timeout,
+            keep_alive_while_idle: config.keep_alive_while_idle,
+            initial_stream_window_size: config.initial_stream_window_size,
+            initial_connection_window_size: config.initial_connection_window_size,
         }
     }
 }
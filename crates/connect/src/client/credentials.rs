@@ -0,0 +1,336 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Credential providers used to authenticate each gRPC request
+
+use std::env;
+use std::fmt::Debug;
+
+use futures_util::future::{BoxFuture, FutureExt};
+
+use crate::errors::SparkError;
+
+/// Supplies the bearer token sent in the `authorization` header of every request
+///
+/// The provider is queried by the [HeadersMiddleware](crate::client::HeadersMiddleware) before
+/// each request, so implementations should cache tokens and only refresh them when needed.
+///
+/// # Example
+/// ```rust
+/// let provider = EnvTokenProvider::new("DATABRICKS_TOKEN");
+///
+/// let spark = SparkSessionBuilder::remote("sc://myhost.com:443/;use_ssl=true")
+///     .credentials(provider)
+///     .build()
+///     .await?;
+/// ```
+pub trait CredentialProvider: Debug + Send + Sync {
+    /// Returns the bearer token used to authenticate the next request
+    fn token(&self) -> BoxFuture<'_, Result<String, SparkError>>;
+
+    /// Discard any cached credentials, so the next call to `token` fetches fresh ones.
+    ///
+    /// Called when the server rejects a request as unauthenticated
+    fn invalidate(&self) {}
+}
+
+/// A fixed token, e.g. a personal access token
+#[derive(Clone)]
+pub struct StaticTokenProvider {
+    token: String,
+}
+
+impl StaticTokenProvider {
+    pub fn new(token: impl Into<String>) -> Self {
+        StaticTokenProvider {
+            token: token.into(),
+        }
+    }
+}
+
+impl Debug for StaticTokenProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StaticTokenProvider")
+            .field("token", &"***")
+            .finish()
+    }
+}
+
+impl CredentialProvider for StaticTokenProvider {
+    fn token(&self) -> BoxFuture<'_, Result<String, SparkError>> {
+        let token = self.token.clone();
+        async move { Ok(token) }.boxed()
+    }
+}
+
+/// A token read from an environment variable on every request
+///
+/// Rotating the variable takes effect without reconnecting the session.
+#[derive(Clone, Debug)]
+pub struct EnvTokenProvider {
+    var: String,
+}
+
+impl EnvTokenProvider {
+    pub fn new(var: impl Into<String>) -> Self {
+        EnvTokenProvider { var: var.into() }
+    }
+}
+
+impl CredentialProvider for EnvTokenProvider {
+    fn token(&self) -> BoxFuture<'_, Result<String, SparkError>> {
+        let token = env::var(&self.var).map_err(|_| {
+            SparkError::Unauthenticated(format!(
+                "Environment variable '{}' with the token is not set",
+                self.var
+            ))
+        });
+
+        async move { token }.boxed()
+    }
+}
+
+#[cfg(feature = "oauth")]
+pub use oauth::OAuthClientCredentialsProvider;
+
+#[cfg(feature = "oauth")]
+mod oauth {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::{Duration, Instant};
+
+    use futures_util::future::{BoxFuture, FutureExt};
+
+    use tokio::sync::Mutex;
+
+    use super::CredentialProvider;
+    use crate::errors::SparkError;
+
+    /// Tokens are refreshed this long before they expire
+    const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+    /// Lifetime assumed when the token response has no `expires_in`
+    const DEFAULT_EXPIRES_IN: u64 = 3600;
+
+    #[derive(Debug)]
+    struct CachedToken {
+        token: String,
+        expires_at: Instant,
+        /// Value of the provider generation when the token was fetched
+        generation: u64,
+    }
+
+    /// Tokens fetched with the OAuth 2.0 client credentials flow, refreshed before they expire
+    ///
+    /// Requires the `oauth` feature.
+    ///
+    /// # Example
+    /// ```rust
+    /// // Databricks OAuth machine-to-machine authentication
+    /// let provider = OAuthClientCredentialsProvider::new(
+    ///     "https://<workspace>.cloud.databricks.com/oidc/v1/token",
+    ///     "<client-id>",
+    ///     "<client-secret>",
+    /// )
+    /// .scope("all-apis");
+    /// ```
+    pub struct OAuthClientCredentialsProvider {
+        token_url: String,
+        client_id: String,
+        client_secret: String,
+        scope: Option<String>,
+        http: reqwest::Client,
+        cached: Mutex<Option<CachedToken>>,
+        /// Incremented by `invalidate`, so tokens fetched before are never reused, even
+        /// when the cache is locked by a concurrent refresh
+        generation: AtomicU64,
+    }
+
+    impl OAuthClientCredentialsProvider {
+        pub fn new(
+            token_url: impl Into<String>,
+            client_id: impl Into<String>,
+            client_secret: impl Into<String>,
+        ) -> Self {
+            OAuthClientCredentialsProvider {
+                token_url: token_url.into(),
+                client_id: client_id.into(),
+                client_secret: client_secret.into(),
+                scope: None,
+                http: reqwest::Client::new(),
+                cached: Mutex::new(None),
+                generation: AtomicU64::new(0),
+            }
+        }
+
+        /// Scope requested for the token, e.g. `all-apis` for Databricks
+        pub fn scope(mut self, scope: impl Into<String>) -> Self {
+            self.scope = Some(scope.into());
+            self
+        }
+
+        fn is_valid(&self, token: &CachedToken) -> bool {
+            token.generation == self.generation.load(Ordering::Acquire)
+                && Instant::now() + EXPIRY_MARGIN < token.expires_at
+        }
+
+        async fn fetch_token(&self, generation: u64) -> Result<CachedToken, SparkError> {
+            let mut form = vec![("grant_type", "client_credentials")];
+
+            if let Some(scope) = &self.scope {
+                form.push(("scope", scope.as_str()));
+            }
+
+            let resp = self
+                .http
+                .post(&self.token_url)
+                .basic_auth(&self.client_id, Some(&self.client_secret))
+                .form(&form)
+                .send()
+                .await
+                .map_err(|err| SparkError::ExternalError(Box::new(err)))?;
+
+            let status = resp.status();
+
+            let body = resp
+                .text()
+                .await
+                .map_err(|err| SparkError::ExternalError(Box::new(err)))?;
+
+            if !status.is_success() {
+                return Err(SparkError::Unauthenticated(format!(
+                    "OAuth token request failed with {}: {}",
+                    status, body
+                )));
+            }
+
+            let value: serde_json::Value = serde_json::from_str(&body)?;
+
+            let token = value["access_token"]
+                .as_str()
+                .ok_or_else(|| {
+                    SparkError::Unauthenticated(
+                        "OAuth token response is missing 'access_token'".to_string(),
+                    )
+                })?
+                .to_string();
+
+            let expires_in = value["expires_in"].as_u64().unwrap_or(DEFAULT_EXPIRES_IN);
+
+            Ok(CachedToken {
+                token,
+                expires_at: Instant::now() + Duration::from_secs(expires_in),
+                generation,
+            })
+        }
+    }
+
+    impl std::fmt::Debug for OAuthClientCredentialsProvider {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("OAuthClientCredentialsProvider")
+                .field("token_url", &self.token_url)
+                .field("client_id", &self.client_id)
+                .field("scope", &self.scope)
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl CredentialProvider for OAuthClientCredentialsProvider {
+        fn token(&self) -> BoxFuture<'_, Result<String, SparkError>> {
+            async move {
+                // holding the lock while refreshing keeps concurrent requests
+                // from each fetching a new token
+                let mut cached = self.cached.lock().await;
+
+                if let Some(token) = cached.as_ref() {
+                    if self.is_valid(token) {
+                        return Ok(token.token.clone());
+                    }
+                }
+
+                let generation = self.generation.load(Ordering::Acquire);
+
+                let token = self.fetch_token(generation).await?;
+                let value = token.token.clone();
+
+                *cached = Some(token);
+
+                Ok(value)
+            }
+            .boxed()
+        }
+
+        fn invalidate(&self) {
+            self.generation.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_oauth_invalidate_during_refresh() -> Result<(), SparkError> {
+            let provider =
+                OAuthClientCredentialsProvider::new("http://127.0.0.1:1/token", "id", "secret");
+
+            // a concurrent request holds the lock while it refreshes the token
+            let mut cached = provider.cached.lock().await;
+
+            *cached = Some(CachedToken {
+                token: "rejected".to_string(),
+                expires_at: Instant::now() + Duration::from_secs(3600),
+                generation: provider.generation.load(Ordering::Acquire),
+            });
+
+            assert!(provider.is_valid(cached.as_ref().unwrap()));
+
+            provider.invalidate();
+
+            assert!(!provider.is_valid(cached.as_ref().unwrap()));
+
+            drop(cached);
+
+            // the rejected token is not reused, so a new one is requested
+            assert!(provider.token().await.is_err());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_static_token_provider() -> Result<(), SparkError> {
+        let provider = StaticTokenProvider::new("ABCDEFG");
+
+        assert_eq!("ABCDEFG", provider.token().await?);
+        assert!(!format!("{:?}", provider).contains("ABCDEFG"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_env_token_provider() {
+        let provider = EnvTokenProvider::new("SPARK_CONNECT_RS_TEST_MISSING_TOKEN");
+
+        assert!(matches!(
+            provider.token().await,
+            Err(SparkError::Unauthenticated(_))
+        ));
+    }
+}
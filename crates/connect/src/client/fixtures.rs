@@ -0,0 +1,884 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Record Spark Connect calls to a fixture file, so they can be replayed without a cluster
+
+use std::borrow::BorrowMut;
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures_util::future::BoxFuture;
+use http_body::combinators::UnsyncBoxBody;
+use http_body::{Body, Full, SizeHint};
+
+use prost::bytes::{Buf, Bytes, BytesMut};
+use prost::Message;
+
+use serde_json::{json, Value};
+
+use tonic::codegen::http::{HeaderMap, Request, Response};
+use tonic::codegen::StdError;
+
+use tower::Service;
+
+use crate::errors::SparkError;
+use crate::spark;
+
+/// A single recorded gRPC call
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Fixture {
+    /// Name of the RPC, e.g. `ExecutePlan`
+    pub(crate) method: String,
+    /// Normalized request messages
+    pub(crate) requests: Vec<Vec<u8>>,
+    /// Response messages, in the order they were received
+    pub(crate) responses: Vec<Vec<u8>>,
+    /// gRPC status code the call ended with
+    pub(crate) code: i32,
+    pub(crate) message: String,
+    /// Encoded `grpc-status-details-bin` of the status, e.g. the Spark error info
+    pub(crate) details: Vec<u8>,
+}
+
+impl Fixture {
+    /// Set the status of the call from response headers or trailers
+    fn set_status(&mut self, headers: &HeaderMap) {
+        if let Some(status) = tonic::Status::from_header_map(headers) {
+            self.code = status.code() as i32;
+            self.message = status.message().to_string();
+            self.details = status.details().to_vec();
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "method": self.method,
+            "requests": self.requests.iter().map(|msg| to_hex(msg)).collect::<Vec<_>>(),
+            "responses": self.responses.iter().map(|msg| to_hex(msg)).collect::<Vec<_>>(),
+            "code": self.code,
+            "message": self.message,
+            "details": to_hex(&self.details),
+        })
+    }
+
+    #[cfg(any(test, feature = "testing"))]
+    fn from_json(value: &Value) -> Result<Fixture, SparkError> {
+        let invalid = || SparkError::InvalidArgument(format!("Invalid fixture: {}", value));
+
+        let messages = |key: &str| -> Result<Vec<Vec<u8>>, SparkError> {
+            value[key]
+                .as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(|msg| msg.as_str().and_then(from_hex).ok_or_else(invalid))
+                .collect()
+        };
+
+        Ok(Fixture {
+            method: value["method"].as_str().ok_or_else(invalid)?.to_string(),
+            requests: messages("requests")?,
+            responses: messages("responses")?,
+            code: value["code"].as_i64().unwrap_or_default() as i32,
+            message: value["message"].as_str().unwrap_or_default().to_string(),
+            details: match value["details"].as_str() {
+                Some(details) => from_hex(details).ok_or_else(invalid)?,
+                None => vec![],
+            },
+        })
+    }
+}
+
+/// Read the fixtures recorded to a file, one call per line
+#[cfg(any(test, feature = "testing"))]
+pub(crate) fn read_fixtures(path: impl AsRef<Path>) -> Result<Vec<Fixture>, SparkError> {
+    use std::io::{BufRead, BufReader};
+
+    let file = File::open(path)?;
+
+    BufReader::new(file)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| Fixture::from_json(&serde_json::from_str(&line?)?))
+        .collect()
+}
+
+/// Appends each recorded call to the fixture file
+#[derive(Debug)]
+struct FixtureRecorder {
+    file: Mutex<File>,
+}
+
+impl FixtureRecorder {
+    fn record(&self, fixture: &Fixture) {
+        let mut line = fixture.to_json().to_string();
+        line.push('\n');
+
+        // a failed write must not fail the call being recorded
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+}
+
+/// Request message of a Spark Connect RPC
+///
+/// Requests are compared after [normalize](FixtureRequest::normalize), which strips the
+/// identifiers that change between runs, so a replayed call matches its recording.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum FixtureRequest {
+    ExecutePlan(Box<spark::ExecutePlanRequest>),
+    AnalyzePlan(Box<spark::AnalyzePlanRequest>),
+    Config(spark::ConfigRequest),
+    AddArtifacts(spark::AddArtifactsRequest),
+    ArtifactStatus(spark::ArtifactStatusesRequest),
+    Interrupt(spark::InterruptRequest),
+    ReattachExecute(spark::ReattachExecuteRequest),
+    ReleaseExecute(spark::ReleaseExecuteRequest),
+    #[cfg(feature = "spark-4")]
+    ReleaseSession(spark::ReleaseSessionRequest),
+    #[cfg(feature = "spark-4")]
+    FetchErrorDetails(spark::FetchErrorDetailsRequest),
+}
+
+macro_rules! strip_metadata {
+    ($req:expr) => {{
+        $req.session_id.clear();
+        $req.user_context = None;
+        $req.client_type = None;
+        #[cfg(feature = "spark-4")]
+        {
+            $req.client_observed_server_side_session_id = None;
+        }
+    }};
+}
+
+impl FixtureRequest {
+    pub(crate) fn decode(method: &str, buf: &[u8]) -> Result<FixtureRequest, SparkError> {
+        let decode_err = |err: prost::DecodeError| SparkError::InvalidArgument(err.to_string());
+
+        let req = match method {
+            "ExecutePlan" => {
+                FixtureRequest::ExecutePlan(Box::new(Message::decode(buf).map_err(decode_err)?))
+            }
+            "AnalyzePlan" => {
+                FixtureRequest::AnalyzePlan(Box::new(Message::decode(buf).map_err(decode_err)?))
+            }
+            "Config" => FixtureRequest::Config(Message::decode(buf).map_err(decode_err)?),
+            "AddArtifacts" => {
+                FixtureRequest::AddArtifacts(Message::decode(buf).map_err(decode_err)?)
+            }
+            "ArtifactStatus" => {
+                FixtureRequest::ArtifactStatus(Message::decode(buf).map_err(decode_err)?)
+            }
+            "Interrupt" => FixtureRequest::Interrupt(Message::decode(buf).map_err(decode_err)?),
+            "ReattachExecute" => {
+                FixtureRequest::ReattachExecute(Message::decode(buf).map_err(decode_err)?)
+            }
+            "ReleaseExecute" => {
+                FixtureRequest::ReleaseExecute(Message::decode(buf).map_err(decode_err)?)
+            }
+            #[cfg(feature = "spark-4")]
+            "ReleaseSession" => {
+                FixtureRequest::ReleaseSession(Message::decode(buf).map_err(decode_err)?)
+            }
+            #[cfg(feature = "spark-4")]
+            "FetchErrorDetails" => {
+                FixtureRequest::FetchErrorDetails(Message::decode(buf).map_err(decode_err)?)
+            }
+            _ => {
+                return Err(SparkError::NotYetImplemented(format!(
+                    "Fixtures for '{}' are not supported",
+                    method
+                )))
+            }
+        };
+
+        Ok(req)
+    }
+
+    pub(crate) fn encode_to_vec(&self) -> Vec<u8> {
+        match self {
+            FixtureRequest::ExecutePlan(req) => req.encode_to_vec(),
+            FixtureRequest::AnalyzePlan(req) => req.encode_to_vec(),
+            FixtureRequest::Config(req) => req.encode_to_vec(),
+            FixtureRequest::AddArtifacts(req) => req.encode_to_vec(),
+            FixtureRequest::ArtifactStatus(req) => req.encode_to_vec(),
+            FixtureRequest::Interrupt(req) => req.encode_to_vec(),
+            FixtureRequest::ReattachExecute(req) => req.encode_to_vec(),
+            FixtureRequest::ReleaseExecute(req) => req.encode_to_vec(),
+            #[cfg(feature = "spark-4")]
+            FixtureRequest::ReleaseSession(req) => req.encode_to_vec(),
+            #[cfg(feature = "spark-4")]
+            FixtureRequest::FetchErrorDetails(req) => req.encode_to_vec(),
+        }
+    }
+
+    /// Strip session ids, operation ids, client details and plan ids from the request
+    pub(crate) fn normalize(&mut self) {
+        match self {
+            FixtureRequest::ExecutePlan(req) => {
+                strip_metadata!(req);
+                req.operation_id = None;
+
+                if let Some(plan) = req.plan.as_mut() {
+                    normalize_plan(plan);
+                }
+            }
+            FixtureRequest::AnalyzePlan(req) => {
+                strip_metadata!(req);
+
+                if let Some(analyze) = req.analyze.as_mut() {
+                    normalize_analyze(analyze);
+                }
+            }
+            FixtureRequest::Config(req) => {
+                strip_metadata!(req);
+
+                // pairs and keys are collected from a HashMap, so their order is random
+                use spark::config_request::operation::OpType;
+
+                match req.operation.as_mut().and_then(|op| op.op_type.as_mut()) {
+                    Some(OpType::Set(set)) => set.pairs.sort_by(|a, b| a.key.cmp(&b.key)),
+                    Some(OpType::Unset(unset)) => unset.keys.sort(),
+                    _ => {}
+                }
+            }
+            FixtureRequest::AddArtifacts(req) => strip_metadata!(req),
+            FixtureRequest::ArtifactStatus(req) => strip_metadata!(req),
+            FixtureRequest::Interrupt(req) => {
+                strip_metadata!(req);
+
+                if let Some(spark::interrupt_request::Interrupt::OperationId(id)) =
+                    req.interrupt.as_mut()
+                {
+                    id.clear();
+                }
+            }
+            FixtureRequest::ReattachExecute(req) => {
+                strip_metadata!(req);
+                req.operation_id.clear();
+            }
+            FixtureRequest::ReleaseExecute(req) => {
+                strip_metadata!(req);
+                req.operation_id.clear();
+            }
+            // the only request without the server side session id observed by the client
+            #[cfg(feature = "spark-4")]
+            FixtureRequest::ReleaseSession(req) => {
+                req.session_id.clear();
+                req.user_context = None;
+                req.client_type = None;
+            }
+            #[cfg(feature = "spark-4")]
+            FixtureRequest::FetchErrorDetails(req) => strip_metadata!(req),
+        }
+    }
+}
+
+/// Normalize an encoded request, or keep it as is if the method is unknown
+fn normalize_request(method: &str, buf: &[u8]) -> Vec<u8> {
+    match FixtureRequest::decode(method, buf) {
+        Ok(mut req) => {
+            req.normalize();
+            req.encode_to_vec()
+        }
+        Err(_) => buf.to_vec(),
+    }
+}
+
+fn normalize_plan(plan: &mut spark::Plan) {
+    use spark::command::CommandType;
+
+    match plan.op_type.as_mut() {
+        Some(spark::plan::OpType::Root(relation)) => normalize_relation(relation),
+        Some(spark::plan::OpType::Command(command)) => match command.command_type.as_mut() {
+            Some(CommandType::WriteOperation(write)) => normalize_relations(&mut write.input),
+            Some(CommandType::CreateDataframeView(view)) => normalize_relations(&mut view.input),
+            Some(CommandType::WriteOperationV2(write)) => {
+                normalize_relations(&mut write.input);
+                normalize_exprs(&mut write.partitioning_columns);
+                normalize_exprs(&mut write.overwrite_condition);
+            }
+            Some(CommandType::WriteStreamOperationStart(write)) => {
+                normalize_relations(&mut write.input)
+            }
+            _ => {}
+        },
+        #[cfg(feature = "spark-4")]
+        Some(spark::plan::OpType::CompressedOperation(_)) => {}
+        None => {}
+    }
+}
+
+fn normalize_analyze(analyze: &mut spark::analyze_plan_request::Analyze) {
+    use spark::analyze_plan_request::Analyze;
+
+    let plans = match analyze {
+        Analyze::Schema(req) => vec![&mut req.plan],
+        Analyze::Explain(req) => vec![&mut req.plan],
+        Analyze::TreeString(req) => vec![&mut req.plan],
+        Analyze::IsLocal(req) => vec![&mut req.plan],
+        Analyze::IsStreaming(req) => vec![&mut req.plan],
+        Analyze::InputFiles(req) => vec![&mut req.plan],
+        Analyze::SemanticHash(req) => vec![&mut req.plan],
+        Analyze::SameSemantics(req) => vec![&mut req.target_plan, &mut req.other_plan],
+        Analyze::Persist(req) => return normalize_relations(&mut req.relation),
+        Analyze::Unpersist(req) => return normalize_relations(&mut req.relation),
+        Analyze::GetStorageLevel(req) => return normalize_relations(&mut req.relation),
+        Analyze::SparkVersion(_) | Analyze::DdlParse(_) => vec![],
+        #[cfg(feature = "spark-4")]
+        Analyze::JsonToDdl(_) => vec![],
+    };
+
+    for plan in plans.into_iter().flatten() {
+        normalize_plan(plan);
+    }
+}
+
+/// Normalize every relation of an `Option` or `Vec`, boxed or not
+fn normalize_relations<'a, R>(relations: impl IntoIterator<Item = &'a mut R>)
+where
+    R: BorrowMut<spark::Relation> + 'a,
+{
+    for relation in relations {
+        normalize_relation(relation.borrow_mut());
+    }
+}
+
+/// Normalize every expression of an `Option` or `Vec`, boxed or not
+fn normalize_exprs<'a, E>(exprs: impl IntoIterator<Item = &'a mut E>)
+where
+    E: BorrowMut<spark::Expression> + 'a,
+{
+    for expr in exprs {
+        normalize_expr(expr.borrow_mut());
+    }
+}
+
+fn normalize_relation(relation: &mut spark::Relation) {
+    use spark::relation::RelType;
+
+    if let Some(common) = relation.common.as_mut() {
+        common.plan_id = None;
+    }
+
+    let Some(rel_type) = relation.rel_type.as_mut() else {
+        return;
+    };
+
+    match rel_type {
+        RelType::Project(rel) => {
+            normalize_relations(&mut rel.input);
+            normalize_exprs(&mut rel.expressions);
+        }
+        RelType::Filter(rel) => {
+            normalize_relations(&mut rel.input);
+            normalize_exprs(&mut rel.condition);
+        }
+        RelType::Join(rel) => {
+            normalize_relations(&mut rel.left);
+            normalize_relations(&mut rel.right);
+            normalize_exprs(&mut rel.join_condition);
+        }
+        RelType::SetOp(rel) => {
+            normalize_relations(&mut rel.left_input);
+            normalize_relations(&mut rel.right_input);
+        }
+        RelType::Sort(rel) => {
+            normalize_relations(&mut rel.input);
+            rel.order.iter_mut().for_each(normalize_sort_order);
+        }
+        RelType::Aggregate(rel) => {
+            normalize_relations(&mut rel.input);
+            normalize_exprs(&mut rel.grouping_expressions);
+            normalize_exprs(&mut rel.aggregate_expressions);
+
+            if let Some(pivot) = rel.pivot.as_mut() {
+                normalize_exprs(&mut pivot.col);
+            }
+        }
+        RelType::Drop(rel) => {
+            normalize_relations(&mut rel.input);
+            normalize_exprs(&mut rel.columns);
+        }
+        RelType::WithColumns(rel) => {
+            normalize_relations(&mut rel.input);
+
+            for alias in rel.aliases.iter_mut() {
+                normalize_exprs(&mut alias.expr);
+            }
+        }
+        RelType::Hint(rel) => {
+            normalize_relations(&mut rel.input);
+            normalize_exprs(&mut rel.parameters);
+        }
+        RelType::Unpivot(rel) => {
+            normalize_relations(&mut rel.input);
+            normalize_exprs(&mut rel.ids);
+
+            if let Some(values) = rel.values.as_mut() {
+                normalize_exprs(&mut values.values);
+            }
+        }
+        RelType::RepartitionByExpression(rel) => {
+            normalize_relations(&mut rel.input);
+            normalize_exprs(&mut rel.partition_exprs);
+        }
+        RelType::CollectMetrics(rel) => {
+            normalize_relations(&mut rel.input);
+            normalize_exprs(&mut rel.metrics);
+        }
+        RelType::SampleBy(rel) => {
+            normalize_relations(&mut rel.input);
+            normalize_exprs(&mut rel.col);
+        }
+        RelType::GroupMap(rel) => {
+            normalize_relations(&mut rel.input);
+            normalize_relations(&mut rel.initial_input);
+            normalize_exprs(&mut rel.grouping_expressions);
+            normalize_exprs(&mut rel.sorting_expressions);
+            normalize_exprs(&mut rel.initial_grouping_expressions);
+        }
+        RelType::CoGroupMap(rel) => {
+            normalize_relations(&mut rel.input);
+            normalize_relations(&mut rel.other);
+            normalize_exprs(&mut rel.input_grouping_expressions);
+            normalize_exprs(&mut rel.other_grouping_expressions);
+            normalize_exprs(&mut rel.input_sorting_expressions);
+            normalize_exprs(&mut rel.other_sorting_expressions);
+        }
+        RelType::ApplyInPandasWithState(rel) => {
+            normalize_relations(&mut rel.input);
+            normalize_exprs(&mut rel.grouping_expressions);
+        }
+        RelType::Limit(rel) => normalize_relations(&mut rel.input),
+        RelType::Sample(rel) => normalize_relations(&mut rel.input),
+        RelType::Offset(rel) => normalize_relations(&mut rel.input),
+        RelType::Deduplicate(rel) => normalize_relations(&mut rel.input),
+        RelType::SubqueryAlias(rel) => normalize_relations(&mut rel.input),
+        RelType::Repartition(rel) => normalize_relations(&mut rel.input),
+        RelType::ToDf(rel) => normalize_relations(&mut rel.input),
+        RelType::WithColumnsRenamed(rel) => normalize_relations(&mut rel.input),
+        RelType::ShowString(rel) => normalize_relations(&mut rel.input),
+        RelType::HtmlString(rel) => normalize_relations(&mut rel.input),
+        RelType::Tail(rel) => normalize_relations(&mut rel.input),
+        RelType::ToSchema(rel) => normalize_relations(&mut rel.input),
+        RelType::MapPartitions(rel) => normalize_relations(&mut rel.input),
+        RelType::WithWatermark(rel) => normalize_relations(&mut rel.input),
+        RelType::Parse(rel) => normalize_relations(&mut rel.input),
+        RelType::FillNa(rel) => normalize_relations(&mut rel.input),
+        RelType::DropNa(rel) => normalize_relations(&mut rel.input),
+        RelType::Replace(rel) => normalize_relations(&mut rel.input),
+        RelType::Summary(rel) => normalize_relations(&mut rel.input),
+        RelType::Crosstab(rel) => normalize_relations(&mut rel.input),
+        RelType::Describe(rel) => normalize_relations(&mut rel.input),
+        RelType::Cov(rel) => normalize_relations(&mut rel.input),
+        RelType::Corr(rel) => normalize_relations(&mut rel.input),
+        RelType::ApproxQuantile(rel) => normalize_relations(&mut rel.input),
+        RelType::FreqItems(rel) => normalize_relations(&mut rel.input),
+        _ => {}
+    }
+}
+
+fn normalize_sort_order(order: &mut spark::expression::SortOrder) {
+    normalize_exprs(&mut order.child);
+}
+
+fn normalize_expr(expr: &mut spark::Expression) {
+    use spark::expression::ExprType;
+
+    let Some(expr_type) = expr.expr_type.as_mut() else {
+        return;
+    };
+
+    match expr_type {
+        ExprType::UnresolvedAttribute(attr) => attr.plan_id = None,
+        ExprType::UnresolvedRegex(regex) => regex.plan_id = None,
+        ExprType::UnresolvedFunction(func) => normalize_exprs(&mut func.arguments),
+        ExprType::Alias(alias) => normalize_exprs(&mut alias.expr),
+        ExprType::Cast(cast) => normalize_exprs(&mut cast.expr),
+        ExprType::SortOrder(order) => normalize_sort_order(order),
+        ExprType::LambdaFunction(lambda) => normalize_exprs(&mut lambda.function),
+        ExprType::Window(window) => {
+            normalize_exprs(&mut window.window_function);
+            normalize_exprs(&mut window.partition_spec);
+            window.order_spec.iter_mut().for_each(normalize_sort_order);
+        }
+        ExprType::UnresolvedExtractValue(extract) => {
+            normalize_exprs(&mut extract.child);
+            normalize_exprs(&mut extract.extraction);
+        }
+        ExprType::UpdateFields(update) => {
+            normalize_exprs(&mut update.struct_expression);
+            normalize_exprs(&mut update.value_expression);
+        }
+        ExprType::CommonInlineUserDefinedFunction(func) => normalize_exprs(&mut func.arguments),
+        ExprType::CallFunction(func) => normalize_exprs(&mut func.arguments),
+        _ => {}
+    }
+}
+
+/// Split complete gRPC messages off the front of the buffer
+///
+/// Each message is framed by a compression flag and its length as a big-endian u32.
+pub(crate) fn decode_frames(buf: &mut BytesMut) -> Vec<Vec<u8>> {
+    let mut messages = vec![];
+
+    while buf.len() >= 5 {
+        let len = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+
+        if buf.len() < 5 + len {
+            break;
+        }
+
+        buf.advance(5);
+        messages.push(buf.split_to(len).to_vec());
+    }
+
+    messages
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(any(test, feature = "testing"))]
+fn from_hex(value: &str) -> Option<Vec<u8>> {
+    if value.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(value.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+/// Records every request and response passing through the channel to a fixture file
+///
+/// Request plans are normalized before they are written: plan ids, session ids, operation
+/// ids and client details are stripped, so a later run with the same queries matches the
+/// recording. Calls are appended to the file, one per line, and served back by the
+/// `ReplaySparkServer` of the `testing` feature. Compression must be disabled while recording.
+#[derive(Clone, Debug, Default)]
+pub struct RecordLayer {
+    recorder: Option<Arc<FixtureRecorder>>,
+}
+
+impl RecordLayer {
+    /// Record the calls to the fixture file, which is created if it does not exist
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, SparkError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.as_ref())
+            .map_err(|err| {
+                SparkError::IoError(
+                    format!("Failed to open fixture file {}", path.as_ref().display()),
+                    err,
+                )
+            })?;
+
+        Ok(Self {
+            recorder: Some(Arc::new(FixtureRecorder {
+                file: Mutex::new(file),
+            })),
+        })
+    }
+
+    /// A layer that passes every call through without recording it
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> tower::Layer<S> for RecordLayer {
+    type Service = RecordMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RecordMiddleware {
+            inner,
+            recorder: self.recorder.clone(),
+        }
+    }
+}
+
+/// Middleware used to record gRPC calls to a fixture file
+#[derive(Clone, Debug)]
+pub struct RecordMiddleware<S> {
+    inner: S,
+    recorder: Option<Arc<FixtureRecorder>>,
+}
+
+impl<S, B> Service<Request<UnsyncBoxBody<Bytes, tonic::Status>>> for RecordMiddleware<S>
+where
+    S: Service<Request<UnsyncBoxBody<Bytes, tonic::Status>>, Response = Response<B>>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<StdError>,
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Response = Response<RecordingBody<B>>;
+    type Error = StdError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<UnsyncBoxBody<Bytes, tonic::Status>>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let recorder = self.recorder.clone();
+
+        Box::pin(async move {
+            let Some(recorder) = recorder else {
+                let response = inner.call(request).await.map_err(Into::into)?;
+                return Ok(response.map(|body| RecordingBody {
+                    inner: body,
+                    recording: None,
+                }));
+            };
+
+            let method = request
+                .uri()
+                .path()
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .to_string();
+
+            // the request body is buffered, so its messages can be recorded before it is sent
+            let (parts, mut body) = request.into_parts();
+
+            let mut buf = BytesMut::new();
+            while let Some(chunk) = body.data().await {
+                buf.extend_from_slice(&chunk?);
+            }
+
+            let data = buf.clone().freeze();
+
+            let requests = decode_frames(&mut buf)
+                .iter()
+                .map(|msg| normalize_request(&method, msg))
+                .collect();
+
+            let body = UnsyncBoxBody::new(Full::new(data).map_err(|never| match never {}));
+
+            let response = inner
+                .call(Request::from_parts(parts, body))
+                .await
+                .map_err(Into::into)?;
+
+            let mut fixture = Fixture {
+                method,
+                requests,
+                ..Default::default()
+            };
+
+            // errors without a response stream are returned in the headers
+            fixture.set_status(response.headers());
+
+            Ok(response.map(|body| RecordingBody {
+                inner: body,
+                recording: Some(Recording {
+                    recorder,
+                    fixture,
+                    buf: BytesMut::new(),
+                }),
+            }))
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Recording {
+    recorder: Arc<FixtureRecorder>,
+    fixture: Fixture,
+    buf: BytesMut,
+}
+
+impl Recording {
+    fn finish(mut self, trailers: Option<&HeaderMap>) {
+        if let Some(trailers) = trailers {
+            self.fixture.set_status(trailers);
+        }
+
+        self.recorder.record(&self.fixture);
+    }
+}
+
+/// Response body that records the messages read from it
+///
+/// The call is written to the fixture file once the trailers are read, or when the body
+/// is dropped before the stream is complete.
+#[derive(Debug)]
+pub struct RecordingBody<B> {
+    inner: B,
+    recording: Option<Recording>,
+}
+
+impl<B> Body for RecordingBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_data(cx);
+
+        if let (Poll::Ready(Some(Ok(data))), Some(recording)) = (&poll, this.recording.as_mut()) {
+            recording.buf.extend_from_slice(data);
+
+            let messages = decode_frames(&mut recording.buf);
+            recording.fixture.responses.extend(messages);
+        }
+
+        poll
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_trailers(cx);
+
+        if let Poll::Ready(Ok(trailers)) = &poll {
+            if let Some(recording) = this.recording.take() {
+                recording.finish(trailers.as_ref());
+            }
+        }
+
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for RecordingBody<B> {
+    fn drop(&mut self) {
+        if let Some(recording) = self.recording.take() {
+            recording.finish(None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::plan::LogicalPlanBuilder;
+
+    fn execute_request(plan: spark::Plan, session_id: &str) -> FixtureRequest {
+        FixtureRequest::ExecutePlan(Box::new(spark::ExecutePlanRequest {
+            session_id: session_id.to_string(),
+            operation_id: Some(uuid::Uuid::new_v4().to_string()),
+            plan: Some(plan),
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn test_normalize_plan_ids() {
+        let plan = |start| {
+            let range = LogicalPlanBuilder::from(spark::relation::RelType::Range(spark::Range {
+                start: Some(start),
+                end: 10,
+                step: 1,
+                num_partitions: None,
+            }));
+
+            range.limit(5).plan_root()
+        };
+
+        let mut left = execute_request(plan(0), "session-a");
+        let mut right = execute_request(plan(0), "session-b");
+        let mut other = execute_request(plan(1), "session-a");
+
+        assert_ne!(left, right);
+
+        left.normalize();
+        right.normalize();
+        other.normalize();
+
+        assert_eq!(left, right);
+        assert_ne!(left, other);
+    }
+
+    #[test]
+    fn test_fixture_json() -> Result<(), SparkError> {
+        let fixture = Fixture {
+            method: "Config".to_string(),
+            requests: vec![vec![0, 1, 255]],
+            responses: vec![vec![], vec![16, 32]],
+            code: 5,
+            message: "not found".to_string(),
+            details: vec![8, 5],
+        };
+
+        assert_eq!(fixture, Fixture::from_json(&fixture.to_json())?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_frames() {
+        let mut buf = BytesMut::from(&[0, 0, 0, 0, 2, 7, 8, 0, 0, 0, 0, 3, 9][..]);
+
+        assert_eq!(vec![vec![7, 8]], decode_frames(&mut buf));
+        assert_eq!(6, buf.len());
+    }
+
+    #[test]
+    fn test_fixture_status() -> Result<(), Box<dyn std::error::Error>> {
+        let status = tonic::Status::with_details(
+            tonic::Code::Internal,
+            "a b%",
+            Bytes::from_static(&[8, 13, 18, 1]),
+        );
+
+        let mut headers = HeaderMap::new();
+        status.add_header(&mut headers)?;
+
+        let mut fixture = Fixture::default();
+        fixture.set_status(&headers);
+
+        assert_eq!(13, fixture.code);
+        assert_eq!("a b%", fixture.message);
+        assert_eq!(vec![8, 13, 18, 1], fixture.details);
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Cancel server-side operations that are abandoned by the caller
+
+use futures_util::future::BoxFuture;
+
+use tokio::runtime::Handle;
+
+/// Guard that cancels a server-side operation when it is dropped before being disarmed.
+///
+/// The guard is created when an operation is submitted and disarmed once the operation
+/// completes. If the future driving the operation is dropped first, e.g. by
+/// `tokio::time::timeout` or `select!`, the cancellation is spawned onto the current runtime.
+pub(crate) struct OperationGuard {
+    cancel: Option<BoxFuture<'static, ()>>,
+}
+
+impl OperationGuard {
+    pub(crate) fn new(cancel: BoxFuture<'static, ()>) -> Self {
+        OperationGuard {
+            cancel: Some(cancel),
+        }
+    }
+
+    /// The operation completed and no longer needs to be cancelled
+    pub(crate) fn disarm(mut self) {
+        self.cancel = None;
+    }
+}
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        if let Some(cancel) = self.cancel.take() {
+            // without a runtime the operation can not be cancelled, the server
+            // will clean it up once it is abandoned
+            if let Ok(handle) = Handle::try_current() {
+                handle.spawn(cancel);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for OperationGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OperationGuard")
+            .field("armed", &self.cancel.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use futures_util::FutureExt;
+
+    fn guard(cancelled: &Arc<AtomicBool>) -> OperationGuard {
+        let cancelled = cancelled.clone();
+
+        OperationGuard::new(async move { cancelled.store(true, Ordering::SeqCst) }.boxed())
+    }
+
+    #[tokio::test]
+    async fn test_dropped_guard_cancels() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        drop(guard(&cancelled));
+        tokio::task::yield_now().await;
+
+        assert!(cancelled.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_disarmed_guard_does_not_cancel() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        guard(&cancelled).disarm();
+        tokio::task::yield_now().await;
+
+        assert!(!cancelled.load(Ordering::SeqCst));
+    }
+}
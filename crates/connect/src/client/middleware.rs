@@ -20,6 +20,7 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use futures_util::future::BoxFuture;
@@ -27,18 +28,31 @@ use http_body::combinators::UnsyncBoxBody;
 
 use tonic::codegen::http::Request;
 use tonic::codegen::http::{HeaderName, HeaderValue};
+use tonic::codegen::StdError;
 
 use tower::Service;
 
+use crate::client::{trace, CredentialProvider};
+
 /// Headers to apply a gRPC request
 #[derive(Debug, Clone)]
 pub struct HeadersLayer {
     headers: HashMap<String, String>,
+    credentials: Option<Arc<dyn CredentialProvider>>,
 }
 
 impl HeadersLayer {
     pub fn new(headers: HashMap<String, String>) -> Self {
-        Self { headers }
+        Self {
+            headers,
+            credentials: None,
+        }
+    }
+
+    /// Set the [CredentialProvider] queried for the `authorization` header of each request
+    pub fn credentials(mut self, credentials: Option<Arc<dyn CredentialProvider>>) -> Self {
+        self.credentials = credentials;
+        self
     }
 }
 
@@ -46,7 +60,7 @@ impl<S> tower::Layer<S> for HeadersLayer {
     type Service = HeadersMiddleware<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        HeadersMiddleware::new(inner, self.headers.clone())
+        HeadersMiddleware::new(inner, self.headers.clone()).credentials(self.credentials.clone())
     }
 }
 
@@ -55,12 +69,23 @@ impl<S> tower::Layer<S> for HeadersLayer {
 pub struct HeadersMiddleware<S> {
     inner: S,
     headers: HashMap<String, String>,
+    credentials: Option<Arc<dyn CredentialProvider>>,
 }
 
 #[allow(dead_code)]
 impl<S> HeadersMiddleware<S> {
     pub fn new(inner: S, headers: HashMap<String, String>) -> Self {
-        Self { inner, headers }
+        Self {
+            inner,
+            headers,
+            credentials: None,
+        }
+    }
+
+    /// Set the [CredentialProvider] queried for the `authorization` header of each request
+    pub fn credentials(mut self, credentials: Option<Arc<dyn CredentialProvider>>) -> Self {
+        self.credentials = credentials;
+        self
     }
 }
 
@@ -75,10 +100,10 @@ where
         + 'static,
     S::Future: Send + 'static,
     S::Response: Send + Debug + 'static,
-    S::Error: Debug,
+    S::Error: Into<StdError>,
 {
     type Response = S::Response;
-    type Error = S::Error;
+    type Error = StdError;
     type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
@@ -93,16 +118,136 @@ where
         let mut inner = std::mem::replace(&mut self.inner, clone);
 
         let headers = self.headers.clone();
+        let credentials = self.credentials.clone();
 
         Box::pin(async move {
             for (key, value) in &headers {
-                let meta_key = HeaderName::from_str(key.as_str()).unwrap();
-                let meta_val = HeaderValue::from_str(value.as_str()).unwrap();
+                let meta_key = HeaderName::from_str(key.as_str()).map_err(|_| {
+                    tonic::Status::invalid_argument(format!("Invalid header name '{}'", key))
+                })?;
+                let meta_val = HeaderValue::from_str(value.as_str()).map_err(|_| {
+                    tonic::Status::invalid_argument(format!("Invalid value for header '{}'", key))
+                })?;
 
                 request.headers_mut().insert(meta_key, meta_val);
             }
 
-            inner.call(request).await
+            if let Some(credentials) = credentials {
+                // surfaced as an Unauthenticated status, so the client refreshes and retries
+                let token = credentials.token().await.map_err(|err| {
+                    tonic::Status::unauthenticated(format!("Failed to get credentials: {}", err))
+                })?;
+
+                let meta_val = HeaderValue::from_str(&format!("Bearer {}", token))
+                    .map_err(|_| tonic::Status::unauthenticated("Invalid token characters"))?;
+
+                request.headers_mut().insert("authorization", meta_val);
+            }
+
+            inner.call(request).await.map_err(Into::into)
         })
     }
 }
+
+/// Propagates the OpenTelemetry context of the current `tracing` span to the server
+///
+/// The headers of the global text map propagator, e.g. the W3C `traceparent` and
+/// `tracestate`, are injected into each gRPC request, so the client traces link to the
+/// server-side request logs. Headers are only injected with `feature = 'otel'`, and only
+/// when the request is made within a span with a valid OpenTelemetry context.
+#[derive(Clone, Debug, Default)]
+pub struct TraceContextLayer {
+    enabled: bool,
+}
+
+impl TraceContextLayer {
+    pub fn new() -> Self {
+        Self { enabled: true }
+    }
+
+    /// A layer that passes every request through without trace context headers
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> tower::Layer<S> for TraceContextLayer {
+    type Service = TraceContextMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TraceContextMiddleware {
+            inner,
+            enabled: self.enabled,
+        }
+    }
+}
+
+/// Middleware used to inject the W3C trace context headers onto a gRPC request
+#[derive(Clone, Debug)]
+pub struct TraceContextMiddleware<S> {
+    inner: S,
+    enabled: bool,
+}
+
+impl<S, B> Service<Request<B>> for TraceContextMiddleware<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        // the context is read when the call is made, within the task of the request
+        if self.enabled {
+            trace::inject_trace_context(request.headers_mut());
+        }
+
+        self.inner.call(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accepts every request
+    #[derive(Clone, Debug)]
+    struct Accept;
+
+    impl<R> Service<R> for Accept {
+        type Response = ();
+        type Error = tonic::Status;
+        type Future = futures_util::future::Ready<Result<(), tonic::Status>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: R) -> Self::Future {
+            futures_util::future::ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_headers_middleware_invalid_value() {
+        let inner = Accept;
+
+        let headers = HashMap::from([("x-custom".to_string(), "bad\nvalue".to_string())]);
+
+        let mut middleware = HeadersMiddleware::new(inner, headers);
+
+        let err = middleware
+            .call(Request::new(UnsyncBoxBody::default()))
+            .await
+            .unwrap_err();
+
+        let status = err.downcast_ref::<tonic::Status>().unwrap();
+
+        assert_eq!(tonic::Code::InvalidArgument, status.code());
+    }
+}
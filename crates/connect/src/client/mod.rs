@@ -17,19 +17,29 @@
 
 //! Implementation of the SparkConnectServiceClient
 
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::future::{BoxFuture, FutureExt};
 
 use tokio::sync::RwLock;
 
 use tonic::codec::Streaming;
 use tonic::codegen::{Body, Bytes, StdError};
 use tonic::transport::Channel;
+use tonic::{Code, Status};
+
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
 
 use crate::spark;
 use spark::execute_plan_response::ResponseType;
 use spark::spark_connect_service_client::SparkConnectServiceClient;
 
 use arrow::compute::concat_batches;
+use arrow::datatypes::Schema;
 use arrow::error::ArrowError;
 use arrow::record_batch::RecordBatch;
 use arrow_ipc::reader::StreamReader;
@@ -37,36 +47,76 @@ use arrow_ipc::reader::StreamReader;
 use uuid::Uuid;
 
 use crate::errors::SparkError;
+use crate::extension::{ExtensionRegistry, ExtensionResponse};
+#[cfg(feature = "spark-4")]
+use crate::metrics::{ExecutionProgress, ProgressHandlers};
+use crate::metrics::{ObservedMetrics, QueryMetrics};
 
 mod builder;
 mod config;
+mod credentials;
+#[cfg(any(test, feature = "testing"))]
+pub(crate) mod fixtures;
+mod guard;
 mod middleware;
+mod retry;
+mod stream;
+mod trace;
 
-pub use builder::ChannelBuilder;
+pub use builder::{ChannelBuilder, Compression};
 pub use config::Config;
-pub use middleware::{HeadersLayer, HeadersMiddleware};
-
-pub type SparkClient = SparkConnectClient<HeadersMiddleware<Channel>>;
-
+#[cfg(feature = "oauth")]
+pub use credentials::OAuthClientCredentialsProvider;
+pub use credentials::{CredentialProvider, EnvTokenProvider, StaticTokenProvider};
+#[cfg(any(test, feature = "testing"))]
+pub use fixtures::{RecordLayer, RecordMiddleware, RecordingBody};
+use guard::OperationGuard;
+pub use middleware::{HeadersLayer, HeadersMiddleware, TraceContextLayer, TraceContextMiddleware};
+pub use retry::RetryPolicy;
+pub use stream::{RecordBatchStream, RecordBatchStreamReader};
+
+#[cfg(any(test, feature = "testing"))]
+pub type SparkClient =
+    SparkConnectClient<TraceContextMiddleware<HeadersMiddleware<RecordMiddleware<Channel>>>>;
+
+#[cfg(not(any(test, feature = "testing")))]
+pub type SparkClient = SparkConnectClient<TraceContextMiddleware<HeadersMiddleware<Channel>>>;
+
+/// Response state of a single ExecutePlan operation
 #[allow(dead_code)]
 #[derive(Default, Debug, Clone)]
 pub(crate) struct ResponseHandler {
-    metrics: Option<spark::execute_plan_response::Metrics>,
-    observed_metrics: Option<spark::execute_plan_response::ObservedMetrics>,
+    operation_id: Option<String>,
+    response_id: Option<String>,
+    pub(crate) metrics: Option<spark::execute_plan_response::Metrics>,
+    pub(crate) observed_metrics: Vec<spark::execute_plan_response::ObservedMetrics>,
     pub(crate) schema: Option<spark::DataType>,
-    batches: Vec<RecordBatch>,
+    pub(crate) batches: Vec<RecordBatch>,
     pub(crate) sql_command_result: Option<spark::execute_plan_response::SqlCommandResult>,
     pub(crate) write_stream_operation_start_result: Option<spark::WriteStreamOperationStartResult>,
     pub(crate) streaming_query_command_result: Option<spark::StreamingQueryCommandResult>,
     pub(crate) get_resources_command_result: Option<spark::GetResourcesCommandResult>,
     pub(crate) streaming_query_manager_command_result:
         Option<spark::StreamingQueryManagerCommandResult>,
+    pub(crate) extensions: Vec<ExtensionResponse>,
     pub(crate) result_complete: bool,
     total_count: isize,
+    response_count: usize,
+    arrow_bytes: usize,
 }
 
+impl ResponseHandler {
+    /// Record the response, Arrow byte and row totals of the operation on its span
+    pub(crate) fn record(&self, span: &Span) {
+        span.record("responses", self.response_count);
+        span.record("arrow_bytes", self.arrow_bytes);
+        span.record("rows", self.total_count);
+    }
+}
+
+/// Result of a single AnalyzePlan request
 #[derive(Default, Debug, Clone)]
-pub(crate) struct AnalyzeHandler {
+pub struct AnalyzeHandler {
     pub(crate) schema: Option<spark::DataType>,
     pub(crate) explain: Option<String>,
     pub(crate) tree_string: Option<String>,
@@ -86,19 +136,25 @@ pub struct SparkConnectClient<T> {
     stub: Arc<RwLock<SparkConnectServiceClient<T>>>,
     builder: ChannelBuilder,
     session_id: String,
-    operation_id: Option<String>,
-    response_id: Option<String>,
-    pub(crate) handler: ResponseHandler,
-    pub(crate) analyzer: AnalyzeHandler,
     pub(crate) user_context: Option<spark::UserContext>,
     pub(crate) tags: Vec<String>,
     pub(crate) use_reattachable_execute: bool,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) extensions: Arc<ExtensionRegistry>,
+    pub(crate) trace_context: bool,
+    pub(crate) observed_metrics: Arc<Mutex<HashMap<String, ObservedMetrics>>>,
+    #[cfg(feature = "spark-4")]
+    server_side_session_id: Arc<Mutex<Option<String>>>,
+    #[cfg(feature = "spark-4")]
+    pub(crate) progress_handlers: ProgressHandlers,
 }
 
 impl<T> SparkConnectClient<T>
 where
-    T: tonic::client::GrpcService<tonic::body::BoxBody>,
+    T: tonic::client::GrpcService<tonic::body::BoxBody> + Clone + Send + Sync + 'static,
     T::Error: Into<StdError>,
+    T::Future: Send,
     T::ResponseBody: Body<Data = Bytes> + Send + 'static,
     <T::ResponseBody as Body>::Error: Into<StdError> + Send,
 {
@@ -110,10 +166,6 @@ where
             stub,
             builder,
             session_id,
-            operation_id: None,
-            response_id: None,
-            handler: ResponseHandler::default(),
-            analyzer: AnalyzeHandler::default(),
             user_context: Some(spark::UserContext {
                 user_id: user_ref.clone(),
                 user_name: user_ref,
@@ -121,6 +173,15 @@ where
             }),
             tags: vec![],
             use_reattachable_execute: true,
+            retry_policy: RetryPolicy::default(),
+            timeout: None,
+            extensions: Arc::new(ExtensionRegistry::default()),
+            trace_context: false,
+            observed_metrics: Arc::new(Mutex::new(HashMap::new())),
+            #[cfg(feature = "spark-4")]
+            server_side_session_id: Arc::new(Mutex::new(None)),
+            #[cfg(feature = "spark-4")]
+            progress_handlers: ProgressHandlers::default(),
         }
     }
 
@@ -135,6 +196,89 @@ where
         Ok(())
     }
 
+    /// Change the [RetryPolicy] used for transient gRPC failures
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// Set the [ExtensionRegistry] used to decode extension responses
+    pub fn set_extensions(&mut self, extensions: ExtensionRegistry) {
+        self.extensions = Arc::new(extensions);
+    }
+
+    /// Tag each operation with the trace id of the current OpenTelemetry span
+    pub fn set_trace_context(&mut self, enabled: bool) {
+        self.trace_context = enabled;
+    }
+
+    /// Set the deadline for each execute and analyze call.
+    ///
+    /// Operations that exceed the deadline are interrupted on the server and
+    /// return [SparkError::DeadlineExceeded].
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Send a request with the [RetryPolicy], and retry it once with fresh credentials
+    /// if the server rejects the current ones
+    async fn send<F, Fut, R>(&self, mut request: F) -> Result<R, Status>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<R, Status>>,
+    {
+        match self.retry_policy.retry(&mut request).await {
+            Err(status) if status.code() == Code::Unauthenticated => {
+                match &self.builder.credentials {
+                    Some(credentials) => {
+                        credentials.invalidate();
+                        self.retry_policy.retry(request).await
+                    }
+                    None => Err(status),
+                }
+            }
+            result => result,
+        }
+    }
+
+    /// Await the future within the configured deadline
+    async fn with_deadline<F, R>(&self, fut: F) -> Result<R, SparkError>
+    where
+        F: Future<Output = Result<R, SparkError>>,
+    {
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, fut).await.map_err(|_| {
+                SparkError::DeadlineExceeded(format!(
+                    "operation did not complete within {:?}",
+                    timeout
+                ))
+            })?,
+            None => fut.await,
+        }
+    }
+
+    /// Interrupt the operation on the server and release its responses.
+    ///
+    /// Used by an [OperationGuard] when the caller abandons the operation
+    fn cancel_operation(&self, operation_id: String) -> BoxFuture<'static, ()> {
+        let client = self.clone();
+
+        async move {
+            // the operation may have already completed or never been registered,
+            // so failures are ignored
+            let _ = client
+                .interrupt_request(
+                    spark::interrupt_request::InterruptType::OperationId,
+                    Some(operation_id.clone()),
+                )
+                .await;
+
+            if client.use_reattachable_execute {
+                let _ = client.release_operation(&operation_id).await;
+            }
+        }
+        .boxed()
+    }
+
     fn request_options(&self) -> Vec<spark::execute_plan_request::RequestOption> {
         if self.use_reattachable_execute {
             let reattach_opt = spark::ReattachOptions { reattachable: true };
@@ -152,10 +296,13 @@ where
         vec![]
     }
 
-    pub fn execute_plan_request_with_metadata(&mut self) -> spark::ExecutePlanRequest {
-        let operation_id = Uuid::new_v4().to_string();
+    /// Clone of the gRPC stub, so that requests on the same session run concurrently
+    async fn stub(&self) -> SparkConnectServiceClient<T> {
+        self.stub.read().await.clone()
+    }
 
-        self.operation_id = Some(operation_id.clone());
+    pub fn execute_plan_request_with_metadata(&self) -> spark::ExecutePlanRequest {
+        let operation_id = Uuid::new_v4().to_string();
 
         spark::ExecutePlanRequest {
             session_id: self.session_id(),
@@ -163,8 +310,10 @@ where
             operation_id: Some(operation_id),
             plan: None,
             client_type: self.builder.user_agent.clone(),
+            #[cfg(feature = "spark-4")]
+            client_observed_server_side_session_id: self.server_side_session_id(),
             request_options: self.request_options(),
-            tags: self.tags.clone(),
+            tags: self.operation_tags(),
         }
     }
 
@@ -173,135 +322,354 @@ where
             session_id: self.session_id(),
             user_context: self.user_context.clone(),
             client_type: self.builder.user_agent.clone(),
+            #[cfg(feature = "spark-4")]
+            client_observed_server_side_session_id: self.server_side_session_id(),
             analyze: None,
         }
     }
 
-    pub async fn execute_and_fetch(
-        &mut self,
+    pub(crate) async fn execute_and_fetch(
+        &self,
         req: spark::ExecutePlanRequest,
-    ) -> Result<(), SparkError> {
-        let mut client = self.stub.write().await;
+    ) -> Result<ResponseHandler, SparkError> {
+        let mut handler = ResponseHandler {
+            operation_id: req.operation_id.clone(),
+            ..Default::default()
+        };
 
-        let mut stream = client.execute_plan(req).await?.into_inner();
-        drop(client);
+        // interrupts the operation if this future is dropped or the deadline is exceeded
+        let guard = OperationGuard::new(self.cancel_operation(req.operation_id.clone().unwrap()));
+
+        let span = tracing::info_span!(
+            "execute",
+            session_id = %self.session_id,
+            operation_id = req.operation_id.as_deref(),
+            tags = ?req.tags,
+            plan = trace::plan_kind(req.plan.as_ref()),
+            responses = Empty,
+            arrow_bytes = Empty,
+            rows = Empty,
+            elapsed_ms = Empty,
+        );
+
+        // boxed, as the request and the response stream make the future too large to
+        // nest in the futures of every action on the stack
+        let result = Box::pin(trace::traced(
+            span.clone(),
+            self.with_deadline(async {
+                let stream = self.execute_stream(req).await?;
+
+                self.fetch(&mut handler, stream).await
+            }),
+        ))
+        .await;
 
-        // clear out any prior responses
-        self.handler = ResponseHandler::default();
+        let result = self.with_error_details(result).await;
 
-        self.process_stream(&mut stream).await?;
+        handler.record(&span);
 
-        if self.use_reattachable_execute && self.handler.result_complete {
-            self.release_all().await?
+        match result {
+            Err(err @ SparkError::DeadlineExceeded(_)) => Err(err),
+            result => {
+                guard.disarm();
+                result.map(|_| handler)
+            }
+        }
+    }
+
+    /// Consume the response stream of an operation and release it once it is complete
+    async fn fetch(
+        &self,
+        handler: &mut ResponseHandler,
+        stream: Streaming<spark::ExecutePlanResponse>,
+    ) -> Result<(), SparkError> {
+        self.process_stream(handler, stream).await?;
+
+        if self.use_reattachable_execute && handler.result_complete {
+            self.release_all(handler).await?
         }
 
         Ok(())
     }
 
-    async fn reattach_execute(&mut self) -> Result<(), SparkError> {
-        let mut client = self.stub.write().await;
+    /// Submit a plan and return its operation id without waiting for the results.
+    ///
+    /// The response stream is dropped once the server accepts the request, while the
+    /// operation keeps running on the server and can be resumed with [Self::reattach_and_fetch]
+    /// as long as reattachable execution is enabled.
+    pub(crate) async fn submit(&self, plan: spark::Plan) -> Result<String, SparkError> {
+        let mut req = self.execute_plan_request_with_metadata();
+
+        req.plan = Some(plan);
+
+        let operation_id = req.operation_id.clone().unwrap();
+
+        let span = tracing::info_span!(
+            "submit",
+            session_id = %self.session_id,
+            operation_id = %operation_id,
+            tags = ?req.tags,
+            plan = trace::plan_kind(req.plan.as_ref()),
+            elapsed_ms = Empty,
+        );
+
+        trace::traced(span, self.with_deadline(self.execute_stream(req))).await?;
+
+        Ok(operation_id)
+    }
+
+    /// Reattach to an existing operation and fetch all of its remaining responses
+    pub(crate) async fn reattach_and_fetch(
+        &self,
+        operation_id: &str,
+    ) -> Result<ResponseHandler, SparkError> {
+        let mut handler = ResponseHandler {
+            operation_id: Some(operation_id.to_string()),
+            ..Default::default()
+        };
+
+        let span = tracing::info_span!(
+            "reattach",
+            session_id = %self.session_id,
+            operation_id,
+            responses = Empty,
+            arrow_bytes = Empty,
+            rows = Empty,
+            elapsed_ms = Empty,
+        );
+
+        let result = trace::traced(
+            span.clone(),
+            self.with_deadline(async {
+                let stream = self.reattach_stream(&handler).await?;
+
+                self.fetch(&mut handler, stream).await
+            }),
+        )
+        .await;
+
+        let result = self.with_error_details(result).await;
+
+        handler.record(&span);
+
+        result.map(|_| handler)
+    }
+
+    /// Submit the ExecutePlan request and open the response stream.
+    ///
+    /// Only opening the stream is retried here. Once the server has registered the
+    /// operation, sending ExecutePlan again with the same operation id fails with
+    /// `INVALID_HANDLE.OPERATION_ALREADY_EXISTS`, so a stream that fails after its first
+    /// response is resumed with ReattachExecute by `process_stream` instead.
+    async fn execute_stream(
+        &self,
+        req: spark::ExecutePlanRequest,
+    ) -> Result<Streaming<spark::ExecutePlanResponse>, SparkError> {
+        let span = tracing::debug_span!(
+            "execute_plan",
+            session_id = %self.session_id,
+            operation_id = req.operation_id.as_deref(),
+            elapsed_ms = Empty,
+        );
+
+        let resp = trace::traced(span, async {
+            Ok(self
+                .send(|| async { self.stub().await.execute_plan(req.clone()).await })
+                .await?)
+        })
+        .await?;
 
+        Ok(resp.into_inner())
+    }
+
+    async fn reattach_stream(
+        &self,
+        handler: &ResponseHandler,
+    ) -> Result<Streaming<spark::ExecutePlanResponse>, SparkError> {
         let req = spark::ReattachExecuteRequest {
             session_id: self.session_id(),
             user_context: self.user_context.clone(),
-            operation_id: self.operation_id.clone().unwrap(),
+            operation_id: handler.operation_id.clone().unwrap(),
             client_type: self.builder.user_agent.clone(),
-            last_response_id: self.response_id.clone(),
+            #[cfg(feature = "spark-4")]
+            client_observed_server_side_session_id: self.server_side_session_id(),
+            last_response_id: handler.response_id.clone(),
         };
 
-        let mut stream = client.reattach_execute(req).await?.into_inner();
-        drop(client);
+        let span = tracing::debug_span!(
+            "reattach_execute",
+            session_id = %self.session_id,
+            operation_id = %req.operation_id,
+            last_response_id = req.last_response_id.as_deref(),
+            elapsed_ms = Empty,
+        );
+
+        let resp = trace::traced(span, async {
+            Ok(self
+                .send(|| async { self.stub().await.reattach_execute(req.clone()).await })
+                .await?)
+        })
+        .await?;
 
-        self.process_stream(&mut stream).await?;
+        Ok(resp.into_inner())
+    }
 
-        if self.use_reattachable_execute && self.handler.result_complete {
-            self.release_all().await?
+    /// Resume a response stream that failed with a retryable error by reattaching to the
+    /// operation after the last received response, instead of executing the plan again.
+    ///
+    /// Returns `None` when the stream can not be resumed.
+    async fn resume_stream(
+        &self,
+        handler: &ResponseHandler,
+        status: &Status,
+        attempt: usize,
+    ) -> Result<Option<Streaming<spark::ExecutePlanResponse>>, SparkError> {
+        if !self.use_reattachable_execute {
+            return Ok(None);
         }
 
-        Ok(())
+        match self.retry_policy.next_delay(attempt, status) {
+            Some(delay) => {
+                tracing::debug!(attempt, code = ?status.code(), ?delay, "resuming response stream");
+                tokio::time::sleep(delay).await;
+                Ok(Some(self.reattach_stream(handler).await?))
+            }
+            None => Ok(None),
+        }
     }
 
     async fn process_stream(
-        &mut self,
-        stream: &mut Streaming<spark::ExecutePlanResponse>,
+        &self,
+        handler: &mut ResponseHandler,
+        mut stream: Streaming<spark::ExecutePlanResponse>,
     ) -> Result<(), SparkError> {
-        while let Some(_resp) = match stream.message().await {
-            Ok(Some(msg)) => {
-                self.handle_response(msg.clone())?;
-                Some(msg)
-            }
-            Ok(None) => {
-                if self.use_reattachable_execute && !self.handler.result_complete {
-                    Box::pin(self.reattach_execute()).await?;
+        let mut attempt = 0;
+
+        loop {
+            match stream.message().await {
+                Ok(Some(msg)) => {
+                    attempt = 0;
+                    let batches = self.handle_response(handler, msg)?;
+                    handler.batches.extend(batches);
                 }
-                None
-            }
-            Err(err) => {
-                if self.use_reattachable_execute && self.response_id.is_some() {
-                    self.release_until().await?;
+                Ok(None) => {
+                    if self.use_reattachable_execute && !handler.result_complete {
+                        stream = self.reattach_stream(handler).await?;
+                    } else {
+                        return Ok(());
+                    }
+                }
+                Err(status) => {
+                    if let Some(resumed) = self.resume_stream(handler, &status, attempt).await? {
+                        attempt += 1;
+                        stream = resumed;
+                        continue;
+                    }
+
+                    if self.use_reattachable_execute && handler.response_id.is_some() {
+                        self.release_until(handler).await?;
+                    }
+                    return Err(status.into());
                 }
-                return Err(err.into());
             }
-        } {}
-
-        Ok(())
+        }
     }
 
-    async fn release_until(&mut self) -> Result<(), SparkError> {
+    async fn release_until(&self, handler: &ResponseHandler) -> Result<(), SparkError> {
         let release_until = spark::release_execute_request::ReleaseUntil {
-            response_id: self.response_id.clone().unwrap(),
+            response_id: handler.response_id.clone().unwrap(),
         };
 
-        self.release_execute(Some(spark::release_execute_request::Release::ReleaseUntil(
-            release_until,
-        )))
+        self.release_execute(
+            handler.operation_id.as_deref().unwrap(),
+            Some(spark::release_execute_request::Release::ReleaseUntil(
+                release_until,
+            )),
+        )
         .await
     }
 
-    async fn release_all(&mut self) -> Result<(), SparkError> {
+    async fn release_all(&self, handler: &ResponseHandler) -> Result<(), SparkError> {
+        self.release_operation(handler.operation_id.as_deref().unwrap())
+            .await
+    }
+
+    /// Release all of the responses of an operation held by the server
+    pub(crate) async fn release_operation(&self, operation_id: &str) -> Result<(), SparkError> {
         let release_all = spark::release_execute_request::ReleaseAll {};
 
-        self.release_execute(Some(spark::release_execute_request::Release::ReleaseAll(
-            release_all,
-        )))
+        self.release_execute(
+            operation_id,
+            Some(spark::release_execute_request::Release::ReleaseAll(
+                release_all,
+            )),
+        )
         .await
     }
 
     async fn release_execute(
-        &mut self,
+        &self,
+        operation_id: &str,
         release: Option<spark::release_execute_request::Release>,
     ) -> Result<(), SparkError> {
-        let mut client = self.stub.write().await;
-
         let req = spark::ReleaseExecuteRequest {
             session_id: self.session_id(),
             user_context: self.user_context.clone(),
-            operation_id: self.operation_id.clone().unwrap(),
+            operation_id: operation_id.to_string(),
             client_type: self.builder.user_agent.clone(),
+            #[cfg(feature = "spark-4")]
+            client_observed_server_side_session_id: self.server_side_session_id(),
             release,
         };
 
-        let _resp = client.release_execute(req).await?.into_inner();
-
-        Ok(())
+        let span = tracing::debug_span!(
+            "release_execute",
+            session_id = %self.session_id,
+            operation_id,
+            release = ?req.release,
+            elapsed_ms = Empty,
+        );
+
+        trace::traced(span, async {
+            self.send(|| async { self.stub().await.release_execute(req.clone()).await })
+                .await?;
+            Ok(())
+        })
+        .await
     }
 
     pub async fn analyze(
-        &mut self,
+        &self,
         analyze: spark::analyze_plan_request::Analyze,
-    ) -> Result<&mut Self, SparkError> {
+    ) -> Result<AnalyzeHandler, SparkError> {
         let mut req = self.analyze_plan_request_with_metadata();
 
+        let span = tracing::info_span!(
+            "analyze",
+            session_id = %self.session_id,
+            analyze = trace::analyze_kind(&analyze),
+            elapsed_ms = Empty,
+        );
+
         req.analyze = Some(analyze);
 
-        // clear out any prior responses
-        self.analyzer = AnalyzeHandler::default();
+        // boxed for the same reason as in `execute_and_fetch`
+        let result = Box::pin(trace::traced(span, async {
+            let resp = self
+                .with_deadline(async {
+                    let resp = self
+                        .send(|| async { self.stub().await.analyze_plan(req.clone()).await })
+                        .await?;
 
-        let mut client = self.stub.write().await;
-        let resp = client.analyze_plan(req).await?.into_inner();
-        drop(client);
+                    Ok(resp.into_inner())
+                })
+                .await?;
 
-        self.handle_analyze(resp)
+            self.handle_analyze(resp)
+        }))
+        .await;
+
+        self.with_error_details(result).await
     }
 
     fn validate_tag(&self, tag: &str) -> Result<(), SparkError> {
@@ -332,6 +700,24 @@ where
         Ok(())
     }
 
+    /// Tags assigned to a new operation, including the trace id tag of the current
+    /// span when trace context propagation is enabled. The trace id tag is not kept
+    /// with the session tags, so it does not leak into later operations.
+    fn operation_tags(&self) -> Vec<String> {
+        let mut tags = self.tags.clone();
+
+        if let Some(tag) = self.trace_context.then(trace::trace_id_tag).flatten() {
+            tags.push(tag);
+        }
+
+        tags
+    }
+
+    /// Latest metrics observed under each name by the operations of the session
+    pub fn observed_metrics(&self) -> HashMap<String, ObservedMetrics> {
+        self.observed_metrics.lock().unwrap().clone()
+    }
+
     pub fn get_tags(&self) -> &Vec<String> {
         &self.tags
     }
@@ -344,18 +730,100 @@ where
         &self,
         operation: spark::config_request::Operation,
     ) -> Result<spark::ConfigResponse, SparkError> {
+        let span = tracing::debug_span!(
+            "config",
+            session_id = %self.session_id,
+            operation = trace::config_kind(&operation),
+            elapsed_ms = Empty,
+        );
+
         let operation = spark::ConfigRequest {
             session_id: self.session_id(),
             user_context: self.user_context.clone(),
             client_type: self.builder.user_agent.clone(),
+            #[cfg(feature = "spark-4")]
+            client_observed_server_side_session_id: self.server_side_session_id(),
             operation: Some(operation),
         };
 
-        let mut client = self.stub.write().await;
+        trace::traced(span, async {
+            let resp = self
+                .send(|| async { self.stub().await.config(operation.clone()).await })
+                .await?
+                .into_inner();
 
-        let resp = client.config(operation).await?.into_inner();
+            #[cfg(feature = "spark-4")]
+            self.validate_server_side_session(&resp.server_side_session_id)?;
 
-        Ok(resp)
+            Ok(resp)
+        })
+        .await
+    }
+
+    pub async fn add_artifacts_request(
+        &self,
+        payloads: Vec<spark::add_artifacts_request::Payload>,
+    ) -> Result<spark::AddArtifactsResponse, SparkError> {
+        let requests: Vec<spark::AddArtifactsRequest> = payloads
+            .into_iter()
+            .map(|payload| spark::AddArtifactsRequest {
+                session_id: self.session_id(),
+                user_context: self.user_context.clone(),
+                client_type: self.builder.user_agent.clone(),
+                #[cfg(feature = "spark-4")]
+                client_observed_server_side_session_id: self.server_side_session_id(),
+                payload: Some(payload),
+            })
+            .collect();
+
+        let span = tracing::debug_span!(
+            "add_artifacts",
+            session_id = %self.session_id,
+            payloads = requests.len(),
+            elapsed_ms = Empty,
+        );
+
+        trace::traced(span, async {
+            let resp = self
+                .send(|| async {
+                    let requests = futures_util::stream::iter(requests.clone());
+                    self.stub().await.add_artifacts(requests).await
+                })
+                .await?;
+
+            Ok(resp.into_inner())
+        })
+        .await
+    }
+
+    pub async fn artifact_status_request(
+        &self,
+        names: Vec<String>,
+    ) -> Result<spark::ArtifactStatusesResponse, SparkError> {
+        let req = spark::ArtifactStatusesRequest {
+            session_id: self.session_id(),
+            user_context: self.user_context.clone(),
+            client_type: self.builder.user_agent.clone(),
+            #[cfg(feature = "spark-4")]
+            client_observed_server_side_session_id: self.server_side_session_id(),
+            names,
+        };
+
+        let span = tracing::debug_span!(
+            "artifact_status",
+            session_id = %self.session_id,
+            names = req.names.len(),
+            elapsed_ms = Empty,
+        );
+
+        trace::traced(span, async {
+            let resp = self
+                .send(|| async { self.stub().await.artifact_status(req.clone()).await })
+                .await?;
+
+            Ok(resp.into_inner())
+        })
+        .await
     }
 
     pub async fn interrupt_request(
@@ -367,6 +835,8 @@ where
             session_id: self.session_id(),
             user_context: self.user_context.clone(),
             client_type: self.builder.user_agent.clone(),
+            #[cfg(feature = "spark-4")]
+            client_observed_server_side_session_id: self.server_side_session_id(),
             interrupt_type: 0,
             interrupt: None,
         };
@@ -394,100 +864,230 @@ where
             }
         };
 
-        let mut client = self.stub.write().await;
+        let span = tracing::info_span!(
+            "interrupt",
+            session_id = %self.session_id,
+            interrupt_type = interrupt_type.as_str_name(),
+            interrupt = ?req.interrupt,
+            elapsed_ms = Empty,
+        );
+
+        trace::traced(span, async {
+            let resp = self
+                .send(|| async { self.stub().await.interrupt(req.clone()).await })
+                .await?;
+
+            Ok(resp.into_inner())
+        })
+        .await
+    }
+
+    /// Release the session on the server along with all of its state, e.g. temporary
+    /// views, configs and running operations
+    #[cfg(feature = "spark-4")]
+    pub async fn release_session(&self) -> Result<spark::ReleaseSessionResponse, SparkError> {
+        let req = spark::ReleaseSessionRequest {
+            session_id: self.session_id(),
+            user_context: self.user_context.clone(),
+            client_type: self.builder.user_agent.clone(),
+            allow_reconnect: false,
+        };
+
+        let span = tracing::info_span!(
+            "release_session",
+            session_id = %self.session_id,
+            elapsed_ms = Empty,
+        );
+
+        trace::traced(span, async {
+            let resp = self
+                .send(|| async { self.stub().await.release_session(req.clone()).await })
+                .await?;
+
+            Ok(resp.into_inner())
+        })
+        .await
+    }
+
+    /// Fetch the full details of a server error that were cached under its error id
+    #[cfg(feature = "spark-4")]
+    pub async fn fetch_error_details(
+        &self,
+        error_id: &str,
+    ) -> Result<spark::FetchErrorDetailsResponse, SparkError> {
+        let req = spark::FetchErrorDetailsRequest {
+            session_id: self.session_id(),
+            client_observed_server_side_session_id: self.server_side_session_id(),
+            user_context: self.user_context.clone(),
+            error_id: error_id.to_string(),
+            client_type: self.builder.user_agent.clone(),
+        };
+
+        let span = tracing::debug_span!(
+            "fetch_error_details",
+            session_id = %self.session_id,
+            error_id,
+            elapsed_ms = Empty,
+        );
 
-        let resp = client.interrupt(req).await?.into_inner();
+        trace::traced(span, async {
+            let resp = self
+                .send(|| async { self.stub().await.fetch_error_details(req.clone()).await })
+                .await?;
 
-        Ok(resp)
+            Ok(resp.into_inner())
+        })
+        .await
     }
 
-    fn handle_response(&mut self, resp: spark::ExecutePlanResponse) -> Result<(), SparkError> {
+    /// Enrich a failed result with the error details held by the server.
+    ///
+    /// The original error is kept when the server has no details for it
+    #[cfg(feature = "spark-4")]
+    async fn with_error_details<R>(&self, result: Result<R, SparkError>) -> Result<R, SparkError> {
+        match result {
+            Err(err) => match err.error_id() {
+                Some(error_id) => match self.fetch_error_details(error_id).await {
+                    Ok(details) => Err(err.with_details(details)),
+                    Err(_) => Err(err),
+                },
+                None => Err(err),
+            },
+            result => result,
+        }
+    }
+
+    /// Server errors carry no error details before Spark 4
+    #[cfg(not(feature = "spark-4"))]
+    async fn with_error_details<R>(&self, result: Result<R, SparkError>) -> Result<R, SparkError> {
+        result
+    }
+
+    /// Apply an [spark::ExecutePlanResponse] to the operation handler and return
+    /// any arrow batches that were decoded from the response
+    fn handle_response(
+        &self,
+        handler: &mut ResponseHandler,
+        resp: spark::ExecutePlanResponse,
+    ) -> Result<Vec<RecordBatch>, SparkError> {
+        let mut batches = vec![];
+
         self.validate_session(&resp.session_id)?;
+        #[cfg(feature = "spark-4")]
+        self.validate_server_side_session(&resp.server_side_session_id)?;
 
-        self.operation_id = Some(resp.operation_id);
-        self.response_id = Some(resp.response_id);
+        handler.response_count += 1;
+        handler.operation_id = Some(resp.operation_id);
+        handler.response_id = Some(resp.response_id);
 
         if let Some(schema) = &resp.schema {
-            self.handler.schema = Some(schema.clone());
+            handler.schema = Some(schema.clone());
         }
         if let Some(metrics) = &resp.metrics {
-            self.handler.metrics = Some(metrics.clone());
+            handler.metrics = Some(metrics.clone());
+        }
+        if !resp.observed_metrics.is_empty() {
+            let mut observed = self.observed_metrics.lock().unwrap();
+
+            for metrics in &resp.observed_metrics {
+                observed.insert(metrics.name.clone(), metrics.clone().into());
+            }
+
+            handler.observed_metrics.extend(resp.observed_metrics);
         }
         if let Some(data) = resp.response_type {
             match data {
                 ResponseType::ArrowBatch(res) => {
-                    self.deserialize(res.data.as_slice(), res.row_count)?
+                    handler.arrow_bytes += res.data.len();
+                    batches = self.deserialize(handler, res.data.as_slice(), res.row_count)?
                 }
                 ResponseType::SqlCommandResult(sql_cmd) => {
-                    self.handler.sql_command_result = Some(sql_cmd.clone())
+                    handler.sql_command_result = Some(sql_cmd.clone())
                 }
                 ResponseType::WriteStreamOperationStartResult(write_stream_op) => {
-                    self.handler.write_stream_operation_start_result = Some(write_stream_op)
+                    handler.write_stream_operation_start_result = Some(write_stream_op)
                 }
                 ResponseType::StreamingQueryCommandResult(stream_qry_cmd) => {
-                    self.handler.streaming_query_command_result = Some(stream_qry_cmd)
+                    handler.streaming_query_command_result = Some(stream_qry_cmd)
                 }
                 ResponseType::GetResourcesCommandResult(resource_cmd) => {
-                    self.handler.get_resources_command_result = Some(resource_cmd)
+                    handler.get_resources_command_result = Some(resource_cmd)
                 }
                 ResponseType::StreamingQueryManagerCommandResult(stream_qry_mngr_cmd) => {
-                    self.handler.streaming_query_manager_command_result = Some(stream_qry_mngr_cmd)
+                    handler.streaming_query_manager_command_result = Some(stream_qry_mngr_cmd)
                 }
-                ResponseType::ResultComplete(_) => self.handler.result_complete = true,
-                ResponseType::Extension(_) => {
-                    unimplemented!("extension response types are not implemented")
+                ResponseType::ResultComplete(_) => handler.result_complete = true,
+                ResponseType::Extension(extension) => {
+                    handler.extensions.push(self.extensions.decode(extension)?)
                 }
+                #[cfg(feature = "spark-4")]
+                ResponseType::ExecutionProgress(progress) => {
+                    let operation_id = handler.operation_id.clone().unwrap_or_default();
+
+                    self.progress_handlers
+                        .notify(&ExecutionProgress::new(operation_id, progress))
+                }
+                // results of commands this client does not send
+                #[cfg(feature = "spark-4")]
+                _ => {}
             }
         }
-        Ok(())
+        Ok(batches)
     }
 
     fn handle_analyze(
-        &mut self,
+        &self,
         resp: spark::AnalyzePlanResponse,
-    ) -> Result<&mut Self, SparkError> {
+    ) -> Result<AnalyzeHandler, SparkError> {
         self.validate_session(&resp.session_id)?;
+        #[cfg(feature = "spark-4")]
+        self.validate_server_side_session(&resp.server_side_session_id)?;
+
+        let mut analyzer = AnalyzeHandler::default();
+
         if let Some(result) = resp.result {
             match result {
                 spark::analyze_plan_response::Result::Schema(schema) => {
-                    self.analyzer.schema = schema.schema
+                    analyzer.schema = schema.schema
                 }
                 spark::analyze_plan_response::Result::Explain(explain) => {
-                    self.analyzer.explain = Some(explain.explain_string)
+                    analyzer.explain = Some(explain.explain_string)
                 }
                 spark::analyze_plan_response::Result::TreeString(tree_string) => {
-                    self.analyzer.tree_string = Some(tree_string.tree_string)
+                    analyzer.tree_string = Some(tree_string.tree_string)
                 }
                 spark::analyze_plan_response::Result::IsLocal(is_local) => {
-                    self.analyzer.is_local = Some(is_local.is_local)
+                    analyzer.is_local = Some(is_local.is_local)
                 }
                 spark::analyze_plan_response::Result::IsStreaming(is_streaming) => {
-                    self.analyzer.is_streaming = Some(is_streaming.is_streaming)
+                    analyzer.is_streaming = Some(is_streaming.is_streaming)
                 }
                 spark::analyze_plan_response::Result::InputFiles(input_files) => {
-                    self.analyzer.input_files = Some(input_files.files)
+                    analyzer.input_files = Some(input_files.files)
                 }
                 spark::analyze_plan_response::Result::SparkVersion(spark_version) => {
-                    self.analyzer.spark_version = Some(spark_version.version)
+                    analyzer.spark_version = Some(spark_version.version)
                 }
                 spark::analyze_plan_response::Result::DdlParse(ddl_parse) => {
-                    self.analyzer.ddl_parse = ddl_parse.parsed
+                    analyzer.ddl_parse = ddl_parse.parsed
                 }
                 spark::analyze_plan_response::Result::SameSemantics(same_semantics) => {
-                    self.analyzer.same_semantics = Some(same_semantics.result)
+                    analyzer.same_semantics = Some(same_semantics.result)
                 }
                 spark::analyze_plan_response::Result::SemanticHash(semantic_hash) => {
-                    self.analyzer.semantic_hash = Some(semantic_hash.result)
+                    analyzer.semantic_hash = Some(semantic_hash.result)
                 }
                 spark::analyze_plan_response::Result::Persist(_) => {}
                 spark::analyze_plan_response::Result::Unpersist(_) => {}
                 spark::analyze_plan_response::Result::GetStorageLevel(level) => {
-                    self.analyzer.get_storage_level = level.storage_level
+                    analyzer.get_storage_level = level.storage_level
                 }
+                #[cfg(feature = "spark-4")]
+                spark::analyze_plan_response::Result::JsonToDdl(_) => {}
             }
         }
 
-        Ok(self)
+        Ok(analyzer)
     }
 
     fn validate_session(&self, session_id: &str) -> Result<(), SparkError> {
@@ -500,8 +1100,45 @@ where
         Ok(())
     }
 
-    fn deserialize(&mut self, res: &[u8], row_count: i64) -> Result<(), SparkError> {
+    /// Server side session id of the first response, which is sent back with every request
+    #[cfg(feature = "spark-4")]
+    fn server_side_session_id(&self) -> Option<String> {
+        self.server_side_session_id.lock().unwrap().clone()
+    }
+
+    /// Fails when the server side session changed, e.g. because the server restarted and
+    /// the temporary views and configs of the session are gone
+    #[cfg(feature = "spark-4")]
+    fn validate_server_side_session(&self, server_side_session_id: &str) -> Result<(), SparkError> {
+        if server_side_session_id.is_empty() {
+            return Ok(());
+        }
+
+        let mut observed = self.server_side_session_id.lock().unwrap();
+
+        match observed.as_deref() {
+            Some(id) if id != server_side_session_id => {
+                Err(SparkError::SessionNotSameException(format!(
+                    "Received incorrect server side session identifier for request: {0} != {1}",
+                    id, server_side_session_id
+                )))
+            }
+            Some(_) => Ok(()),
+            None => {
+                *observed = Some(server_side_session_id.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    fn deserialize(
+        &self,
+        handler: &mut ResponseHandler,
+        res: &[u8],
+        row_count: i64,
+    ) -> Result<Vec<RecordBatch>, SparkError> {
         let reader = StreamReader::try_new(res, None)?;
+        let mut batches = vec![];
         for batch in reader {
             let record = batch?;
             if record.num_rows() != row_count as usize {
@@ -511,13 +1148,13 @@ where
                     record.num_rows()
                 ))));
             };
-            self.handler.batches.push(record);
-            self.handler.total_count += row_count as isize;
+            batches.push(record);
+            handler.total_count += row_count as isize;
         }
-        Ok(())
+        Ok(batches)
     }
 
-    pub async fn execute_command(&mut self, plan: spark::Plan) -> Result<(), SparkError> {
+    pub async fn execute_command(&self, plan: spark::Plan) -> Result<(), SparkError> {
         let mut req = self.execute_plan_request_with_metadata();
 
         req.plan = Some(plan);
@@ -528,34 +1165,131 @@ where
     }
 
     pub(crate) async fn execute_command_and_fetch(
-        &mut self,
+        &self,
         plan: spark::Plan,
     ) -> Result<ResponseHandler, SparkError> {
         let mut req = self.execute_plan_request_with_metadata();
 
         req.plan = Some(plan);
 
-        self.execute_and_fetch(req).await?;
+        self.execute_and_fetch(req).await
+    }
 
-        Ok(self.handler.clone())
+    #[allow(clippy::wrong_self_convention)]
+    pub async fn to_arrow(&self, plan: spark::Plan) -> Result<RecordBatch, SparkError> {
+        let mut req = self.execute_plan_request_with_metadata();
+
+        req.plan = Some(plan);
+
+        let batches = self.execute_and_fetch(req).await?.batches;
+
+        concat_or_empty(&batches)
     }
 
+    /// Submit a plan and return the records with the [QueryMetrics] of its execution
     #[allow(clippy::wrong_self_convention)]
-    pub async fn to_arrow(&mut self, plan: spark::Plan) -> Result<RecordBatch, SparkError> {
+    pub async fn to_arrow_with_metrics(
+        &self,
+        plan: spark::Plan,
+    ) -> Result<(RecordBatch, QueryMetrics), SparkError> {
         let mut req = self.execute_plan_request_with_metadata();
 
         req.plan = Some(plan);
 
-        self.execute_and_fetch(req).await?;
+        let handler = self.execute_and_fetch(req).await?;
 
-        Ok(concat_batches(
-            &self.handler.batches[0].schema(),
-            &self.handler.batches,
-        )?)
+        let batches = concat_or_empty(&handler.batches)?;
+        let metrics = handler.metrics.map(QueryMetrics::from).unwrap_or_default();
+
+        Ok((batches, metrics))
     }
 
+    /// Submit a plan and return a [RecordBatchStream] which yields each [RecordBatch]
+    /// as it is received from the server instead of buffering the full result
     #[allow(clippy::wrong_self_convention)]
-    pub(crate) async fn to_first_value(&mut self, plan: spark::Plan) -> Result<String, SparkError> {
+    pub async fn to_arrow_stream(self, plan: spark::Plan) -> Result<RecordBatchStream, SparkError>
+    where
+        T::ResponseBody: Sync,
+    {
+        let mut req = self.execute_plan_request_with_metadata();
+
+        req.plan = Some(plan);
+
+        let handler = ResponseHandler {
+            operation_id: req.operation_id.clone(),
+            ..Default::default()
+        };
+
+        // interrupts the operation if the stream is dropped before it is exhausted
+        let guard = OperationGuard::new(self.cancel_operation(req.operation_id.clone().unwrap()));
+
+        let span = tracing::info_span!(
+            "execute_stream",
+            session_id = %self.session_id,
+            operation_id = req.operation_id.as_deref(),
+            tags = ?req.tags,
+            plan = trace::plan_kind(req.plan.as_ref()),
+            responses = Empty,
+            arrow_bytes = Empty,
+            rows = Empty,
+            elapsed_ms = Empty,
+        );
+
+        let started = Instant::now();
+
+        let stream = self
+            .with_deadline(self.execute_stream(req))
+            .instrument(span.clone())
+            .await?;
+
+        Ok(RecordBatchStream::new(
+            self,
+            handler,
+            Some(guard),
+            stream,
+            span,
+            started,
+        ))
+    }
+
+    /// Reattach to an existing operation and return a [RecordBatchStream] over its
+    /// remaining results
+    pub(crate) async fn reattach_arrow_stream(
+        self,
+        operation_id: &str,
+    ) -> Result<RecordBatchStream, SparkError>
+    where
+        T::ResponseBody: Sync,
+    {
+        let handler = ResponseHandler {
+            operation_id: Some(operation_id.to_string()),
+            ..Default::default()
+        };
+
+        let span = tracing::info_span!(
+            "reattach_stream",
+            session_id = %self.session_id,
+            operation_id,
+            responses = Empty,
+            arrow_bytes = Empty,
+            rows = Empty,
+            elapsed_ms = Empty,
+        );
+
+        let started = Instant::now();
+
+        let stream = self
+            .with_deadline(self.reattach_stream(&handler))
+            .instrument(span.clone())
+            .await?;
+
+        Ok(RecordBatchStream::new(
+            self, handler, None, stream, span, started,
+        ))
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    pub(crate) async fn to_first_value(&self, plan: spark::Plan) -> Result<String, SparkError> {
         let rows = self.to_arrow(plan).await?;
         let col = rows.column(0);
 
@@ -566,74 +1300,81 @@ where
 
         Ok(data.value(0).to_string())
     }
+}
 
+impl AnalyzeHandler {
     pub fn schema(&self) -> Result<spark::DataType, SparkError> {
-        self.analyzer
-            .schema
+        self.schema
             .to_owned()
             .ok_or_else(|| SparkError::AnalysisException("Schema response is empty".to_string()))
     }
 
     pub fn explain(&self) -> Result<String, SparkError> {
-        self.analyzer
-            .explain
+        self.explain
             .to_owned()
             .ok_or_else(|| SparkError::AnalysisException("Explain response is empty".to_string()))
     }
 
     pub fn tree_string(&self) -> Result<String, SparkError> {
-        self.analyzer.tree_string.to_owned().ok_or_else(|| {
+        self.tree_string.to_owned().ok_or_else(|| {
             SparkError::AnalysisException("Tree String response is empty".to_string())
         })
     }
 
     pub fn is_local(&self) -> Result<bool, SparkError> {
-        self.analyzer
-            .is_local
+        self.is_local
             .to_owned()
             .ok_or_else(|| SparkError::AnalysisException("Is Local response is empty".to_string()))
     }
 
     pub fn is_streaming(&self) -> Result<bool, SparkError> {
-        self.analyzer.is_streaming.to_owned().ok_or_else(|| {
+        self.is_streaming.to_owned().ok_or_else(|| {
             SparkError::AnalysisException("Is Streaming response is empty".to_string())
         })
     }
 
     pub fn input_files(&self) -> Result<Vec<String>, SparkError> {
-        self.analyzer.input_files.to_owned().ok_or_else(|| {
+        self.input_files.to_owned().ok_or_else(|| {
             SparkError::AnalysisException("Input Files response is empty".to_string())
         })
     }
 
-    pub fn spark_version(&mut self) -> Result<String, SparkError> {
-        self.analyzer.spark_version.to_owned().ok_or_else(|| {
+    pub fn spark_version(&self) -> Result<String, SparkError> {
+        self.spark_version.to_owned().ok_or_else(|| {
             SparkError::AnalysisException("Spark Version resonse is empty".to_string())
         })
     }
 
     pub fn ddl_parse(&self) -> Result<spark::DataType, SparkError> {
-        self.analyzer
-            .ddl_parse
+        self.ddl_parse
             .to_owned()
             .ok_or_else(|| SparkError::AnalysisException("DDL parse response is empty".to_string()))
     }
 
     pub fn same_semantics(&self) -> Result<bool, SparkError> {
-        self.analyzer.same_semantics.to_owned().ok_or_else(|| {
+        self.same_semantics.to_owned().ok_or_else(|| {
             SparkError::AnalysisException("Same Semantics response is empty".to_string())
         })
     }
 
     pub fn semantic_hash(&self) -> Result<i32, SparkError> {
-        self.analyzer.semantic_hash.to_owned().ok_or_else(|| {
+        self.semantic_hash.to_owned().ok_or_else(|| {
             SparkError::AnalysisException("Semantic Hash response is empty".to_string())
         })
     }
 
     pub fn get_storage_level(&self) -> Result<spark::StorageLevel, SparkError> {
-        self.analyzer.get_storage_level.to_owned().ok_or_else(|| {
+        self.get_storage_level.to_owned().ok_or_else(|| {
             SparkError::AnalysisException("Storage Level response is empty".to_string())
         })
     }
 }
+
+/// Concatenate the batches of a result, or return an empty batch without columns if the
+/// result has no batches
+pub(crate) fn concat_or_empty(batches: &[RecordBatch]) -> Result<RecordBatch, SparkError> {
+    match batches.first() {
+        Some(batch) => Ok(concat_batches(&batch.schema(), batches)?),
+        None => Ok(RecordBatch::new_empty(Arc::new(Schema::empty()))),
+    }
+}
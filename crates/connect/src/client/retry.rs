@@ -0,0 +1,250 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Retry policy for transient gRPC failures
+
+use std::future::Future;
+use std::time::Duration;
+
+use tonic::{Code, Status};
+
+/// Policy used to retry RPCs that fail with a transient error
+///
+/// Each retry waits for an exponentially increasing backoff, capped at `max_backoff`,
+/// plus a random jitter. The defaults follow the PySpark Spark Connect client.
+///
+/// # Example
+/// ```rust
+/// let policy = RetryPolicy::default()
+///     .max_retries(5)
+///     .initial_backoff(Duration::from_millis(100))
+///     .max_backoff(Duration::from_secs(10));
+///
+/// let spark = SparkSessionBuilder::remote("sc://127.0.0.1:15002/")
+///     .retry_policy(policy)
+///     .build()
+///     .await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: usize,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub backoff_multiplier: f64,
+    pub jitter: Duration,
+    pub retryable_codes: Vec<Code>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 15,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(60),
+            backoff_multiplier: 4.0,
+            jitter: Duration::from_millis(500),
+            retryable_codes: vec![Code::Unavailable],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        RetryPolicy::default()
+    }
+
+    /// A policy that never retries
+    pub fn none() -> Self {
+        RetryPolicy::default().max_retries(0)
+    }
+
+    pub fn max_retries(mut self, val: usize) -> Self {
+        self.max_retries = val;
+        self
+    }
+
+    pub fn initial_backoff(mut self, val: Duration) -> Self {
+        self.initial_backoff = val;
+        self
+    }
+
+    pub fn max_backoff(mut self, val: Duration) -> Self {
+        self.max_backoff = val;
+        self
+    }
+
+    pub fn backoff_multiplier(mut self, val: f64) -> Self {
+        self.backoff_multiplier = val;
+        self
+    }
+
+    pub fn jitter(mut self, val: Duration) -> Self {
+        self.jitter = val;
+        self
+    }
+
+    pub fn retryable_codes(mut self, val: Vec<Code>) -> Self {
+        self.retryable_codes = val;
+        self
+    }
+
+    /// Returns true if the status is one of the retryable codes
+    pub fn is_retryable(&self, status: &Status) -> bool {
+        self.retryable_codes.contains(&status.code())
+    }
+
+    /// Backoff before the given retry attempt (starting at 0), without jitter
+    ///
+    /// A negative `backoff_multiplier` is treated as 0, and a backoff that can not be
+    /// represented as a [Duration] is capped at `max_backoff`.
+    pub(crate) fn backoff(&self, attempt: usize) -> Duration {
+        let factor = self
+            .backoff_multiplier
+            .max(0.0)
+            .powi(attempt.min(i32::MAX as usize) as i32);
+
+        let backoff = self.initial_backoff.as_secs_f64() * factor;
+
+        Duration::try_from_secs_f64(backoff)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+
+    /// Delay to wait before the given retry attempt, or None if the failure should not be retried
+    pub(crate) fn next_delay(&self, attempt: usize, status: &Status) -> Option<Duration> {
+        if attempt >= self.max_retries || !self.is_retryable(status) {
+            return None;
+        }
+
+        let jitter = Duration::try_from_secs_f64(self.jitter.as_secs_f64() * rand::random::<f64>())
+            .unwrap_or(self.jitter);
+
+        Some(self.backoff(attempt).saturating_add(jitter))
+    }
+
+    /// Run the request until it succeeds, fails with a non-retryable error,
+    /// or the retries are exhausted
+    pub(crate) async fn retry<F, Fut, R>(&self, mut request: F) -> Result<R, Status>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<R, Status>>,
+    {
+        let mut attempt = 0;
+
+        loop {
+            match request().await {
+                Ok(resp) => return Ok(resp),
+                Err(status) => match self.next_delay(attempt, &status) {
+                    Some(delay) => {
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    None => return Err(status),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let policy = RetryPolicy::default()
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_secs(1))
+            .backoff_multiplier(2.0);
+
+        assert_eq!(Duration::from_millis(100), policy.backoff(0));
+        assert_eq!(Duration::from_millis(400), policy.backoff(2));
+        assert_eq!(Duration::from_secs(1), policy.backoff(10));
+        assert_eq!(Duration::from_secs(1), policy.backoff(usize::MAX));
+    }
+
+    #[test]
+    fn test_backoff_out_of_range() {
+        let unbounded = RetryPolicy::default()
+            .max_backoff(Duration::MAX)
+            .jitter(Duration::MAX);
+
+        let unavailable = Status::unavailable("connection reset");
+
+        assert_eq!(Duration::MAX, unbounded.backoff(usize::MAX));
+        assert!(unbounded.next_delay(14, &unavailable).is_some());
+
+        let negative = RetryPolicy::default()
+            .initial_backoff(Duration::from_millis(100))
+            .backoff_multiplier(-2.0);
+
+        assert_eq!(Duration::from_millis(100), negative.backoff(0));
+        assert_eq!(Duration::ZERO, negative.backoff(3));
+    }
+
+    #[test]
+    fn test_next_delay() {
+        let policy = RetryPolicy::default().max_retries(2).jitter(Duration::ZERO);
+
+        let unavailable = Status::unavailable("connection reset");
+        let invalid = Status::invalid_argument("bad plan");
+
+        assert!(policy.next_delay(0, &unavailable).is_some());
+        assert!(policy.next_delay(2, &unavailable).is_none());
+        assert!(policy.next_delay(0, &invalid).is_none());
+        assert!(RetryPolicy::none().next_delay(0, &unavailable).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_retry_until_success() {
+        let policy = RetryPolicy::default()
+            .initial_backoff(Duration::from_millis(1))
+            .jitter(Duration::ZERO);
+
+        let calls = AtomicUsize::new(0);
+
+        let resp = policy
+            .retry(|| async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 | 1 => Err(Status::unavailable("server restarting")),
+                    _ => Ok("done"),
+                }
+            })
+            .await;
+
+        assert_eq!("done", resp.unwrap());
+        assert_eq!(3, calls.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_retry_non_retryable() {
+        let policy = RetryPolicy::default();
+
+        let calls = AtomicUsize::new(0);
+
+        let resp: Result<(), Status> = policy
+            .retry(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(Status::invalid_argument("bad plan"))
+            })
+            .await;
+
+        assert_eq!(Code::InvalidArgument, resp.unwrap_err().code());
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,258 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Incremental [RecordBatch] results from an ExecutePlan response stream
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use futures_util::stream::{self, BoxStream, Stream, StreamExt};
+
+use tonic::codec::Streaming;
+use tonic::codegen::{Body, Bytes, StdError};
+
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+
+use tokio::runtime::Handle;
+
+use tracing::{Instrument, Span};
+
+use crate::client::{OperationGuard, ResponseHandler, SparkConnectClient};
+use crate::errors::SparkError;
+use crate::spark;
+
+/// Holds the client, response state and the open response stream for a single operation
+struct ExecuteStreamState<T> {
+    client: SparkConnectClient<T>,
+    handler: ResponseHandler,
+    guard: Option<OperationGuard>,
+    stream: Streaming<spark::ExecutePlanResponse>,
+    batches: VecDeque<RecordBatch>,
+    finished: bool,
+    attempt: usize,
+    span: Span,
+    started: Instant,
+}
+
+impl<T> ExecuteStreamState<T>
+where
+    T: tonic::client::GrpcService<tonic::body::BoxBody> + Clone + Send + Sync + 'static,
+    T::Error: Into<StdError>,
+    T::Future: Send,
+    T::ResponseBody: Body<Data = Bytes> + Send + 'static,
+    <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+{
+    async fn next_batch(mut self) -> Result<Option<(RecordBatch, Self)>, SparkError> {
+        let span = self.span.clone();
+
+        match self.pull_batch().instrument(span.clone()).await {
+            Ok(Some(batch)) => Ok(Some((batch, self))),
+            result => {
+                // the operation is no longer running on the server
+                if let Some(guard) = self.guard.take() {
+                    guard.disarm();
+                }
+
+                self.handler.record(&span);
+                span.record("elapsed_ms", self.started.elapsed().as_millis() as u64);
+
+                if let Err(err) = &result {
+                    span.in_scope(|| tracing::warn!(error = %err, "request failed"));
+                }
+
+                result.map(|_| None)
+            }
+        }
+    }
+
+    /// Pull responses off of the stream until a [RecordBatch] is available.
+    ///
+    /// Follows the same reattach and release flow as `SparkConnectClient::process_stream`
+    async fn pull_batch(&mut self) -> Result<Option<RecordBatch>, SparkError> {
+        loop {
+            if let Some(batch) = self.batches.pop_front() {
+                return Ok(Some(batch));
+            }
+
+            if self.finished {
+                return Ok(None);
+            }
+
+            match self.stream.message().await {
+                Ok(Some(msg)) => {
+                    self.attempt = 0;
+                    let batches = self.client.handle_response(&mut self.handler, msg)?;
+                    self.batches.extend(batches);
+                }
+                Ok(None) => {
+                    let reattachable = self.client.use_reattachable_execute;
+
+                    if reattachable && !self.handler.result_complete {
+                        self.stream = self.client.reattach_stream(&self.handler).await?;
+                    } else {
+                        if reattachable {
+                            self.client.release_all(&self.handler).await?;
+                        }
+                        self.finished = true;
+                    }
+                }
+                Err(status) => {
+                    let resumed = self
+                        .client
+                        .resume_stream(&self.handler, &status, self.attempt)
+                        .await?;
+
+                    if let Some(resumed) = resumed {
+                        self.attempt += 1;
+                        self.stream = resumed;
+                        continue;
+                    }
+
+                    if self.client.use_reattachable_execute && self.handler.response_id.is_some() {
+                        self.client.release_until(&self.handler).await?;
+                    }
+                    return Err(status.into());
+                }
+            }
+        }
+    }
+}
+
+/// An asynchronous [Stream] of [RecordBatch] returned from an executed plan.
+///
+/// Batches are decoded as the server delivers them and the next response is only
+/// requested once the prior batch has been consumed. Dropping the stream before it is
+/// exhausted interrupts the operation on the server.
+///
+/// # Example:
+///
+/// ```rust
+/// use futures::StreamExt;
+///
+/// let mut stream = df.collect_stream().await?;
+///
+/// while let Some(batch) = stream.next().await {
+///     let batch = batch?;
+/// }
+/// ```
+pub struct RecordBatchStream {
+    inner: BoxStream<'static, Result<RecordBatch, SparkError>>,
+}
+
+impl RecordBatchStream {
+    pub(crate) fn new<T>(
+        client: SparkConnectClient<T>,
+        handler: ResponseHandler,
+        guard: Option<OperationGuard>,
+        stream: Streaming<spark::ExecutePlanResponse>,
+        span: Span,
+        started: Instant,
+    ) -> Self
+    where
+        T: tonic::client::GrpcService<tonic::body::BoxBody> + Clone + Send + Sync + 'static,
+        T::Error: Into<StdError>,
+        T::Future: Send,
+        T::ResponseBody: Body<Data = Bytes> + Send + Sync + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+    {
+        let state = ExecuteStreamState {
+            client,
+            handler,
+            guard,
+            stream,
+            batches: VecDeque::new(),
+            finished: false,
+            attempt: 0,
+            span,
+            started,
+        };
+
+        let inner = stream::try_unfold(state, ExecuteStreamState::next_batch).boxed();
+
+        RecordBatchStream { inner }
+    }
+
+    /// Convert the stream into a blocking [RecordBatchReader].
+    ///
+    /// The first batch is fetched eagerly to determine the schema.
+    pub async fn into_reader(mut self) -> Result<RecordBatchStreamReader, SparkError> {
+        let first = self.next().await.transpose()?;
+
+        let schema = match &first {
+            Some(batch) => batch.schema(),
+            None => Arc::new(Schema::empty()),
+        };
+
+        Ok(RecordBatchStreamReader {
+            handle: Handle::current(),
+            stream: self,
+            schema,
+            first,
+        })
+    }
+}
+
+impl Stream for RecordBatchStream {
+    type Item = Result<RecordBatch, SparkError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+impl std::fmt::Debug for RecordBatchStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecordBatchStream").finish_non_exhaustive()
+    }
+}
+
+/// A blocking [RecordBatchReader] over a [RecordBatchStream]
+///
+/// Each call to `next` blocks on the tokio runtime the reader was created in, so the reader
+/// must be consumed outside of an async context, e.g. within `tokio::task::spawn_blocking`.
+#[derive(Debug)]
+pub struct RecordBatchStreamReader {
+    handle: Handle,
+    stream: RecordBatchStream,
+    schema: SchemaRef,
+    first: Option<RecordBatch>,
+}
+
+impl Iterator for RecordBatchStreamReader {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(batch) = self.first.take() {
+            return Some(Ok(batch));
+        }
+
+        self.handle
+            .block_on(self.stream.next())
+            .map(|batch| batch.map_err(|err| ArrowError::ExternalError(Box::new(err))))
+    }
+}
+
+impl RecordBatchReader for RecordBatchStreamReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
@@ -0,0 +1,269 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Helpers for the `tracing` spans emitted around each client RPC, and the
+//! W3C trace context propagated to the server with `feature = 'otel'`
+
+use std::future::Future;
+use std::time::Instant;
+
+use tonic::codegen::http::HeaderMap;
+#[cfg(feature = "otel")]
+use tonic::codegen::http::{HeaderName, HeaderValue};
+use tracing::{Instrument, Span};
+
+use crate::errors::SparkError;
+use crate::spark;
+
+use spark::analyze_plan_request::Analyze;
+use spark::command::CommandType;
+use spark::config_request::operation::OpType;
+
+/// Await the future within the span, then record its duration as `elapsed_ms`
+/// and emit an event with the error if it failed
+pub(super) async fn traced<F, R>(span: Span, fut: F) -> Result<R, SparkError>
+where
+    F: Future<Output = Result<R, SparkError>>,
+{
+    let start = Instant::now();
+
+    let result = fut.instrument(span.clone()).await;
+
+    span.record("elapsed_ms", start.elapsed().as_millis() as u64);
+
+    if let Err(err) = &result {
+        span.in_scope(|| tracing::warn!(error = %err, "request failed"));
+    }
+
+    result
+}
+
+/// Kind of plan submitted by an ExecutePlan request, either `root` for a relation
+/// or the name of the command
+pub(super) fn plan_kind(plan: Option<&spark::Plan>) -> &'static str {
+    let command = match plan.and_then(|plan| plan.op_type.as_ref()) {
+        Some(spark::plan::OpType::Root(_)) => return "root",
+        Some(spark::plan::OpType::Command(command)) => command.command_type.as_ref(),
+        #[cfg(feature = "spark-4")]
+        Some(spark::plan::OpType::CompressedOperation(_)) => return "compressed_operation",
+        None => None,
+    };
+
+    match command {
+        Some(CommandType::RegisterFunction(_)) => "register_function",
+        Some(CommandType::WriteOperation(_)) => "write_operation",
+        Some(CommandType::CreateDataframeView(_)) => "create_dataframe_view",
+        Some(CommandType::WriteOperationV2(_)) => "write_operation_v2",
+        Some(CommandType::SqlCommand(_)) => "sql_command",
+        Some(CommandType::WriteStreamOperationStart(_)) => "write_stream_operation_start",
+        Some(CommandType::StreamingQueryCommand(_)) => "streaming_query_command",
+        Some(CommandType::GetResourcesCommand(_)) => "get_resources_command",
+        Some(CommandType::StreamingQueryManagerCommand(_)) => "streaming_query_manager_command",
+        Some(CommandType::RegisterTableFunction(_)) => "register_table_function",
+        Some(CommandType::Extension(_)) => "extension",
+        #[cfg(feature = "spark-4")]
+        Some(CommandType::StreamingQueryListenerBusCommand(_)) => {
+            "streaming_query_listener_bus_command"
+        }
+        #[cfg(feature = "spark-4")]
+        Some(CommandType::RegisterDataSource(_)) => "register_data_source",
+        #[cfg(feature = "spark-4")]
+        Some(CommandType::CreateResourceProfileCommand(_)) => "create_resource_profile_command",
+        #[cfg(feature = "spark-4")]
+        Some(CommandType::CheckpointCommand(_)) => "checkpoint_command",
+        #[cfg(feature = "spark-4")]
+        Some(CommandType::RemoveCachedRemoteRelationCommand(_)) => {
+            "remove_cached_remote_relation_command"
+        }
+        #[cfg(feature = "spark-4")]
+        Some(CommandType::MergeIntoTableCommand(_)) => "merge_into_table_command",
+        #[cfg(feature = "spark-4")]
+        Some(CommandType::MlCommand(_)) => "ml_command",
+        #[cfg(feature = "spark-4")]
+        Some(CommandType::ExecuteExternalCommand(_)) => "execute_external_command",
+        #[cfg(feature = "spark-4")]
+        Some(CommandType::PipelineCommand(_)) => "pipeline_command",
+        None => "unknown",
+    }
+}
+
+/// Kind of analysis requested by an AnalyzePlan request
+pub(super) fn analyze_kind(analyze: &Analyze) -> &'static str {
+    match analyze {
+        Analyze::Schema(_) => "schema",
+        Analyze::Explain(_) => "explain",
+        Analyze::TreeString(_) => "tree_string",
+        Analyze::IsLocal(_) => "is_local",
+        Analyze::IsStreaming(_) => "is_streaming",
+        Analyze::InputFiles(_) => "input_files",
+        Analyze::SparkVersion(_) => "spark_version",
+        Analyze::DdlParse(_) => "ddl_parse",
+        Analyze::SameSemantics(_) => "same_semantics",
+        Analyze::SemanticHash(_) => "semantic_hash",
+        Analyze::Persist(_) => "persist",
+        Analyze::Unpersist(_) => "unpersist",
+        Analyze::GetStorageLevel(_) => "get_storage_level",
+        #[cfg(feature = "spark-4")]
+        Analyze::JsonToDdl(_) => "json_to_ddl",
+    }
+}
+
+/// Kind of operation requested by a Config request
+pub(super) fn config_kind(operation: &spark::config_request::Operation) -> &'static str {
+    match &operation.op_type {
+        Some(OpType::Set(_)) => "set",
+        Some(OpType::Get(_)) => "get",
+        Some(OpType::GetWithDefault(_)) => "get_with_default",
+        Some(OpType::GetOption(_)) => "get_option",
+        Some(OpType::GetAll(_)) => "get_all",
+        Some(OpType::Unset(_)) => "unset",
+        Some(OpType::IsModifiable(_)) => "is_modifiable",
+        None => "unknown",
+    }
+}
+
+/// Inject the OpenTelemetry context of the current `tracing` span into the headers,
+/// with the global text map propagator, e.g. the W3C `traceparent` and `tracestate`
+#[cfg(feature = "otel")]
+pub(super) fn inject_trace_context(headers: &mut HeaderMap) {
+    use opentelemetry::propagation::Injector;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+    impl Injector for HeaderInjector<'_> {
+        fn set(&mut self, key: &str, value: String) {
+            let name = HeaderName::from_bytes(key.as_bytes());
+            let value = HeaderValue::from_str(&value);
+
+            if let (Ok(name), Ok(value)) = (name, value) {
+                self.0.insert(name, value);
+            }
+        }
+    }
+
+    let cx = Span::current().context();
+
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&cx, &mut HeaderInjector(headers))
+    });
+}
+
+#[cfg(not(feature = "otel"))]
+pub(super) fn inject_trace_context(_headers: &mut HeaderMap) {}
+
+/// Operation tag carrying the trace id of the current `tracing` span, or `None` if the
+/// span has no valid OpenTelemetry context
+#[cfg(feature = "otel")]
+pub(super) fn trace_id_tag() -> Option<String> {
+    use opentelemetry::trace::TraceContextExt;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let cx = Span::current().context();
+    let span_context = cx.span().span_context().clone();
+
+    span_context
+        .is_valid()
+        .then(|| format!("trace-id-{}", span_context.trace_id()))
+}
+
+#[cfg(not(feature = "otel"))]
+pub(super) fn trace_id_tag() -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_kind() {
+        let root = spark::Plan {
+            op_type: Some(spark::plan::OpType::Root(spark::Relation::default())),
+        };
+
+        let command = spark::Plan {
+            op_type: Some(spark::plan::OpType::Command(spark::Command {
+                command_type: Some(CommandType::SqlCommand(spark::SqlCommand::default())),
+            })),
+        };
+
+        assert_eq!("root", plan_kind(Some(&root)));
+        assert_eq!("sql_command", plan_kind(Some(&command)));
+        assert_eq!("unknown", plan_kind(None));
+    }
+
+    #[tokio::test]
+    async fn test_traced_returns_result() {
+        let span = tracing::info_span!("test", elapsed_ms = tracing::field::Empty);
+
+        let ok = traced(span.clone(), async { Ok::<_, SparkError>(1) }).await;
+        let err = traced(span, async {
+            Err::<i32, _>(SparkError::NotFound("missing".to_string()))
+        })
+        .await;
+
+        assert_eq!(1, ok.unwrap());
+        assert!(matches!(err, Err(SparkError::NotFound(_))));
+    }
+
+    #[cfg(feature = "otel")]
+    #[test]
+    fn test_trace_context() {
+        use opentelemetry::trace::{
+            SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+        };
+        use opentelemetry_sdk::propagation::TraceContextPropagator;
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer());
+        let _subscriber = tracing::subscriber::set_default(subscriber);
+
+        let mut headers = HeaderMap::new();
+        inject_trace_context(&mut headers);
+
+        assert!(headers.is_empty());
+        assert!(trace_id_tag().is_none());
+
+        let span_context = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::from_key_value([("congo", "t61rcWkgMzE")]).unwrap(),
+        );
+
+        let span = tracing::info_span!("query");
+        span.set_parent(opentelemetry::Context::new().with_remote_span_context(span_context));
+        let _span = span.enter();
+
+        inject_trace_context(&mut headers);
+
+        let traceparent = headers["traceparent"].to_str().unwrap();
+
+        assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(traceparent.ends_with("-01"));
+        assert_eq!("congo=t61rcWkgMzE", headers["tracestate"]);
+        assert_eq!(
+            Some("trace-id-4bf92f3577b34da6a3ce929d0e0e4736".to_string()),
+            trace_id_tag()
+        );
+    }
+}
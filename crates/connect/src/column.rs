@@ -21,6 +21,7 @@ use std::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Rem, Sub};
 
 use crate::spark;
 
+use crate::extension::ExpressionExtension;
 use crate::functions::invoke_func;
 use crate::window::WindowSpec;
 
@@ -67,6 +68,13 @@ impl Column {
         Self::from(s.as_str())
     }
 
+    /// Create a column from an [ExpressionExtension] handled by a server plugin
+    pub fn from_extension(extension: &impl ExpressionExtension) -> Self {
+        Self::from(spark::Expression::from(
+            spark::expression::ExprType::Extension(extension.to_any()),
+        ))
+    }
+
     /// Returns the column with a new name
     ///
     /// # Example:
@@ -85,9 +93,8 @@ impl Column {
             metadata: None,
         };
 
-        let expression = spark::Expression {
-            expr_type: Some(spark::expression::ExprType::Alias(Box::new(alias))),
-        };
+        let expression =
+            spark::Expression::from(spark::expression::ExprType::Alias(Box::new(alias)));
 
         Column::from(expression)
     }
@@ -116,9 +123,8 @@ impl Column {
             null_ordering: 1,
         };
 
-        let expression = spark::Expression {
-            expr_type: Some(spark::expression::ExprType::SortOrder(Box::new(asc))),
-        };
+        let expression =
+            spark::Expression::from(spark::expression::ExprType::SortOrder(Box::new(asc)));
 
         Column::from(expression)
     }
@@ -130,9 +136,8 @@ impl Column {
             null_ordering: 2,
         };
 
-        let expression = spark::Expression {
-            expr_type: Some(spark::expression::ExprType::SortOrder(Box::new(asc))),
-        };
+        let expression =
+            spark::Expression::from(spark::expression::ExprType::SortOrder(Box::new(asc)));
 
         Column::from(expression)
     }
@@ -156,9 +161,8 @@ impl Column {
             null_ordering: 1,
         };
 
-        let expression = spark::Expression {
-            expr_type: Some(spark::expression::ExprType::SortOrder(Box::new(asc))),
-        };
+        let expression =
+            spark::Expression::from(spark::expression::ExprType::SortOrder(Box::new(asc)));
 
         Column::from(expression)
     }
@@ -170,9 +174,8 @@ impl Column {
             null_ordering: 2,
         };
 
-        let expression = spark::Expression {
-            expr_type: Some(spark::expression::ExprType::SortOrder(Box::new(asc))),
-        };
+        let expression =
+            spark::Expression::from(spark::expression::ExprType::SortOrder(Box::new(asc)));
 
         Column::from(expression)
     }
@@ -184,34 +187,105 @@ impl Column {
         let mut parent_col = self.expression;
 
         for field in field_names {
-            parent_col = spark::Expression {
-                expr_type: Some(spark::expression::ExprType::UpdateFields(Box::new(
-                    spark::expression::UpdateFields {
-                        struct_expression: Some(Box::new(parent_col)),
-                        field_name: field.as_ref().to_string(),
-                        value_expression: None,
-                    },
-                ))),
-            };
+            parent_col = spark::Expression::from(spark::expression::ExprType::UpdateFields(
+                Box::new(spark::expression::UpdateFields {
+                    struct_expression: Some(Box::new(parent_col)),
+                    field_name: field.as_ref().to_string(),
+                    value_expression: None,
+                }),
+            ));
         }
 
         Column::from(parent_col)
     }
 
     pub fn with_field(self, field_name: &str, col: impl Into<Column>) -> Column {
-        let update_field = spark::Expression {
-            expr_type: Some(spark::expression::ExprType::UpdateFields(Box::new(
-                spark::expression::UpdateFields {
-                    struct_expression: Some(Box::new(self.expression)),
-                    field_name: field_name.to_string(),
-                    value_expression: Some(Box::new(col.into().expression)),
-                },
-            ))),
-        };
+        let update_field = spark::Expression::from(spark::expression::ExprType::UpdateFields(
+            Box::new(spark::expression::UpdateFields {
+                struct_expression: Some(Box::new(self.expression)),
+                field_name: field_name.to_string(),
+                value_expression: Some(Box::new(col.into().expression)),
+            }),
+        ));
 
         Column::from(update_field)
     }
 
+    /// Index-style access that extracts an element of an array by its zero-based position,
+    /// or a value of a map by its key. This is the equivalent of `col[key]` in PySpark
+    ///
+    /// # Example:
+    /// ```rust
+    /// // payload["items"][0]
+    /// df.select([col("payload").get_item("items").get_item(0)]);
+    /// ```
+    pub fn get_item(self, key: impl Into<spark::expression::Literal>) -> Column {
+        self.extract_value(Column::from(key.into()))
+    }
+
+    /// Extracts a field by name from a struct column
+    ///
+    /// # Example:
+    /// ```rust
+    /// df.select([col("event").get_field("user").get_field("id")]);
+    /// ```
+    pub fn get_field(self, name: &str) -> Column {
+        self.extract_value(Column::from(spark::expression::Literal::from(name)))
+    }
+
+    /// Extracts a nested value following a path of struct fields, map keys and array
+    /// positions, relative to the column
+    ///
+    /// Fields are separated by `.`, and array positions or map keys are wrapped in brackets.
+    /// Field names containing `.` can be quoted with backticks, and bracketed keys can be
+    /// quoted with `'` or `"` to keep them as strings.
+    ///
+    /// # Example:
+    /// ```rust
+    /// // equivalent to col("payload").get_item("items").get_item(0).get_field("name")
+    /// df.select([col("payload").get_path("items[0].name")]);
+    ///
+    /// df.select([col("payload").get_path("attributes['user.agent']")]);
+    /// ```
+    pub fn get_path(self, path: &str) -> Column {
+        parse_path(path)
+            .into_iter()
+            .fold(self, |col, key| col.extract_value(Column::from(key)))
+    }
+
+    /// Creates a column from a dotted path, where the first segment is the column name and
+    /// the rest are extracted as with [Column::get_path]
+    ///
+    /// Unlike `col("payload.items")`, which is resolved by the server, the path can contain
+    /// array positions and map keys, e.g. `Column::from_path("payload.items[0].name")`.
+    /// Table qualifiers are not supported and should be used with `col` instead.
+    pub fn from_path(path: &str) -> Column {
+        let mut keys = parse_path(path).into_iter();
+
+        let name = match keys.next().and_then(|key| key.literal_type) {
+            Some(spark::expression::literal::LiteralType::String(name)) => name,
+            Some(spark::expression::literal::LiteralType::Integer(idx)) => idx.to_string(),
+            _ => return Column::from(path),
+        };
+
+        let root = Column::from(format!("`{}`", name.replace('`', "``")));
+
+        keys.fold(root, |col, key| col.extract_value(Column::from(key)))
+    }
+
+    fn extract_value(self, extraction: Column) -> Column {
+        let extract = spark::expression::UnresolvedExtractValue {
+            child: Some(Box::new(self.expression)),
+            extraction: Some(Box::new(extraction.expression)),
+        };
+
+        let expression = spark::Expression::from(
+            spark::expression::ExprType::UnresolvedExtractValue(Box::new(extract)),
+        );
+
+        Column::from(expression)
+    }
+
     pub fn substr(self, start_pos: impl Into<Column>, length: impl Into<Column>) -> Column {
         invoke_func("substr", vec![self, start_pos.into(), length.into()])
     }
@@ -241,11 +315,11 @@ impl Column {
         let cast = spark::expression::Cast {
             expr: Some(Box::new(self.expression)),
             cast_to_type: Some(to_type.into()),
+            #[cfg(feature = "spark-4")]
+            eval_mode: spark::expression::cast::EvalMode::Unspecified.into(),
         };
 
-        let expression = spark::Expression {
-            expr_type: Some(spark::expression::ExprType::Cast(Box::new(cast))),
-        };
+        let expression = spark::Expression::from(spark::expression::ExprType::Cast(Box::new(cast)));
 
         Column::from(expression)
     }
@@ -314,6 +388,43 @@ impl Column {
         invoke_func("==", vec![self, other.into()])
     }
 
+    /// Inequality comparison, the negation of [Column::eq]
+    pub fn ne(self, other: impl Into<Column>) -> Column {
+        invoke_func("not", vec![self.eq(other)])
+    }
+
+    /// Null-safe equality comparison `<=>`, which returns `true` if both sides are null,
+    /// and `false` if only one side is null
+    pub fn eq_null_safe(self, other: impl Into<Column>) -> Column {
+        invoke_func("<=>", vec![self, other.into()])
+    }
+
+    /// Greater than comparison. Cannot overload the '>' and return something other
+    /// than a bool
+    ///
+    /// # Example:
+    /// ```rust
+    /// df.filter(col("age").gt(lit(30)));
+    /// ```
+    pub fn gt(self, other: impl Into<Column>) -> Column {
+        invoke_func(">", vec![self, other.into()])
+    }
+
+    /// Greater than or equal comparison
+    pub fn ge(self, other: impl Into<Column>) -> Column {
+        invoke_func(">=", vec![self, other.into()])
+    }
+
+    /// Less than comparison
+    pub fn lt(self, other: impl Into<Column>) -> Column {
+        invoke_func("<", vec![self, other.into()])
+    }
+
+    /// Less than or equal comparison
+    pub fn le(self, other: impl Into<Column>) -> Column {
+        invoke_func("<=", vec![self, other.into()])
+    }
+
     /// Logical AND comparion. Cannot overload the '&&' and return something other
     /// than a bool
     pub fn and(self, other: impl Into<Column>) -> Column {
@@ -363,9 +474,8 @@ impl Column {
             frame_spec: window.frame_spec,
         };
 
-        let expression = spark::Expression {
-            expr_type: Some(spark::expression::ExprType::Window(Box::new(window_expr))),
-        };
+        let expression =
+            spark::Expression::from(spark::expression::ExprType::Window(Box::new(window_expr)));
 
         Column::from(expression)
     }
@@ -381,9 +491,9 @@ impl From<spark::Expression> for Column {
 impl From<spark::expression::Literal> for Column {
     /// Used for creating columns from a [spark::Expression]
     fn from(expression: spark::expression::Literal) -> Self {
-        Self::from(spark::Expression {
-            expr_type: Some(spark::expression::ExprType::Literal(expression)),
-        })
+        Self::from(spark::Expression::from(
+            spark::expression::ExprType::Literal(expression),
+        ))
     }
 }
 
@@ -404,28 +514,28 @@ impl From<&str> for Column {
     /// Otherwise, the value is created as an unresolved attribute
     fn from(value: &str) -> Self {
         let expression = match value {
-            "*" => spark::Expression {
-                expr_type: Some(spark::expression::ExprType::UnresolvedStar(
-                    spark::expression::UnresolvedStar {
-                        unparsed_target: None,
-                    },
-                )),
-            },
-            value if value.ends_with(".*") => spark::Expression {
-                expr_type: Some(spark::expression::ExprType::UnresolvedStar(
-                    spark::expression::UnresolvedStar {
-                        unparsed_target: Some(value.to_string()),
-                    },
-                )),
-            },
-            _ => spark::Expression {
-                expr_type: Some(spark::expression::ExprType::UnresolvedAttribute(
-                    spark::expression::UnresolvedAttribute {
-                        unparsed_identifier: value.to_string(),
-                        plan_id: None,
-                    },
-                )),
-            },
+            "*" => spark::Expression::from(spark::expression::ExprType::UnresolvedStar(
+                spark::expression::UnresolvedStar {
+                    unparsed_target: None,
+                    #[cfg(feature = "spark-4")]
+                    plan_id: None,
+                },
+            )),
+            value if value.ends_with(".*") => spark::Expression::from(
+                spark::expression::ExprType::UnresolvedStar(spark::expression::UnresolvedStar {
+                    unparsed_target: Some(value.to_string()),
+                    #[cfg(feature = "spark-4")]
+                    plan_id: None,
+                }),
+            ),
+            _ => spark::Expression::from(spark::expression::ExprType::UnresolvedAttribute(
+                spark::expression::UnresolvedAttribute {
+                    unparsed_identifier: value.to_string(),
+                    plan_id: None,
+                    #[cfg(feature = "spark-4")]
+                    is_metadata_column: None,
+                },
+            )),
         };
 
         Column::from(expression)
@@ -511,3 +621,217 @@ impl Not for Column {
         invoke_func("not", vec![self])
     }
 }
+
+/// Splits a path like ``a.`b.c`[0]['d']`` into its keys, where field names and
+/// quoted or non-numeric bracketed keys are strings, and numeric bracketed keys are positions
+fn parse_path(path: &str) -> Vec<spark::expression::Literal> {
+    let mut keys = vec![];
+    let mut field = String::new();
+    let mut chars = path.chars().peekable();
+
+    let flush = |field: &mut String, keys: &mut Vec<spark::expression::Literal>| {
+        if !field.is_empty() {
+            keys.push(spark::expression::Literal::from(std::mem::take(field)));
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => flush(&mut field, &mut keys),
+            '`' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '`' if chars.peek() == Some(&'`') => {
+                            chars.next();
+                            field.push('`');
+                        }
+                        '`' => break,
+                        c => field.push(c),
+                    }
+                }
+            }
+            '[' => {
+                flush(&mut field, &mut keys);
+
+                let mut key = String::new();
+                let mut quote = None;
+                let mut quoted = false;
+
+                for c in chars.by_ref() {
+                    match (c, quote) {
+                        (']', None) => break,
+                        ('\'' | '"', None) if !quoted && key.trim().is_empty() => {
+                            key.clear();
+                            quote = Some(c);
+                            quoted = true;
+                        }
+                        (c, Some(q)) if c == q => quote = None,
+                        (c, Some(_)) => key.push(c),
+                        (c, None) if !quoted => key.push(c),
+                        _ => {}
+                    }
+                }
+
+                match key.trim().parse::<i32>() {
+                    Ok(idx) if !quoted => keys.push(spark::expression::Literal::from(idx)),
+                    _ if quoted => keys.push(spark::expression::Literal::from(key)),
+                    _ => keys.push(spark::expression::Literal::from(key.trim())),
+                }
+            }
+            c => field.push(c),
+        }
+    }
+
+    flush(&mut field, &mut keys);
+
+    keys
+}
+
+/// Arithmetic and bitwise operators with a Rust value on the right-hand side,
+/// e.g. `col("a") + 1`, which is translated into a literal
+macro_rules! impl_literal_op {
+    ($trait:ident, $method:ident, $op:expr) => {
+        impl<T> $trait<T> for Column
+        where
+            T: Into<spark::expression::Literal>,
+        {
+            type Output = Column;
+
+            fn $method(self, other: T) -> Column {
+                invoke_func($op, vec![self, Column::from(other.into())])
+            }
+        }
+    };
+}
+
+impl_literal_op!(Add, add, "+");
+impl_literal_op!(Sub, sub, "-");
+impl_literal_op!(Mul, mul, "*");
+impl_literal_op!(Div, div, "/");
+impl_literal_op!(Rem, rem, "%");
+impl_literal_op!(BitOr, bitor, "|");
+impl_literal_op!(BitAnd, bitand, "&");
+impl_literal_op!(BitXor, bitxor, "^");
+
+/// Arithmetic operators with a Rust value on the left-hand side, e.g. `1 + col("a")`
+macro_rules! impl_reverse_literal_op {
+    ($type:ty, [$(($trait:ident, $method:ident, $op:expr)),*]) => {
+        $(
+            impl $trait<Column> for $type {
+                type Output = Column;
+
+                fn $method(self, other: Column) -> Column {
+                    invoke_func($op, vec![Column::from(spark::expression::Literal::from(self)), other])
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_reverse_literal_ops {
+    ($($type:ty),*) => {
+        $(
+            impl_reverse_literal_op!(
+                $type,
+                [(Add, add, "+"), (Sub, sub, "-"), (Mul, mul, "*"), (Div, div, "/"), (Rem, rem, "%")]
+            );
+        )*
+    };
+}
+
+impl_reverse_literal_ops!(i16, i32, i64, f32, f64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::functions::{col, lit};
+
+    /// Name and arguments of the function called by the column
+    fn function(col: Column) -> (String, Vec<spark::Expression>) {
+        match col.expression.expr_type {
+            Some(spark::expression::ExprType::UnresolvedFunction(func)) => {
+                (func.function_name, func.arguments)
+            }
+            other => panic!("expected a function, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_column_comparisons() {
+        assert_eq!(">", function(col("age").gt(lit(30))).0);
+        assert_eq!(">=", function(col("age").ge(lit(30))).0);
+        assert_eq!("<", function(col("age").lt(lit(30))).0);
+        assert_eq!("<=", function(col("age").le(lit(30))).0);
+        assert_eq!("<=>", function(col("age").eq_null_safe(col("other"))).0);
+
+        let (name, args) = function(col("age").ne(lit(30)));
+
+        assert_eq!("not", name);
+        assert_eq!("==", function(Column::from(args[0].clone())).0);
+    }
+
+    #[test]
+    fn test_column_literal_ops() {
+        let (name, args) = function(col("a") + 1);
+
+        assert_eq!("+", name);
+        assert_eq!(lit(1).expression, args[1]);
+
+        let (name, args) = function(col("b") * 2.5);
+
+        assert_eq!("*", name);
+        assert_eq!(lit(2.5).expression, args[1]);
+
+        let (name, args) = function(10_i64 - col("c"));
+
+        assert_eq!("-", name);
+        assert_eq!(lit(10_i64).expression, args[0]);
+        assert_eq!(col("c").expression, args[1]);
+    }
+
+    #[test]
+    fn test_column_get_item_and_field() {
+        let expected = col("payload")
+            .get_item("items")
+            .get_item(0)
+            .get_field("name");
+
+        let extract = match &expected.expression.expr_type {
+            Some(spark::expression::ExprType::UnresolvedExtractValue(extract)) => extract,
+            other => panic!("expected an extract value, found {:?}", other),
+        };
+
+        assert_eq!(Some(Box::new(lit("name").expression)), extract.extraction);
+
+        assert_eq!(
+            expected.expression,
+            col("payload").get_path("items[0].name").expression
+        );
+
+        assert_eq!(
+            col("`payload`").get_item("items").get_item(0).expression,
+            Column::from_path("payload.items[0]").expression
+        );
+    }
+
+    #[test]
+    fn test_column_get_path_quoting() {
+        assert_eq!(
+            col("c")
+                .get_field("a.b")
+                .get_item("user.agent")
+                .get_item("0")
+                .get_item(-1)
+                .expression,
+            col("c")
+                .get_path("`a.b`[\"user.agent\"]['0'][ -1 ]")
+                .expression
+        );
+
+        assert_eq!(
+            col("c").get_field("x`y").get_item("k").expression,
+            col("c").get_path("`x``y`.[k]").expression
+        );
+    }
+}
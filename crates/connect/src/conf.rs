@@ -64,6 +64,8 @@ impl RunTimeConfig {
                 key: key.into(),
                 value: Some(value.into()),
             }],
+            #[cfg(feature = "spark-4")]
+            silent: None,
         });
         let operation = spark::config_request::Operation {
             op_type: Some(op_type),
@@ -17,10 +17,16 @@
 
 //! DataFrame representation for Spark Connection
 
+use std::time::Duration;
+
+use crate::client::{RecordBatchStream, RecordBatchStreamReader};
 use crate::column::Column;
 use crate::errors::SparkError;
 use crate::expressions::{ToFilterExpr, VecExpression};
+use crate::extension::RelationExtension;
 use crate::group::GroupedData;
+use crate::metrics::QueryMetrics;
+use crate::operation::OperationHandle;
 use crate::plan::LogicalPlanBuilder;
 use crate::session::SparkSession;
 use crate::storage;
@@ -69,7 +75,7 @@ use polars_arrow;
 ///
 /// let data = RecordBatch::try_from_iter(vec![("name", name), ("age", age)])?
 ///
-/// let df = spark.create_dataframe(&data).await?
+/// let df = spark.create_dataframe(&data)?
 /// ```
 ///
 /// ## sql
@@ -111,6 +117,16 @@ impl DataFrame {
         }
     }
 
+    /// Create a [DataFrame] from a [RelationExtension] handled by a server plugin
+    pub fn from_extension(
+        spark_session: SparkSession,
+        extension: &impl RelationExtension,
+    ) -> DataFrame {
+        let relation = spark::relation::RelType::Extension(extension.to_any());
+
+        DataFrame::new(spark_session, LogicalPlanBuilder::from(relation))
+    }
+
     fn check_same_session(&self, other: &DataFrame) -> Result<(), SparkError> {
         if self.spark_session.session_id() != other.spark_session.session_id() {
             return Err(SparkError::SessionNotSameException(
@@ -181,14 +197,12 @@ impl DataFrame {
 
     /// Selects column based on the column name specified as a regex and returns it as [Column].
     pub fn col_regex(self, col_name: &str) -> Column {
-        let expr = spark::Expression {
-            expr_type: Some(spark::expression::ExprType::UnresolvedRegex(
-                spark::expression::UnresolvedRegex {
-                    col_name: col_name.to_string(),
-                    plan_id: Some(self.plan.plan_id()),
-                },
-            )),
-        };
+        let expr = spark::Expression::from(spark::expression::ExprType::UnresolvedRegex(
+            spark::expression::UnresolvedRegex {
+                col_name: col_name.to_string(),
+                plan_id: Some(self.plan.plan_id()),
+            },
+        ));
         Column::from(expr)
     }
 
@@ -206,6 +220,77 @@ impl DataFrame {
         self.spark_session.client().to_arrow(plan).await
     }
 
+    /// Returns all records as a [RecordBatch] together with the [QueryMetrics] of
+    /// each operator in the executed physical plan
+    ///
+    /// # Example:
+    ///
+    /// ```rust
+    /// async {
+    ///     let (records, metrics) = df.collect_with_metrics().await?;
+    ///
+    ///     let root = metrics.root().unwrap();
+    /// }
+    /// ```
+    pub async fn collect_with_metrics(self) -> Result<(RecordBatch, QueryMetrics), SparkError> {
+        let plan = self.plan.plan_root();
+        self.spark_session
+            .client()
+            .to_arrow_with_metrics(plan)
+            .await
+    }
+
+    /// Returns all records as a [RecordBatchStream] which yields each [RecordBatch]
+    /// as it is received from the server, instead of buffering the full result
+    ///
+    /// # Example:
+    ///
+    /// ```rust
+    /// async {
+    ///     let mut stream = df.collect_stream().await?;
+    ///
+    ///     while let Some(batch) = stream.next().await {
+    ///         println!("{}", batch?.num_rows());
+    ///     }
+    /// }
+    /// ```
+    pub async fn collect_stream(self) -> Result<RecordBatchStream, SparkError> {
+        let plan = self.plan.plan_root();
+        self.spark_session.client().to_arrow_stream(plan).await
+    }
+
+    /// Submit the [DataFrame] for execution without waiting for the results.
+    ///
+    /// Returns an [OperationHandle] to wait for, reattach to, or interrupt the operation
+    /// running on the server.
+    ///
+    /// # Example:
+    ///
+    /// ```rust
+    /// async {
+    ///     let mut handle = df.submit().await?;
+    ///
+    ///     let records = handle.wait().await?;
+    /// }
+    /// ```
+    pub async fn submit(self) -> Result<OperationHandle, SparkError> {
+        let plan = self.plan.plan_root();
+
+        let mut client = self.spark_session.client();
+        client.set_reattachable_execute(true)?;
+
+        let operation_id = client.submit(plan).await?;
+
+        Ok(OperationHandle::new(client, operation_id))
+    }
+
+    /// Returns a blocking [RecordBatchStreamReader] that iterates over all records
+    ///
+    /// The reader must be consumed outside of the async runtime, e.g. within `tokio::task::spawn_blocking`
+    pub async fn to_local_iterator(self) -> Result<RecordBatchStreamReader, SparkError> {
+        self.collect_stream().await?.into_reader().await
+    }
+
     /// Retrieves the names of all columns in the [DataFrame] as a `Vec<String>`.
     /// The order of the column names in the list reflects their order in the [DataFrame].
     pub async fn columns(self) -> Result<Vec<String>, SparkError> {
@@ -509,7 +594,7 @@ impl DataFrame {
                 explain_mode: explain_mode.into(),
             });
 
-        let mut client = self.spark_session.client();
+        let client = self.spark_session.client();
         let explain = client.analyze(analyze).await?.explain()?;
 
         println!("{}", explain);
@@ -606,7 +691,7 @@ impl DataFrame {
             },
         );
 
-        let mut client = self.spark_session.client();
+        let client = self.spark_session.client();
 
         client.analyze(input_files).await?.input_files()
     }
@@ -649,7 +734,7 @@ impl DataFrame {
                 plan: Some(self.plan.plan_root()),
             });
 
-        let mut client = self.spark_session.client();
+        let client = self.spark_session.client();
 
         client.analyze(is_local).await?.is_local()
     }
@@ -662,7 +747,7 @@ impl DataFrame {
             },
         );
 
-        let mut client = self.spark_session.client();
+        let client = self.spark_session.client();
 
         client.analyze(is_streaming).await?.is_streaming()
     }
@@ -694,6 +779,22 @@ impl DataFrame {
         }
     }
 
+    /// Lateral joins with another [DataFrame], using the given join expression.
+    #[cfg(feature = "spark-4")]
+    pub fn lateral_join<T: Into<spark::Expression>>(
+        self,
+        other: DataFrame,
+        on: Option<T>,
+        how: JoinType,
+    ) -> DataFrame {
+        let plan = self.plan.lateral_join(other.plan, on, how);
+
+        DataFrame {
+            spark_session: self.spark_session,
+            plan,
+        }
+    }
+
     /// Limits the result count o thte number specified and returns a new [DataFrame]
     ///
     /// # Example:
@@ -730,7 +831,39 @@ impl DataFrame {
         DataFrameNaFunctions::new(self)
     }
 
-    // !TODO observe
+    /// Define named metrics to observe on the [DataFrame], which are computed while an
+    /// action runs on it, e.g. `collect` or `write`
+    ///
+    /// The observed values are retrieved by name with `SparkSession::observed_metrics`
+    /// once the action completes, or from `StreamingQuery::observed_metrics` for a
+    /// streaming query.
+    ///
+    /// # Example:
+    ///
+    /// ```rust
+    /// async {
+    ///     let df = df.observe(
+    ///         "dq",
+    ///         [count(lit(1)).alias("rows"), max(col("age")).alias("max_age")],
+    ///     );
+    ///
+    ///     df.collect().await?;
+    ///
+    ///     let observed = spark.observed_metrics();
+    ///     let rows = observed["dq"].values[0].as_i64();
+    /// }
+    /// ```
+    pub fn observe<I>(self, name: &str, exprs: I) -> DataFrame
+    where
+        I: IntoIterator<Item: Into<Column>>,
+    {
+        let plan = self.plan.observe(name, exprs);
+
+        DataFrame {
+            spark_session: self.spark_session,
+            plan,
+        }
+    }
 
     /// Returns a new [DataFrame] by skiping the first n rows
     pub fn offset(self, num: i32) -> DataFrame {
@@ -763,7 +896,7 @@ impl DataFrame {
                 storage_level: Some(storage_level.into()),
             });
 
-        let mut client = self.spark_session.clone().client();
+        let client = self.spark_session.clone().client();
 
         client.analyze(analyze).await.unwrap();
 
@@ -784,7 +917,7 @@ impl DataFrame {
             },
         );
 
-        let mut client = self.spark_session.client();
+        let client = self.spark_session.client();
 
         client.analyze(tree_string).await?.tree_string()
     }
@@ -900,7 +1033,7 @@ impl DataFrame {
             },
         );
 
-        let mut client = self.spark_session.client();
+        let client = self.spark_session.client();
 
         client.analyze(same_semantics).await?.same_semantics()
     }
@@ -949,7 +1082,7 @@ impl DataFrame {
                 plan: Some(plan),
             });
 
-        let mut client = self.spark_session.client();
+        let client = self.spark_session.client();
 
         client.analyze(schema).await?.schema()
     }
@@ -1008,7 +1141,7 @@ impl DataFrame {
             spark::analyze_plan_request::SemanticHash { plan },
         );
 
-        let mut client = self.spark_session.client();
+        let client = self.spark_session.client();
 
         client.analyze(semantic_hash).await?.semantic_hash()
     }
@@ -1085,7 +1218,7 @@ impl DataFrame {
             },
         );
 
-        let mut client = self.spark_session.client();
+        let client = self.spark_session.client();
         let storage = client.analyze(storage_level).await?.get_storage_level();
 
         Ok(storage?.into())
@@ -1235,6 +1368,24 @@ impl DataFrame {
         func(self)
     }
 
+    /// Transposes a [DataFrame] such that the values in the index column become the new
+    /// columns and the remaining columns become the rows.
+    ///
+    /// The first column is used as the index column when `index_column` is `None`
+    #[cfg(feature = "spark-4")]
+    pub fn transpose<T: Into<Column>>(self, index_column: Option<T>) -> DataFrame {
+        let index_columns = index_column
+            .map(|col| vec![col.into().into()])
+            .unwrap_or_default();
+
+        let plan = self.plan.transpose(index_columns);
+
+        DataFrame {
+            spark_session: self.spark_session,
+            plan,
+        }
+    }
+
     /// Return a new [DataFrame] containing the union of rows in this and another [DataFrame].
     pub fn union(self, other: DataFrame) -> DataFrame {
         self.check_same_session(&other).unwrap();
@@ -1280,7 +1431,7 @@ impl DataFrame {
             },
         );
 
-        let mut client = self.spark_session.clone().client();
+        let client = self.spark_session.clone().client();
 
         client.analyze(unpersist).await.unwrap();
 
@@ -1379,6 +1530,17 @@ impl DataFrame {
         }
     }
 
+    /// Returns a new [DataFrame] whose actions must complete within `timeout`.
+    ///
+    /// An action that exceeds the deadline is interrupted on the server and returns
+    /// [SparkError::DeadlineExceeded].
+    pub fn with_timeout(self, timeout: Duration) -> DataFrame {
+        DataFrame {
+            spark_session: Box::new(self.spark_session.with_timeout(timeout)),
+            plan: self.plan,
+        }
+    }
+
     /// Defines an event time watermark for this [DataFrame].
     pub fn with_watermark(self, event_time: &str, delay_threshold: &str) -> DataFrame {
         let plan = self.plan.with_watermark(event_time, delay_threshold);
@@ -1622,6 +1784,233 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_df_collect_stream() -> Result<(), SparkError> {
+        use futures::StreamExt;
+
+        let spark = setup().await;
+
+        let mut stream = spark
+            .range(None, 100_000, 1, Some(8))
+            .collect_stream()
+            .await?;
+
+        let mut total = 0;
+        while let Some(batch) = stream.next().await {
+            total += batch?.num_rows();
+        }
+
+        assert_eq!(100_000, total);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_df_collect_with_metrics() -> Result<(), SparkError> {
+        let spark = setup().await;
+
+        let (records, metrics) = spark
+            .range(None, 100, 1, Some(1))
+            .collect_with_metrics()
+            .await?;
+
+        assert_eq!(100, records.num_rows());
+        assert!(metrics.root().is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_df_collect_with_metrics_mock() -> Result<(), SparkError> {
+        use crate::testing::{MockResponse, MockSparkServer};
+        use spark::execute_plan_response::{metrics, Metrics};
+
+        let server = MockSparkServer::start().await?;
+
+        let rows = metrics::MetricValue {
+            name: "number of output rows".to_string(),
+            value: 3,
+            metric_type: "sum".to_string(),
+        };
+
+        server.push_execute([
+            MockResponse::Batch(mock_data()),
+            MockResponse::Metrics(Metrics {
+                metrics: vec![metrics::MetricObject {
+                    name: "LocalTableScan".to_string(),
+                    plan_id: 0,
+                    parent: -1,
+                    execution_metrics: HashMap::from([("numOutputRows".to_string(), rows)]),
+                }],
+            }),
+        ]);
+
+        let spark = server.session().await?;
+
+        let (records, metrics) = spark.range(None, 3, 1, None).collect_with_metrics().await?;
+
+        let root = metrics.root().unwrap();
+
+        assert_eq!(3, records.num_rows());
+        assert_eq!("LocalTableScan", root.name);
+        assert_eq!(3, root.get("numOutputRows").unwrap().value);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_df_collect_with_metrics_empty_mock() -> Result<(), SparkError> {
+        use crate::testing::MockSparkServer;
+
+        let server = MockSparkServer::start().await?;
+        server.push_execute([]);
+
+        let spark = server.session().await?;
+
+        let (records, metrics) = spark.range(None, 0, 1, None).collect_with_metrics().await?;
+
+        assert_eq!(0, records.num_rows());
+        assert!(metrics.root().is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_df_observe() -> Result<(), SparkError> {
+        let spark = setup().await;
+
+        let df = spark.create_dataframe(&mock_data())?.observe(
+            "dq",
+            [
+                count(lit(1)).alias("rows"),
+                sum(col("age")).alias("total_age"),
+            ],
+        );
+
+        df.collect().await?;
+
+        let observed = spark.observed_metrics();
+        let metrics = observed.get("dq").unwrap();
+
+        assert_eq!(Some(3), metrics.values[0].as_i64());
+        assert_eq!(Some(53), metrics.values[1].as_i64());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_df_observe_mock() -> Result<(), SparkError> {
+        use crate::metrics::ObservedValue;
+        use crate::testing::{MockResponse, MockSparkServer};
+        use spark::expression::{literal::LiteralType, Literal};
+
+        let server = MockSparkServer::start().await?;
+
+        server.push_execute([
+            MockResponse::Batch(mock_data()),
+            MockResponse::ObservedMetrics(spark::execute_plan_response::ObservedMetrics {
+                name: "dq".to_string(),
+                values: vec![Literal::from(LiteralType::Long(3))],
+                #[cfg(feature = "spark-4")]
+                keys: vec!["rows".to_string()],
+                #[cfg(feature = "spark-4")]
+                plan_id: 0,
+                #[cfg(feature = "spark-4")]
+                root_error_idx: None,
+                #[cfg(feature = "spark-4")]
+                errors: vec![],
+            }),
+        ]);
+
+        let spark = server.session().await?;
+
+        spark
+            .range(None, 3, 1, None)
+            .observe("dq", [count(lit(1)).alias("rows")])
+            .collect()
+            .await?;
+
+        let plan = server.requests().execute[0].plan.clone().unwrap();
+
+        let Some(spark::plan::OpType::Root(relation)) = plan.op_type else {
+            panic!("expected a relation plan");
+        };
+
+        let Some(RelType::CollectMetrics(collect_metrics)) = relation.rel_type else {
+            panic!("expected a CollectMetrics relation");
+        };
+
+        assert_eq!("dq", collect_metrics.name);
+        assert_eq!(1, collect_metrics.metrics.len());
+
+        let observed = spark.observed_metrics();
+
+        assert_eq!(vec![ObservedValue::Long(3)], observed["dq"].values);
+        #[cfg(feature = "spark-4")]
+        assert_eq!(vec!["rows".to_string()], observed["dq"].keys);
+        Ok(())
+    }
+
+    #[cfg(feature = "spark-4")]
+    #[tokio::test]
+    async fn test_df_transpose_mock() -> Result<(), SparkError> {
+        use crate::testing::{MockResponse, MockSparkServer};
+
+        let server = MockSparkServer::start().await?;
+
+        server.push_execute([MockResponse::Batch(mock_data())]);
+
+        let spark = server.session().await?;
+
+        spark
+            .table_function("range", [lit(3)])
+            .transpose(Some(col("id")))
+            .collect()
+            .await?;
+
+        let plan = server.requests().execute[0].plan.clone().unwrap();
+
+        let Some(spark::plan::OpType::Root(relation)) = plan.op_type else {
+            panic!("expected a relation plan");
+        };
+
+        let Some(RelType::Transpose(transpose)) = relation.rel_type else {
+            panic!("expected a Transpose relation");
+        };
+
+        assert_eq!(1, transpose.index_columns.len());
+
+        let Some(RelType::UnresolvedTableValuedFunction(tvf)) =
+            transpose.input.and_then(|input| input.rel_type)
+        else {
+            panic!("expected a table-valued function");
+        };
+
+        assert_eq!("range", tvf.function_name);
+        assert_eq!(1, tvf.arguments.len());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_df_to_local_iterator() -> Result<(), SparkError> {
+        use arrow::record_batch::RecordBatchReader;
+
+        let spark = setup().await;
+
+        let reader = spark
+            .range(None, 1000, 1, Some(4))
+            .to_local_iterator()
+            .await?;
+
+        assert_eq!("id", reader.schema().field(0).name());
+
+        let total = tokio::task::spawn_blocking(move || {
+            reader
+                .map(|batch| batch.map(|b| b.num_rows()))
+                .sum::<Result<usize, _>>()
+        })
+        .await
+        .unwrap()?;
+
+        assert_eq!(1000, total);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_df_columns() -> Result<(), SparkError> {
         let spark = setup().await;
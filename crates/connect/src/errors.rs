@@ -17,6 +17,7 @@
 
 //! Defines a [SparkError] for representing failures in various Spark operations.
 //! Most of these are wrappers for tonic or arrow error messages
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::Debug;
 use std::io::Write;
@@ -24,8 +25,12 @@ use std::io::Write;
 use arrow::error::ArrowError;
 use thiserror::Error;
 
+use prost::Message;
 use tonic::Code;
 
+#[cfg(feature = "spark-4")]
+use crate::spark;
+
 #[cfg(feature = "datafusion")]
 use datafusion::error::DataFusionError;
 #[cfg(feature = "polars")]
@@ -61,6 +66,9 @@ pub enum SparkError {
     #[error("Failed Precondition: {0}")]
     FailedPrecondition(String),
 
+    #[error("Internal: {0}")]
+    Internal(String),
+
     #[error("Invalid Connection Url: {0}")]
     InvalidConnectionUrl(String),
 
@@ -102,6 +110,28 @@ pub enum SparkError {
 
     #[error("Out of Range: {0}")]
     OutOfRange(String),
+
+    /// An exception raised by the Spark Connect server, decoded from the
+    /// `google.rpc.ErrorInfo` attached to the gRPC status
+    #[error("Spark Server Error: {message}")]
+    Server {
+        /// gRPC status code returned with the error
+        code: Code,
+        /// Error message returned by the server
+        message: String,
+        /// Spark error class, e.g. `TABLE_OR_VIEW_NOT_FOUND`
+        error_class: Option<String>,
+        /// SQLSTATE of the error, if the server provided one
+        sql_state: Option<String>,
+        /// Parameters used to construct the error message
+        message_parameters: Box<HashMap<String, String>>,
+        /// JVM stacktrace, only returned when `spark.sql.pyspark.jvmStacktrace.enabled` is set
+        server_stacktrace: Option<String>,
+        /// Id of the error on the server, used to fetch its full details. Boxed like the
+        /// message parameters, to keep the size of `SparkError` small
+        #[cfg(feature = "spark-4")]
+        error_id: Option<Box<str>>,
+    },
 }
 
 impl SparkError {
@@ -109,6 +139,155 @@ impl SparkError {
     pub fn from_external_error(error: Box<dyn Error + Send + Sync>) -> Self {
         Self::ExternalError(error)
     }
+
+    /// The Spark error class of a server error, e.g. `TABLE_OR_VIEW_NOT_FOUND`
+    pub fn error_class(&self) -> Option<&str> {
+        match self {
+            SparkError::Server { error_class, .. } => error_class.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The SQLSTATE of a server error
+    pub fn sql_state(&self) -> Option<&str> {
+        match self {
+            SparkError::Server { sql_state, .. } => sql_state.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// `google.rpc.Status` message attached to the details of a [tonic::Status]
+#[derive(Clone, PartialEq, Message)]
+struct RpcStatus {
+    #[prost(int32, tag = "1")]
+    code: i32,
+    #[prost(string, tag = "2")]
+    message: String,
+    #[prost(message, repeated, tag = "3")]
+    details: Vec<prost_types::Any>,
+}
+
+/// `google.rpc.ErrorInfo` message used by the Spark Connect server to describe an exception
+#[derive(Clone, PartialEq, Message)]
+struct ErrorInfo {
+    #[prost(string, tag = "1")]
+    reason: String,
+    #[prost(string, tag = "2")]
+    domain: String,
+    #[prost(map = "string, string", tag = "3")]
+    metadata: HashMap<String, String>,
+}
+
+const ERROR_INFO_TYPE_URL: &str = "type.googleapis.com/google.rpc.ErrorInfo";
+
+impl SparkError {
+    /// Attempt to decode a [SparkError::Server] from the `google.rpc.ErrorInfo`
+    /// contained in the status details
+    fn from_error_info(status: &tonic::Status) -> Option<Self> {
+        let rpc_status = RpcStatus::decode(status.details()).ok()?;
+
+        let info = rpc_status
+            .details
+            .iter()
+            .find(|any| any.type_url == ERROR_INFO_TYPE_URL)
+            .and_then(|any| ErrorInfo::decode(any.value.as_slice()).ok())?;
+
+        let mut metadata = info.metadata;
+
+        let message_parameters = metadata
+            .remove("messageParameters")
+            .and_then(|val| serde_json::from_str(&val).ok())
+            .unwrap_or_default();
+
+        Some(SparkError::Server {
+            code: status.code(),
+            message: status.message().to_string(),
+            error_class: metadata.remove("errorClass"),
+            sql_state: metadata.remove("sqlState"),
+            message_parameters,
+            server_stacktrace: metadata.remove("stackTrace"),
+            #[cfg(feature = "spark-4")]
+            error_id: metadata.remove("errorId").map(String::into_boxed_str),
+        })
+    }
+}
+
+#[cfg(feature = "spark-4")]
+impl SparkError {
+    /// Id of a server error whose details are cached on the server
+    pub(crate) fn error_id(&self) -> Option<&str> {
+        match self {
+            SparkError::Server { error_id, .. } => error_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Replace the truncated message and metadata of a server error with the
+    /// details of its root error returned by `FetchErrorDetails`
+    pub(crate) fn with_details(self, details: spark::FetchErrorDetailsResponse) -> Self {
+        let SparkError::Server {
+            code,
+            message,
+            error_class,
+            sql_state,
+            message_parameters,
+            server_stacktrace,
+            error_id,
+        } = self
+        else {
+            return self;
+        };
+
+        let Some(root) = details
+            .root_error_idx
+            .and_then(|idx| details.errors.get(idx as usize))
+        else {
+            return SparkError::Server {
+                code,
+                message,
+                error_class,
+                sql_state,
+                message_parameters,
+                server_stacktrace,
+                error_id,
+            };
+        };
+
+        let throwable = root.spark_throwable.clone().unwrap_or_default();
+
+        let stacktrace = root
+            .stack_trace
+            .iter()
+            .map(|elem| {
+                format!(
+                    "\tat {}.{}({}:{})",
+                    elem.declaring_class,
+                    elem.method_name,
+                    elem.file_name.as_deref().unwrap_or("Unknown Source"),
+                    elem.line_number
+                )
+            })
+            .collect::<Vec<_>>();
+
+        SparkError::Server {
+            code,
+            message: root.message.clone(),
+            error_class: throwable.error_class.or(error_class),
+            sql_state: throwable.sql_state.or(sql_state),
+            message_parameters: if throwable.message_parameters.is_empty() {
+                message_parameters
+            } else {
+                Box::new(throwable.message_parameters)
+            },
+            server_stacktrace: if stacktrace.is_empty() {
+                server_stacktrace
+            } else {
+                Some(stacktrace.join("\n"))
+            },
+            error_id,
+        }
+    }
 }
 
 impl From<std::io::Error> for SparkError {
@@ -131,17 +310,21 @@ impl From<std::string::FromUtf8Error> for SparkError {
 
 impl From<tonic::Status> for SparkError {
     fn from(status: tonic::Status) -> Self {
+        if let Some(err) = SparkError::from_error_info(&status) {
+            return err;
+        }
+
         match status.code() {
             Code::Ok => SparkError::AnalysisException(status.message().to_string()),
             Code::Unknown => SparkError::Unknown(status.message().to_string()),
             Code::Aborted => SparkError::Aborted(status.message().to_string()),
             Code::NotFound => SparkError::NotFound(status.message().to_string()),
-            Code::Internal => SparkError::AnalysisException(status.message().to_string()),
+            Code::Internal => SparkError::Internal(status.message().to_string()),
             Code::DataLoss => SparkError::DataLoss(status.message().to_string()),
             Code::Cancelled => SparkError::Cancelled(status.message().to_string()),
             Code::OutOfRange => SparkError::OutOfRange(status.message().to_string()),
             Code::Unavailable => SparkError::Unavailable(status.message().to_string()),
-            Code::AlreadyExists => SparkError::AnalysisException(status.message().to_string()),
+            Code::AlreadyExists => SparkError::AlreadyExists(status.message().to_string()),
             Code::InvalidArgument => SparkError::InvalidArgument(status.message().to_string()),
             Code::DeadlineExceeded => SparkError::DeadlineExceeded(status.message().to_string()),
             Code::Unimplemented => SparkError::Unimplemented(status.message().to_string()),
@@ -192,3 +375,137 @@ impl<W: Write> From<std::io::IntoInnerError<W>> for SparkError {
         SparkError::IoError(error.to_string(), error.into())
     }
 }
+
+/// Status carrying a `google.rpc.ErrorInfo` with the metadata, as sent by the Spark server
+#[cfg(test)]
+pub(crate) fn status_with_error_info(metadata: HashMap<String, String>) -> tonic::Status {
+    let info = ErrorInfo {
+        reason: "org.apache.spark.sql.catalyst.ExtendedAnalysisException".to_string(),
+        domain: "org.apache.spark".to_string(),
+        metadata,
+    };
+
+    let rpc_status = RpcStatus {
+        code: Code::Internal as i32,
+        message: "[TABLE_OR_VIEW_NOT_FOUND] The table or view `missing` cannot be found."
+            .to_string(),
+        details: vec![prost_types::Any {
+            type_url: ERROR_INFO_TYPE_URL.to_string(),
+            value: info.encode_to_vec(),
+        }],
+    };
+
+    tonic::Status::with_details(
+        Code::Internal,
+        rpc_status.message.clone(),
+        rpc_status.encode_to_vec().into(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_error_from_status() {
+        let metadata = HashMap::from([
+            (
+                "classes".to_string(),
+                r#"["org.apache.spark.sql.catalyst.ExtendedAnalysisException","org.apache.spark.sql.AnalysisException"]"#.to_string(),
+            ),
+            ("errorClass".to_string(), "TABLE_OR_VIEW_NOT_FOUND".to_string()),
+            ("sqlState".to_string(), "42P01".to_string()),
+            (
+                "messageParameters".to_string(),
+                r#"{"relationName":"`missing`"}"#.to_string(),
+            ),
+        ]);
+
+        let err = SparkError::from(status_with_error_info(metadata));
+
+        assert_eq!(Some("TABLE_OR_VIEW_NOT_FOUND"), err.error_class());
+        assert_eq!(Some("42P01"), err.sql_state());
+
+        match err {
+            SparkError::Server {
+                code,
+                message_parameters,
+                server_stacktrace,
+                ..
+            } => {
+                assert_eq!(Code::Internal, code);
+                assert_eq!("`missing`", message_parameters["relationName"]);
+                assert!(server_stacktrace.is_none());
+            }
+            _ => panic!("expected a server error"),
+        }
+    }
+
+    #[cfg(feature = "spark-4")]
+    #[test]
+    fn test_server_error_with_details() {
+        use spark::fetch_error_details_response::{Error, SparkThrowable, StackTraceElement};
+
+        let metadata = HashMap::from([
+            (
+                "errorClass".to_string(),
+                "TABLE_OR_VIEW_NOT_FOUND".to_string(),
+            ),
+            ("errorId".to_string(), "e1".to_string()),
+        ]);
+
+        let err = SparkError::from(status_with_error_info(metadata));
+
+        assert_eq!(Some("e1"), err.error_id());
+
+        let details = spark::FetchErrorDetailsResponse {
+            root_error_idx: Some(0),
+            errors: vec![Error {
+                error_type_hierarchy: vec![],
+                message: "The table or view `missing` cannot be found.".to_string(),
+                stack_trace: vec![StackTraceElement {
+                    declaring_class: "org.apache.spark.sql.Dataset".to_string(),
+                    method_name: "ofRows".to_string(),
+                    file_name: Some("Dataset.scala".to_string()),
+                    line_number: 99,
+                }],
+                cause_idx: None,
+                spark_throwable: Some(SparkThrowable {
+                    sql_state: Some("42P01".to_string()),
+                    ..Default::default()
+                }),
+            }],
+            ..Default::default()
+        };
+
+        let err = err.with_details(details);
+
+        assert_eq!(Some("TABLE_OR_VIEW_NOT_FOUND"), err.error_class());
+        assert_eq!(Some("42P01"), err.sql_state());
+
+        match err {
+            SparkError::Server {
+                message,
+                server_stacktrace,
+                ..
+            } => {
+                assert_eq!("The table or view `missing` cannot be found.", message);
+                assert_eq!(
+                    Some("\tat org.apache.spark.sql.Dataset.ofRows(Dataset.scala:99)"),
+                    server_stacktrace.as_deref()
+                );
+            }
+            _ => panic!("expected a server error"),
+        }
+    }
+
+    #[test]
+    fn test_status_without_details() {
+        let err = SparkError::from(tonic::Status::already_exists("table exists"));
+        assert!(matches!(err, SparkError::AlreadyExists(_)));
+
+        let err = SparkError::from(tonic::Status::internal("boom"));
+        assert!(matches!(err, SparkError::Internal(_)));
+        assert!(err.error_class().is_none());
+    }
+}
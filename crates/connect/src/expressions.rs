@@ -87,6 +87,16 @@ impl From<Column> for spark::Expression {
     }
 }
 
+impl From<spark::expression::ExprType> for spark::Expression {
+    fn from(expr_type: spark::expression::ExprType) -> Self {
+        spark::Expression {
+            #[cfg(feature = "spark-4")]
+            common: None,
+            expr_type: Some(expr_type),
+        }
+    }
+}
+
 /// Create a filter expression
 pub trait ToFilterExpr {
     fn to_filter_expr(&self) -> Option<spark::Expression>;
@@ -100,13 +110,12 @@ impl ToFilterExpr for Column {
 
 impl ToFilterExpr for &str {
     fn to_filter_expr(&self) -> Option<spark::Expression> {
-        let expr_type = Some(spark::expression::ExprType::ExpressionString(
-            spark::expression::ExpressionString {
+        let expr_type =
+            spark::expression::ExprType::ExpressionString(spark::expression::ExpressionString {
                 expression: self.to_string(),
-            },
-        ));
+            });
 
-        Some(spark::Expression { expr_type })
+        Some(spark::Expression::from(expr_type))
     }
 }
 
@@ -119,14 +128,24 @@ macro_rules! impl_to_literal {
     ($type:ty, $inner_type:ident) => {
         impl From<$type> for spark::expression::Literal {
             fn from(value: $type) -> spark::expression::Literal {
-                spark::expression::Literal {
-                    literal_type: Some(spark::expression::literal::LiteralType::$inner_type(value)),
-                }
+                spark::expression::Literal::from(
+                    spark::expression::literal::LiteralType::$inner_type(value),
+                )
             }
         }
     };
 }
 
+impl From<spark::expression::literal::LiteralType> for spark::expression::Literal {
+    fn from(literal_type: spark::expression::literal::LiteralType) -> Self {
+        spark::expression::Literal {
+            #[cfg(feature = "spark-4")]
+            data_type: None,
+            literal_type: Some(literal_type),
+        }
+    }
+}
+
 impl_to_literal!(bool, Boolean);
 impl_to_literal!(i32, Integer);
 impl_to_literal!(i64, Long);
@@ -136,29 +155,25 @@ impl_to_literal!(String, String);
 
 impl From<&[u8]> for spark::expression::Literal {
     fn from(value: &[u8]) -> Self {
-        spark::expression::Literal {
-            literal_type: Some(spark::expression::literal::LiteralType::Binary(Vec::from(
-                value,
-            ))),
-        }
+        spark::expression::Literal::from(spark::expression::literal::LiteralType::Binary(
+            Vec::from(value),
+        ))
     }
 }
 
 impl From<i16> for spark::expression::Literal {
     fn from(value: i16) -> Self {
-        spark::expression::Literal {
-            literal_type: Some(spark::expression::literal::LiteralType::Short(value as i32)),
-        }
+        spark::expression::Literal::from(spark::expression::literal::LiteralType::Short(
+            value as i32,
+        ))
     }
 }
 
 impl<'a> From<&'a str> for spark::expression::Literal {
     fn from(value: &'a str) -> Self {
-        spark::expression::Literal {
-            literal_type: Some(spark::expression::literal::LiteralType::String(
-                value.to_string(),
-            )),
-        }
+        spark::expression::Literal::from(spark::expression::literal::LiteralType::String(
+            value.to_string(),
+        ))
     }
 }
 
@@ -167,11 +182,9 @@ impl<Tz: chrono::TimeZone> From<chrono::DateTime<Tz>> for spark::expression::Lit
         // timestamps for spark have to be the microsends since 1/1/1970
         let timestamp = value.timestamp_micros();
 
-        spark::expression::Literal {
-            literal_type: Some(spark::expression::literal::LiteralType::Timestamp(
-                timestamp,
-            )),
-        }
+        spark::expression::Literal::from(spark::expression::literal::LiteralType::Timestamp(
+            timestamp,
+        ))
     }
 }
 
@@ -180,11 +193,9 @@ impl From<NaiveDateTime> for spark::expression::Literal {
         // timestamps for spark have to be the microsends since 1/1/1970
         let timestamp = value.and_utc().timestamp_micros();
 
-        spark::expression::Literal {
-            literal_type: Some(spark::expression::literal::LiteralType::TimestampNtz(
-                timestamp,
-            )),
-        }
+        spark::expression::Literal::from(spark::expression::literal::LiteralType::TimestampNtz(
+            timestamp,
+        ))
     }
 }
 
@@ -195,11 +206,9 @@ impl From<chrono::NaiveDate> for spark::expression::Literal {
         let days_since_unix_epoch =
             value.signed_duration_since(chrono::NaiveDate::from_ymd_opt(1970, 1, 1).unwrap());
 
-        spark::expression::Literal {
-            literal_type: Some(spark::expression::literal::LiteralType::Date(
-                days_since_unix_epoch.num_days() as i32,
-            )),
-        }
+        spark::expression::Literal::from(spark::expression::literal::LiteralType::Date(
+            days_since_unix_epoch.num_days() as i32,
+        ))
     }
 }
 
@@ -215,14 +224,14 @@ where
 
         let elements = value.iter().map(|val| val.clone().into()).collect();
 
+        // Spark 4.0 only reads the deprecated element type of the array
+        #[cfg_attr(feature = "spark-4", allow(deprecated))]
         let array_type = spark::expression::literal::Array {
             element_type,
             elements,
         };
 
-        spark::expression::Literal {
-            literal_type: Some(spark::expression::literal::LiteralType::Array(array_type)),
-        }
+        spark::expression::Literal::from(spark::expression::literal::LiteralType::Array(array_type))
     }
 }
 
@@ -238,14 +247,14 @@ where
 
         let elements = value.iter().map(|val| val.clone().into()).collect();
 
+        // Spark 4.0 only reads the deprecated element type of the array
+        #[cfg_attr(feature = "spark-4", allow(deprecated))]
         let array_type = spark::expression::literal::Array {
             element_type,
             elements,
         };
 
-        spark::expression::Literal {
-            literal_type: Some(spark::expression::literal::LiteralType::Array(array_type)),
-        }
+        spark::expression::Literal::from(spark::expression::literal::LiteralType::Array(array_type))
     }
 }
 
@@ -0,0 +1,321 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Extension points for Spark Connect server plugins
+//!
+//! Server plugins handle custom relations, expressions and commands sent as a
+//! `google.protobuf.Any`, and may return their own response messages the same way.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use prost::Name;
+
+use crate::errors::SparkError;
+
+/// Pack an extension message into a `google.protobuf.Any`, with the same type url as
+/// `Any.pack` of the Java protobuf runtime used by the server
+pub fn pack<M: Name>(message: &M) -> prost_types::Any {
+    prost_types::Any {
+        type_url: format!("type.googleapis.com/{}", M::full_name()),
+        value: message.encode_to_vec(),
+    }
+}
+
+/// Fully qualified message name of a type url, which is matched regardless of the url prefix
+fn type_name(type_url: &str) -> &str {
+    type_url.rsplit('/').next().unwrap_or(type_url)
+}
+
+/// A custom relation handled by a server plugin
+///
+/// # Example
+/// ```rust
+/// impl RelationExtension for VacuumPreview {
+///     fn to_any(&self) -> prost_types::Any {
+///         pack(self)
+///     }
+/// }
+///
+/// let df = DataFrame::from_extension(spark, &VacuumPreview { table: "events".into() });
+/// ```
+pub trait RelationExtension {
+    fn to_any(&self) -> prost_types::Any;
+}
+
+/// A custom expression handled by a server plugin
+pub trait ExpressionExtension {
+    fn to_any(&self) -> prost_types::Any;
+}
+
+/// A custom command handled by a server plugin, executed with [SparkSession::execute_extension](crate::SparkSession::execute_extension)
+pub trait CommandExtension {
+    fn to_any(&self) -> prost_types::Any;
+}
+
+type Decoder = Arc<dyn Fn(&[u8]) -> Result<Arc<dyn Any + Send + Sync>, SparkError> + Send + Sync>;
+
+/// Decoders for the extension responses returned by server plugins, by message name
+///
+/// Responses without a registered decoder are kept as the raw [prost_types::Any].
+#[derive(Clone, Default)]
+pub struct ExtensionRegistry {
+    decoders: HashMap<String, Decoder>,
+}
+
+impl ExtensionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode the responses with the type url of the message into the message
+    pub fn register<M>(&mut self) -> &mut Self
+    where
+        M: Name + Default + Send + Sync + 'static,
+    {
+        self.register_with(M::full_name(), |value| {
+            let message = M::decode(value).map_err(|err| {
+                SparkError::InvalidArgument(format!(
+                    "Failed to decode extension response '{}': {}",
+                    M::full_name(),
+                    err
+                ))
+            })?;
+
+            Ok(message)
+        })
+    }
+
+    /// Decode the responses of a message, e.g. `my.plugin.VacuumResult`, using a custom decoder
+    pub fn register_with<F, R>(&mut self, message_name: impl Into<String>, decoder: F) -> &mut Self
+    where
+        F: Fn(&[u8]) -> Result<R, SparkError> + Send + Sync + 'static,
+        R: Send + Sync + 'static,
+    {
+        let decoder: Decoder =
+            Arc::new(move |value| Ok(Arc::new(decoder(value)?) as Arc<dyn Any + Send + Sync>));
+
+        let message_name = message_name.into();
+
+        self.decoders
+            .insert(type_name(&message_name).to_string(), decoder);
+        self
+    }
+
+    pub(crate) fn decode(&self, any: prost_types::Any) -> Result<ExtensionResponse, SparkError> {
+        let type_url = any.type_url.clone();
+
+        let value = match self.decoders.get(type_name(&type_url)) {
+            Some(decoder) => decoder(&any.value)?,
+            None => Arc::new(any),
+        };
+
+        Ok(ExtensionResponse { type_url, value })
+    }
+}
+
+impl Debug for ExtensionRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExtensionRegistry")
+            .field("messages", &self.decoders.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// An extension response returned by a server plugin
+///
+/// The response is decoded by the decoder registered for its message, or else kept as the
+/// raw [prost_types::Any].
+#[derive(Clone)]
+pub struct ExtensionResponse {
+    type_url: String,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+impl ExtensionResponse {
+    /// Type url of the response message
+    pub fn type_url(&self) -> &str {
+        &self.type_url
+    }
+
+    /// The decoded response, if it is of type `R`
+    pub fn downcast_ref<R: 'static>(&self) -> Option<&R> {
+        self.value.downcast_ref::<R>()
+    }
+
+    /// The raw response, if no decoder is registered for its message
+    pub fn raw(&self) -> Option<&prost_types::Any> {
+        self.downcast_ref::<prost_types::Any>()
+    }
+}
+
+impl Debug for ExtensionResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExtensionResponse")
+            .field("type_url", &self.type_url)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use prost::Message;
+
+    use crate::column::Column;
+    use crate::dataframe::DataFrame;
+    use crate::spark;
+    use crate::testing::{MockResponse, MockSparkServer};
+
+    use spark::execute_plan_response::ResponseType;
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Vacuum {
+        #[prost(string, tag = "1")]
+        table: String,
+        #[prost(int64, tag = "2")]
+        files: i64,
+    }
+
+    impl Name for Vacuum {
+        const NAME: &'static str = "Vacuum";
+        const PACKAGE: &'static str = "my.plugin";
+    }
+
+    impl RelationExtension for Vacuum {
+        fn to_any(&self) -> prost_types::Any {
+            pack(self)
+        }
+    }
+
+    impl ExpressionExtension for Vacuum {
+        fn to_any(&self) -> prost_types::Any {
+            pack(self)
+        }
+    }
+
+    impl CommandExtension for Vacuum {
+        fn to_any(&self) -> prost_types::Any {
+            pack(self)
+        }
+    }
+
+    fn vacuum() -> Vacuum {
+        Vacuum {
+            table: "events".to_string(),
+            files: 12,
+        }
+    }
+
+    #[test]
+    fn test_registry_decode() -> Result<(), SparkError> {
+        let mut registry = ExtensionRegistry::new();
+        registry.register::<Vacuum>();
+
+        let vacuum = vacuum();
+
+        let response = registry.decode(pack(&vacuum))?;
+
+        assert_eq!("type.googleapis.com/my.plugin.Vacuum", response.type_url());
+        assert_eq!(Some(&vacuum), response.downcast_ref::<Vacuum>());
+        assert!(response.downcast_ref::<String>().is_none());
+        assert!(response.raw().is_none());
+
+        let unknown = prost_types::Any {
+            type_url: "type.googleapis.com/my.plugin.Unknown".to_string(),
+            value: vec![1, 2, 3],
+        };
+
+        let response = registry.decode(unknown.clone())?;
+
+        assert_eq!(unknown.type_url, response.type_url());
+        assert_eq!(Some(&unknown), response.raw());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_execute_extension() -> Result<(), SparkError> {
+        let server = MockSparkServer::start().await?;
+
+        server.push_execute([MockResponse::Response(ResponseType::Extension(pack(
+            &vacuum(),
+        )))]);
+
+        let mut registry = ExtensionRegistry::new();
+        registry.register::<Vacuum>();
+
+        let spark = server.builder().extensions(registry).build().await?;
+
+        let responses = spark.execute_extension(&vacuum()).await?;
+
+        assert_eq!(1, responses.len());
+        assert_eq!(Some(&vacuum()), responses[0].downcast_ref::<Vacuum>());
+
+        let command = match server.plans()[0].op_type.clone() {
+            Some(spark::plan::OpType::Command(command)) => command.command_type,
+            _ => None,
+        };
+
+        assert_eq!(
+            Some(spark::command::CommandType::Extension(pack(&vacuum()))),
+            command
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_execute_extension_unregistered() -> Result<(), SparkError> {
+        let server = MockSparkServer::start().await?;
+
+        server.push_execute([MockResponse::Response(ResponseType::Extension(pack(
+            &vacuum(),
+        )))]);
+
+        let spark = server.session().await?;
+
+        let responses = spark.execute_extension(&vacuum()).await?;
+
+        assert_eq!(1, responses.len());
+        assert_eq!(Some(&pack(&vacuum())), responses[0].raw());
+        assert!(responses[0].downcast_ref::<Vacuum>().is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extension_plans() -> Result<(), SparkError> {
+        let server = MockSparkServer::start().await?;
+        let spark = server.session().await?;
+
+        let df = DataFrame::from_extension(spark, &vacuum());
+
+        assert_eq!(
+            Some(spark::relation::RelType::Extension(pack(&vacuum()))),
+            df.plan.clone().relation().rel_type
+        );
+
+        let column = Column::from_extension(&vacuum());
+
+        assert_eq!(
+            Some(spark::expression::ExprType::Extension(pack(&vacuum()))),
+            column.expression.expr_type
+        );
+        Ok(())
+    }
+}
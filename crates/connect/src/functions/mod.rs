@@ -18,6 +18,7 @@
 //! A re-implementation of Spark functions
 
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::expressions::VecExpression;
 use crate::spark;
@@ -27,6 +28,8 @@ use crate::column::Column;
 
 use crate::spark::expression::Literal;
 
+use lambda::{Lambda, Names};
+
 use rand::random;
 
 pub(crate) fn invoke_func<I, S>(name: &str, args: I) -> Column
@@ -34,16 +37,16 @@ where
     I: IntoIterator<Item = S>,
     S: Into<Column>,
 {
-    Column::from(spark::Expression {
-        expr_type: Some(spark::expression::ExprType::UnresolvedFunction(
-            spark::expression::UnresolvedFunction {
-                function_name: name.to_string(),
-                arguments: VecExpression::from_iter(args).into(),
-                is_distinct: false,
-                is_user_defined_function: false,
-            },
-        )),
-    })
+    Column::from(spark::Expression::from(
+        spark::expression::ExprType::UnresolvedFunction(spark::expression::UnresolvedFunction {
+            function_name: name.to_string(),
+            arguments: VecExpression::from_iter(args).into(),
+            is_distinct: false,
+            is_user_defined_function: false,
+            #[cfg(feature = "spark-4")]
+            is_internal: None,
+        }),
+    ))
 }
 
 macro_rules! gen_func {
@@ -157,13 +160,11 @@ pub fn bitwise_not(col: impl Into<Column>) -> Column {
 
 /// Parses the expression string into the column that it represents
 pub fn expr(val: &str) -> Column {
-    Column::from(spark::Expression {
-        expr_type: Some(spark::expression::ExprType::ExpressionString(
-            spark::expression::ExpressionString {
-                expression: val.to_string(),
-            },
-        )),
-    })
+    Column::from(spark::Expression::from(
+        spark::expression::ExprType::ExpressionString(spark::expression::ExpressionString {
+            expression: val.to_string(),
+        }),
+    ))
 }
 
 // Math Functions
@@ -903,6 +904,235 @@ pub fn to_csv(col: impl Into<Column>, options: Option<HashMap<&str, &str>>) -> C
 
 gen_func!(try_element_at, [col: Column, extraction: Column], "Returns element of array at given (1-based) index.");
 
+// Higher-order Functions
+
+static LAMBDA_VARIABLE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Creates a [spark::expression::LambdaFunction] by calling the closure with a fresh
+/// lambda variable for each of the names, e.g. `x_3`, so that nested lambdas never
+/// shadow each other
+fn create_lambda<const N: usize>(
+    names: [&str; N],
+    func: impl FnOnce([Column; N]) -> Column,
+) -> Column {
+    let arguments = names.map(|name| spark::expression::UnresolvedNamedLambdaVariable {
+        name_parts: vec![format!(
+            "{}_{}",
+            name,
+            LAMBDA_VARIABLE_ID.fetch_add(1, Ordering::Relaxed)
+        )],
+    });
+
+    let variables = arguments.clone().map(|var| {
+        Column::from(spark::Expression::from(
+            spark::expression::ExprType::UnresolvedNamedLambdaVariable(var),
+        ))
+    });
+
+    let lambda = spark::expression::LambdaFunction {
+        function: Some(Box::new(func(variables).expression)),
+        arguments: arguments.to_vec(),
+    };
+
+    Column::from(spark::Expression::from(
+        spark::expression::ExprType::LambdaFunction(Box::new(lambda)),
+    ))
+}
+
+/// A Rust closure over [Column] arguments that is translated into a lambda function
+/// of a higher-order function
+///
+/// `Args` is the tuple of the closure arguments, which lets functions like [transform]
+/// and [filter] accept either `|x: Column| ...` or `|x: Column, i: Column| ...`.
+///
+/// The trait is sealed, it is only implemented for closures of one to three [Column]s.
+pub trait IntoLambda<Args>: Lambda<Args> {}
+
+impl<Args, F: Lambda<Args>> IntoLambda<Args> for F {}
+
+mod lambda {
+    use super::{create_lambda, Column};
+
+    /// Only constructed by the higher-order functions of this module, so that
+    /// [Lambda::into_lambda] can not be called from outside the crate
+    pub struct Names<'a>(&'a [&'a str]);
+
+    impl<'a> Names<'a> {
+        /// At least as many names as the arguments of the closures passed with them
+        pub(super) fn new(names: &'a [&'a str]) -> Self {
+            Names(names)
+        }
+    }
+
+    pub trait Lambda<Args> {
+        /// Builds the lambda function expression, naming the variables after the first names
+        fn into_lambda(self, names: Names) -> Column;
+    }
+
+    impl<F> Lambda<(Column,)> for F
+    where
+        F: FnOnce(Column) -> Column,
+    {
+        fn into_lambda(self, Names(names): Names) -> Column {
+            create_lambda([names[0]], |[x]| self(x))
+        }
+    }
+
+    impl<F> Lambda<(Column, Column)> for F
+    where
+        F: FnOnce(Column, Column) -> Column,
+    {
+        fn into_lambda(self, Names(names): Names) -> Column {
+            create_lambda([names[0], names[1]], |[x, y]| self(x, y))
+        }
+    }
+
+    impl<F> Lambda<(Column, Column, Column)> for F
+    where
+        F: FnOnce(Column, Column, Column) -> Column,
+    {
+        fn into_lambda(self, Names(names): Names) -> Column {
+            create_lambda([names[0], names[1], names[2]], |[x, y, z]| self(x, y, z))
+        }
+    }
+}
+
+/// Returns an array of elements after applying a transformation to each element in the
+/// input array. The function takes either the element, or the element and its index.
+///
+/// # Example:
+/// ```rust
+/// df.select([transform("values", |x: Column| x * 2)]);
+///
+/// df.select([transform("values", |x: Column, i: Column| x * i)]);
+/// ```
+pub fn transform<Args>(col: impl Into<Column>, f: impl IntoLambda<Args>) -> Column {
+    invoke_func(
+        "transform",
+        vec![col.into(), f.into_lambda(Names::new(&["x", "i"]))],
+    )
+}
+
+/// Returns whether a predicate holds for one or more elements in the array.
+pub fn exists(col: impl Into<Column>, f: impl FnOnce(Column) -> Column) -> Column {
+    invoke_func(
+        "exists",
+        vec![col.into(), f.into_lambda(Names::new(&["x"]))],
+    )
+}
+
+/// Returns whether a predicate holds for every element in the array.
+pub fn forall(col: impl Into<Column>, f: impl FnOnce(Column) -> Column) -> Column {
+    invoke_func(
+        "forall",
+        vec![col.into(), f.into_lambda(Names::new(&["x"]))],
+    )
+}
+
+/// Returns an array of elements for which a predicate holds in a given array. The
+/// function takes either the element, or the element and its index.
+///
+/// # Example:
+/// ```rust
+/// df.select([filter("values", |x: Column| x.gt(lit(2)))]);
+/// ```
+pub fn filter<Args>(col: impl Into<Column>, f: impl IntoLambda<Args>) -> Column {
+    invoke_func(
+        "filter",
+        vec![col.into(), f.into_lambda(Names::new(&["x", "i"]))],
+    )
+}
+
+/// Applies a binary operator to an initial state and all elements in the array, and reduces
+/// this to a single state. The final state is converted into the final result by applying
+/// the optional finish function.
+///
+/// # Example:
+/// ```rust
+/// df.select([aggregate("values", lit(0.0), |acc, x| acc + x, None::<fn(Column) -> Column>)]);
+///
+/// df.select([aggregate("values", lit(0.0), |acc, x| acc + x, Some(|acc| acc * 10))]);
+/// ```
+pub fn aggregate(
+    col: impl Into<Column>,
+    initial_value: impl Into<Column>,
+    merge: impl FnOnce(Column, Column) -> Column,
+    finish: Option<impl FnOnce(Column) -> Column>,
+) -> Column {
+    let mut args = vec![
+        col.into(),
+        initial_value.into(),
+        merge.into_lambda(Names::new(&["acc", "x"])),
+    ];
+
+    if let Some(finish) = finish {
+        args.push(finish.into_lambda(Names::new(&["acc"])));
+    }
+
+    invoke_func("aggregate", args)
+}
+
+/// Merges two given arrays, element-wise, into a single array using a function.
+pub fn zip_with(
+    left: impl Into<Column>,
+    right: impl Into<Column>,
+    f: impl FnOnce(Column, Column) -> Column,
+) -> Column {
+    invoke_func(
+        "zip_with",
+        vec![
+            left.into(),
+            right.into(),
+            f.into_lambda(Names::new(&["x", "y"])),
+        ],
+    )
+}
+
+/// Returns a map whose key-value pairs satisfy a predicate.
+pub fn map_filter(col: impl Into<Column>, f: impl FnOnce(Column, Column) -> Column) -> Column {
+    invoke_func(
+        "map_filter",
+        vec![col.into(), f.into_lambda(Names::new(&["k", "v"]))],
+    )
+}
+
+/// Merge two given maps, key-wise into a single map using a function.
+pub fn map_zip_with(
+    col1: impl Into<Column>,
+    col2: impl Into<Column>,
+    f: impl FnOnce(Column, Column, Column) -> Column,
+) -> Column {
+    invoke_func(
+        "map_zip_with",
+        vec![
+            col1.into(),
+            col2.into(),
+            f.into_lambda(Names::new(&["k", "v1", "v2"])),
+        ],
+    )
+}
+
+/// Applies a function to every key-value pair in a map and returns a map with the results
+/// of those applications as the new keys for the pairs.
+pub fn transform_keys(col: impl Into<Column>, f: impl FnOnce(Column, Column) -> Column) -> Column {
+    invoke_func(
+        "transform_keys",
+        vec![col.into(), f.into_lambda(Names::new(&["k", "v"]))],
+    )
+}
+
+/// Applies a function to every key-value pair in a map and returns a map with the results
+/// of those applications as the new values for the pairs.
+pub fn transform_values(
+    col: impl Into<Column>,
+    f: impl FnOnce(Column, Column) -> Column,
+) -> Column {
+    invoke_func(
+        "transform_values",
+        vec![col.into(), f.into_lambda(Names::new(&["k", "v"]))],
+    )
+}
+
 // Partition Transformations
 gen_func!(years, [col: Column], "A transform for timestamps and dates to partition data into years.");
 gen_func!(months, [col: Column], "A transform for timestamps and dates to partition data into months.");
@@ -958,16 +1188,16 @@ where
 
     expr.append(&mut cols);
 
-    Column::from(spark::Expression {
-        expr_type: Some(spark::expression::ExprType::UnresolvedFunction(
-            spark::expression::UnresolvedFunction {
-                function_name: "count".to_string(),
-                arguments: VecExpression::from_iter(expr).into(),
-                is_distinct: true,
-                is_user_defined_function: false,
-            },
-        )),
-    })
+    Column::from(spark::Expression::from(
+        spark::expression::ExprType::UnresolvedFunction(spark::expression::UnresolvedFunction {
+            function_name: "count".to_string(),
+            arguments: VecExpression::from_iter(expr).into(),
+            is_distinct: true,
+            is_user_defined_function: false,
+            #[cfg(feature = "spark-4")]
+            is_internal: None,
+        }),
+    ))
 }
 
 /// Returns a count-min sketch of a column with the given esp, confidence and seed.
@@ -1658,9 +1888,10 @@ mod tests {
 
     use arrow::{
         array::{
-            ArrayRef, BooleanArray, Float64Array, Int32Array, Int64Array, StringArray, StructArray,
+            ArrayRef, BooleanArray, Float64Array, Int32Array, Int64Array, ListArray, StringArray,
+            StructArray,
         },
-        datatypes::{DataType, Field, Schema},
+        datatypes::{DataType, Field, Int32Type, Schema},
         record_batch::RecordBatch,
     };
 
@@ -2506,6 +2737,93 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_func_lambda_variables() {
+        let lambda = |col: Column| match col.expression.expr_type {
+            Some(spark::expression::ExprType::UnresolvedFunction(mut func)) => {
+                match func.arguments.pop().and_then(|arg| arg.expr_type) {
+                    Some(spark::expression::ExprType::LambdaFunction(lambda)) => lambda,
+                    other => panic!("expected a lambda function, found {:?}", other),
+                }
+            }
+            other => panic!("expected a function, found {:?}", other),
+        };
+
+        let first = lambda(transform("values", |x: Column| x * 2));
+        let second = lambda(transform("values", |x: Column, i: Column| x * i));
+
+        assert_eq!(1, first.arguments.len());
+        assert_eq!(2, second.arguments.len());
+        assert!(second.arguments[1].name_parts[0].starts_with("i_"));
+        assert_ne!(first.arguments[0], second.arguments[0]);
+
+        let variable = |var: &spark::expression::UnresolvedNamedLambdaVariable| {
+            spark::Expression::from(spark::expression::ExprType::UnresolvedNamedLambdaVariable(
+                var.clone(),
+            ))
+        };
+
+        let expected = invoke_func("*", vec![variable(&first.arguments[0]), lit(2).expression]);
+
+        assert_eq!(Some(Box::new(expected.expression)), first.function);
+
+        let merge = lambda(aggregate(
+            "values",
+            lit(0),
+            |acc, x| acc + x,
+            None::<fn(Column) -> Column>,
+        ));
+
+        assert_eq!(2, merge.arguments.len());
+        assert!(merge.arguments[0].name_parts[0].starts_with("acc_"));
+    }
+
+    test_func!(
+        test_func_transform,
+        "SELECT array(1, 2, 3) AS values",
+        transform("values", |x: Column| x * 2).alias("value"),
+        value,
+        ListArray::from_iter_primitive::<Int32Type, _, _>(vec![Some(vec![
+            Some(2),
+            Some(4),
+            Some(6)
+        ])]),
+        false
+    );
+
+    test_func!(
+        test_func_exists,
+        "SELECT array(1, 2, 3) AS values",
+        exists("values", |x| x.gt(lit(2))).alias("value"),
+        value,
+        BooleanArray::from(vec![true]),
+        true
+    );
+
+    test_func!(
+        test_func_forall,
+        "SELECT array(1, 2, 3) AS values",
+        forall("values", |x| x.gt(lit(2))).alias("value"),
+        value,
+        BooleanArray::from(vec![false]),
+        true
+    );
+
+    test_func!(
+        test_func_aggregate,
+        "SELECT array(1, 2, 3) AS values",
+        aggregate(
+            "values",
+            lit(0),
+            |acc, x| acc + x,
+            Some(|acc: Column| acc * 10)
+        )
+        .alias("value"),
+        value,
+        Int32Array::from(vec![60]),
+        false
+    );
+
     #[tokio::test]
     async fn test_func_over() -> Result<(), SparkError> {
         let spark = setup().await;
@@ -41,7 +41,7 @@
 //!
 //!     let data = RecordBatch::try_from_iter(vec![("name", name), ("age", age)])?
 //!
-//!     let df = spark.create_dataframe(&data).await?
+//!     let df = spark.create_dataframe(&data)?
 //!
 //!     // 2 records total
 //!     let records = df.select(["*"])
@@ -125,6 +125,12 @@
 //! "sc://<workspace id>:443/;token=<personal access token>;x-databricks-cluster-id=<cluster-id>"
 //! ```
 //!
+//! ## Spark 4
+//!
+//! The client speaks the Spark 3.5 protocol by default. The feature flag `feature = "spark-4"`
+//! builds it from the Spark 4 protobuf instead, which adds releasing the session with
+//! `SparkSession::stop`, the full details of server errors, and execution progress handlers.
+//!
 //!
 
 /// Spark Connect gRPC protobuf translated using [tonic]
@@ -132,6 +138,7 @@ pub mod spark {
     tonic::include_proto!("spark.connect");
 }
 
+pub mod artifacts;
 pub mod catalog;
 pub mod client;
 pub mod column;
@@ -139,13 +146,22 @@ pub mod conf;
 pub mod dataframe;
 pub mod errors;
 pub mod expressions;
+pub mod extension;
 pub mod functions;
 pub mod group;
+#[cfg(feature = "local")]
+pub mod local;
+pub mod metrics;
+pub mod operation;
 pub mod plan;
 pub mod readwriter;
+#[cfg(any(test, feature = "testing", feature = "local"))]
+mod server;
 pub mod session;
 pub mod storage;
 pub mod streaming;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod types;
 pub mod window;
 
@@ -0,0 +1,760 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Local Spark Connect server that executes plans with DataFusion
+//!
+//! Requires the `local` feature. Relations and functions that can not be translated
+//! into a DataFusion plan are returned as [SparkError::Unimplemented].
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use arrow::record_batch::RecordBatch;
+
+use datafusion::execution::context::SessionContext;
+use datafusion::logical_expr::LogicalPlan;
+
+use futures_util::stream::{self, BoxStream, StreamExt};
+
+use tokio::sync::oneshot;
+
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+use uuid::Uuid;
+
+use crate::errors::SparkError;
+use crate::plan::serialize;
+use crate::server::spawn;
+use crate::session::{SparkSession, SparkSessionBuilder};
+use crate::spark;
+
+use spark::execute_plan_response::ResponseType;
+use spark::spark_connect_service_server::{SparkConnectService, SparkConnectServiceServer};
+
+mod plan;
+
+use plan::{datafusion_error, spark_schema, PlanTranslator};
+
+/// Spark version reported to the client
+#[cfg(not(feature = "spark-4"))]
+const SPARK_VERSION: &str = "3.5.1";
+#[cfg(feature = "spark-4")]
+const SPARK_VERSION: &str = "4.0.0";
+
+fn to_status(err: SparkError) -> Status {
+    match err {
+        SparkError::Unimplemented(msg) => Status::unimplemented(msg),
+        SparkError::InvalidArgument(msg) | SparkError::AnalysisException(msg) => {
+            Status::invalid_argument(msg)
+        }
+        SparkError::NotFound(msg) => Status::not_found(msg),
+        err => Status::internal(err.to_string()),
+    }
+}
+
+/// Query of a SQL command, failing when it has parameters
+#[cfg(not(feature = "spark-4"))]
+fn sql_query(sql: spark::SqlCommand) -> Result<String, SparkError> {
+    if !sql.args.is_empty() || !sql.pos_args.is_empty() {
+        return Err(SparkError::Unimplemented(
+            "SQL with parameters is not supported by the local backend".to_string(),
+        ));
+    }
+
+    Ok(sql.sql)
+}
+
+/// Query of a SQL command, which Spark 4 sends as a SQL relation, failing when it has
+/// parameters
+#[cfg(feature = "spark-4")]
+fn sql_query(sql: spark::SqlCommand) -> Result<String, SparkError> {
+    let sql = match sql.input.and_then(|input| input.rel_type) {
+        Some(spark::relation::RelType::Sql(sql)) => sql,
+        _ => {
+            return Err(SparkError::InvalidArgument(
+                "SQL command requires a SQL relation".to_string(),
+            ))
+        }
+    };
+
+    if !sql.named_arguments.is_empty() || !sql.pos_arguments.is_empty() {
+        return Err(SparkError::Unimplemented(
+            "SQL with parameters is not supported by the local backend".to_string(),
+        ));
+    }
+
+    Ok(sql.query)
+}
+
+#[cfg(not(feature = "spark-4"))]
+fn sql_relation(query: String) -> spark::Sql {
+    spark::Sql {
+        query,
+        args: HashMap::new(),
+        pos_args: vec![],
+    }
+}
+
+#[cfg(feature = "spark-4")]
+fn sql_relation(query: String) -> spark::Sql {
+    spark::Sql {
+        query,
+        ..Default::default()
+    }
+}
+
+#[derive(Clone)]
+struct LocalService {
+    ctx: SessionContext,
+    conf: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl LocalService {
+    fn new(ctx: SessionContext) -> Self {
+        LocalService {
+            ctx,
+            conf: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn translator(&self) -> PlanTranslator {
+        PlanTranslator::new(self.ctx.clone())
+    }
+
+    /// Execute a plan and return the response types to stream back to the client
+    async fn execute(&self, plan: spark::Plan) -> Result<Vec<ResponseType>, SparkError> {
+        let op_type = plan
+            .op_type
+            .ok_or_else(|| SparkError::InvalidArgument("Plan is empty".to_string()))?;
+
+        match op_type {
+            spark::plan::OpType::Root(relation) => {
+                let df = self.translator().relation(relation).await?;
+
+                let schema = df.schema().inner().clone();
+
+                let mut batches = df.collect().await.map_err(datafusion_error)?;
+
+                // the client expects at least one batch to build the result from
+                if batches.is_empty() {
+                    batches.push(RecordBatch::new_empty(schema));
+                }
+
+                batches
+                    .iter()
+                    .map(|batch| {
+                        Ok(ResponseType::ArrowBatch(
+                            spark::execute_plan_response::ArrowBatch {
+                                row_count: batch.num_rows() as i64,
+                                data: serialize(batch)?,
+                                #[cfg(feature = "spark-4")]
+                                start_offset: None,
+                                #[cfg(feature = "spark-4")]
+                                chunk_index: None,
+                                #[cfg(feature = "spark-4")]
+                                num_chunks_in_batch: None,
+                            },
+                        ))
+                    })
+                    .collect()
+            }
+            spark::plan::OpType::Command(command) => match command.command_type {
+                Some(spark::command::CommandType::SqlCommand(sql)) => {
+                    let relation = self.sql_command(sql).await?;
+
+                    Ok(vec![ResponseType::SqlCommandResult(
+                        spark::execute_plan_response::SqlCommandResult {
+                            relation: Some(relation),
+                        },
+                    )])
+                }
+                _ => Err(SparkError::Unimplemented(
+                    "Only SQL commands are supported by the local backend".to_string(),
+                )),
+            },
+            #[cfg(feature = "spark-4")]
+            spark::plan::OpType::CompressedOperation(_) => Err(SparkError::Unimplemented(
+                "Compressed plans are not supported by the local backend".to_string(),
+            )),
+        }
+    }
+
+    /// Run a SQL statement, and return the relation used by the client to build its DataFrame.
+    ///
+    /// Queries are returned as a SQL relation and executed lazily, while statements that
+    /// change the catalog or data are executed immediately, like on a Spark cluster.
+    async fn sql_command(&self, sql: spark::SqlCommand) -> Result<spark::Relation, SparkError> {
+        let query = sql_query(sql)?;
+
+        let df = self.ctx.sql(&query).await.map_err(datafusion_error)?;
+
+        let rel_type = match df.logical_plan() {
+            LogicalPlan::Dml(_) | LogicalPlan::EmptyRelation(_) => {
+                let batches = df.collect().await.map_err(datafusion_error)?;
+
+                let data = match batches.first() {
+                    Some(batch) => Some(serialize(batch)?),
+                    None => None,
+                };
+
+                spark::relation::RelType::LocalRelation(spark::LocalRelation { data, schema: None })
+            }
+            _ => spark::relation::RelType::Sql(sql_relation(query)),
+        };
+
+        Ok(spark::Relation {
+            common: None,
+            rel_type: Some(rel_type),
+        })
+    }
+
+    async fn analyze(
+        &self,
+        analyze: spark::analyze_plan_request::Analyze,
+    ) -> Result<spark::analyze_plan_response::Result, SparkError> {
+        use spark::analyze_plan_request::Analyze;
+        use spark::analyze_plan_response as resp;
+
+        let root = |plan: Option<spark::Plan>| match plan.and_then(|plan| plan.op_type) {
+            Some(spark::plan::OpType::Root(relation)) => Ok(relation),
+            _ => Err(SparkError::InvalidArgument(
+                "Analyze requires a relation".to_string(),
+            )),
+        };
+
+        let result = match analyze {
+            Analyze::Schema(schema) => {
+                let df = self.translator().relation(root(schema.plan)?).await?;
+
+                resp::Result::Schema(resp::Schema {
+                    schema: Some(spark_schema(df.schema())?),
+                })
+            }
+            Analyze::Explain(explain) => {
+                let df = self.translator().relation(root(explain.plan)?).await?;
+
+                let explain_string = df.logical_plan().display_indent().to_string();
+
+                resp::Result::Explain(resp::Explain { explain_string })
+            }
+            Analyze::TreeString(tree) => {
+                let df = self.translator().relation(root(tree.plan)?).await?;
+
+                let tree_string = df.logical_plan().display_indent_schema().to_string();
+
+                resp::Result::TreeString(resp::TreeString { tree_string })
+            }
+            Analyze::IsLocal(_) => resp::Result::IsLocal(resp::IsLocal { is_local: false }),
+            Analyze::IsStreaming(_) => resp::Result::IsStreaming(resp::IsStreaming {
+                is_streaming: false,
+            }),
+            Analyze::InputFiles(_) => resp::Result::InputFiles(resp::InputFiles { files: vec![] }),
+            Analyze::SparkVersion(_) => resp::Result::SparkVersion(resp::SparkVersion {
+                version: SPARK_VERSION.to_string(),
+            }),
+            _ => {
+                return Err(SparkError::Unimplemented(
+                    "Analyze request is not supported by the local backend".to_string(),
+                ))
+            }
+        };
+
+        Ok(result)
+    }
+
+    fn config(
+        &self,
+        operation: spark::config_request::Operation,
+    ) -> Result<Vec<spark::KeyValue>, SparkError> {
+        use spark::config_request::operation::OpType;
+
+        let mut conf = self.conf.lock().unwrap();
+
+        let key_value = |key: String, value: Option<String>| spark::KeyValue { key, value };
+
+        let pairs = match operation.op_type {
+            Some(OpType::Set(set)) => {
+                for pair in set.pairs {
+                    conf.insert(pair.key, pair.value.unwrap_or_default());
+                }
+                vec![]
+            }
+            Some(OpType::Get(get)) => get
+                .keys
+                .into_iter()
+                .map(|key| match conf.get(&key).cloned() {
+                    Some(value) => Ok(key_value(key, Some(value))),
+                    None => Err(SparkError::NotFound(format!(
+                        "[SQL_CONF_NOT_FOUND] The SQL config \"{}\" cannot be found",
+                        key
+                    ))),
+                })
+                .collect::<Result<_, _>>()?,
+            Some(OpType::GetWithDefault(get)) => get
+                .pairs
+                .into_iter()
+                .map(|pair| {
+                    let value = conf.get(&pair.key).cloned().or(pair.value);
+                    key_value(pair.key, value)
+                })
+                .collect(),
+            Some(OpType::GetOption(get)) => get
+                .keys
+                .into_iter()
+                .map(|key| {
+                    let value = conf.get(&key).cloned();
+                    key_value(key, value)
+                })
+                .collect(),
+            Some(OpType::GetAll(get)) => conf
+                .iter()
+                .filter(|(key, _)| key.starts_with(get.prefix.as_deref().unwrap_or_default()))
+                .map(|(key, value)| key_value(key.clone(), Some(value.clone())))
+                .collect(),
+            Some(OpType::Unset(unset)) => {
+                for key in unset.keys {
+                    conf.remove(&key);
+                }
+                vec![]
+            }
+            Some(OpType::IsModifiable(modifiable)) => modifiable
+                .keys
+                .into_iter()
+                .map(|key| key_value(key, Some("true".to_string())))
+                .collect(),
+            None => vec![],
+        };
+
+        Ok(pairs)
+    }
+}
+
+#[tonic::async_trait]
+impl SparkConnectService for LocalService {
+    type ExecutePlanStream = BoxStream<'static, Result<spark::ExecutePlanResponse, Status>>;
+    type ReattachExecuteStream = BoxStream<'static, Result<spark::ExecutePlanResponse, Status>>;
+
+    async fn execute_plan(
+        &self,
+        request: Request<spark::ExecutePlanRequest>,
+    ) -> Result<Response<Self::ExecutePlanStream>, Status> {
+        let req = request.into_inner();
+
+        let plan = req
+            .plan
+            .ok_or_else(|| Status::invalid_argument("Plan is required"))?;
+
+        let operation_id = req
+            .operation_id
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let mut response_types = self.execute(plan).await.map_err(to_status)?;
+
+        response_types.push(ResponseType::ResultComplete(
+            spark::execute_plan_response::ResultComplete {},
+        ));
+
+        let responses: Vec<spark::ExecutePlanResponse> = response_types
+            .into_iter()
+            .map(|response_type| spark::ExecutePlanResponse {
+                session_id: req.session_id.clone(),
+                #[cfg(feature = "spark-4")]
+                server_side_session_id: self.ctx.session_id(),
+                operation_id: operation_id.clone(),
+                response_id: Uuid::new_v4().to_string(),
+                metrics: None,
+                observed_metrics: vec![],
+                schema: None,
+                response_type: Some(response_type),
+            })
+            .collect();
+
+        Ok(Response::new(stream::iter(responses).map(Ok).boxed()))
+    }
+
+    async fn analyze_plan(
+        &self,
+        request: Request<spark::AnalyzePlanRequest>,
+    ) -> Result<Response<spark::AnalyzePlanResponse>, Status> {
+        let req = request.into_inner();
+
+        let analyze = req
+            .analyze
+            .ok_or_else(|| Status::invalid_argument("Analyze is required"))?;
+
+        let result = self.analyze(analyze).await.map_err(to_status)?;
+
+        Ok(Response::new(spark::AnalyzePlanResponse {
+            session_id: req.session_id,
+            #[cfg(feature = "spark-4")]
+            server_side_session_id: self.ctx.session_id(),
+            result: Some(result),
+        }))
+    }
+
+    async fn config(
+        &self,
+        request: Request<spark::ConfigRequest>,
+    ) -> Result<Response<spark::ConfigResponse>, Status> {
+        let req = request.into_inner();
+
+        let operation = req
+            .operation
+            .ok_or_else(|| Status::invalid_argument("Config operation is required"))?;
+
+        let pairs = LocalService::config(self, operation).map_err(to_status)?;
+
+        Ok(Response::new(spark::ConfigResponse {
+            session_id: req.session_id,
+            #[cfg(feature = "spark-4")]
+            server_side_session_id: self.ctx.session_id(),
+            pairs,
+            warnings: vec![],
+        }))
+    }
+
+    async fn add_artifacts(
+        &self,
+        _request: Request<Streaming<spark::AddArtifactsRequest>>,
+    ) -> Result<Response<spark::AddArtifactsResponse>, Status> {
+        Err(Status::unimplemented(
+            "artifacts are not supported by the local backend",
+        ))
+    }
+
+    async fn artifact_status(
+        &self,
+        request: Request<spark::ArtifactStatusesRequest>,
+    ) -> Result<Response<spark::ArtifactStatusesResponse>, Status> {
+        let req = request.into_inner();
+
+        // nothing is cached, so local relations are always sent inline
+        let statuses = req
+            .names
+            .into_iter()
+            .map(|name| {
+                (
+                    name,
+                    spark::artifact_statuses_response::ArtifactStatus { exists: false },
+                )
+            })
+            .collect();
+
+        Ok(Response::new(spark::ArtifactStatusesResponse {
+            #[cfg(feature = "spark-4")]
+            session_id: req.session_id,
+            #[cfg(feature = "spark-4")]
+            server_side_session_id: self.ctx.session_id(),
+            statuses,
+        }))
+    }
+
+    async fn interrupt(
+        &self,
+        request: Request<spark::InterruptRequest>,
+    ) -> Result<Response<spark::InterruptResponse>, Status> {
+        // operations complete before their response stream is returned
+        Ok(Response::new(spark::InterruptResponse {
+            session_id: request.into_inner().session_id,
+            #[cfg(feature = "spark-4")]
+            server_side_session_id: self.ctx.session_id(),
+            interrupted_ids: vec![],
+        }))
+    }
+
+    async fn reattach_execute(
+        &self,
+        request: Request<spark::ReattachExecuteRequest>,
+    ) -> Result<Response<Self::ReattachExecuteStream>, Status> {
+        Err(Status::not_found(format!(
+            "INVALID_HANDLE.OPERATION_NOT_FOUND {}",
+            request.into_inner().operation_id
+        )))
+    }
+
+    async fn release_execute(
+        &self,
+        request: Request<spark::ReleaseExecuteRequest>,
+    ) -> Result<Response<spark::ReleaseExecuteResponse>, Status> {
+        let req = request.into_inner();
+
+        Ok(Response::new(spark::ReleaseExecuteResponse {
+            session_id: req.session_id,
+            #[cfg(feature = "spark-4")]
+            server_side_session_id: self.ctx.session_id(),
+            operation_id: Some(req.operation_id),
+        }))
+    }
+
+    #[cfg(feature = "spark-4")]
+    async fn release_session(
+        &self,
+        request: Request<spark::ReleaseSessionRequest>,
+    ) -> Result<Response<spark::ReleaseSessionResponse>, Status> {
+        // the state of the session is owned by the server, and dropped with it
+        Ok(Response::new(spark::ReleaseSessionResponse {
+            session_id: request.into_inner().session_id,
+            server_side_session_id: self.ctx.session_id(),
+        }))
+    }
+
+    #[cfg(feature = "spark-4")]
+    async fn fetch_error_details(
+        &self,
+        request: Request<spark::FetchErrorDetailsRequest>,
+    ) -> Result<Response<spark::FetchErrorDetailsResponse>, Status> {
+        // errors are returned in full, so there are no details to fetch
+        Ok(Response::new(spark::FetchErrorDetailsResponse {
+            session_id: request.into_inner().session_id,
+            server_side_session_id: self.ctx.session_id(),
+            root_error_idx: None,
+            errors: vec![],
+        }))
+    }
+
+    #[cfg(feature = "spark-4")]
+    async fn clone_session(
+        &self,
+        _request: Request<spark::CloneSessionRequest>,
+    ) -> Result<Response<spark::CloneSessionResponse>, Status> {
+        Err(Status::unimplemented(
+            "cloning a session is not supported by the local backend",
+        ))
+    }
+
+    #[cfg(feature = "spark-4")]
+    async fn get_status(
+        &self,
+        _request: Request<spark::GetStatusRequest>,
+    ) -> Result<Response<spark::GetStatusResponse>, Status> {
+        Err(Status::unimplemented(
+            "operation status is not supported by the local backend",
+        ))
+    }
+}
+
+/// Spark Connect server that executes plans locally with DataFusion
+///
+/// Tables registered on the [SessionContext] can be read with `SparkSession::table`
+/// and referenced in SQL. The server shuts down when it is dropped.
+///
+/// # Example
+/// ```rust
+/// let server = LocalSparkServer::start().await?;
+///
+/// server.context().register_batch("people", batch)?;
+///
+/// let spark = server.session().await?;
+///
+/// let adults = spark.table("people")?.filter("age >= 18").count().await?;
+/// ```
+pub struct LocalSparkServer {
+    addr: SocketAddr,
+    ctx: SessionContext,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl std::fmt::Debug for LocalSparkServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalSparkServer")
+            .field("addr", &self.addr)
+            .field("session_id", &self.ctx.session_id())
+            .finish_non_exhaustive()
+    }
+}
+
+impl LocalSparkServer {
+    /// Start the server on an ephemeral port of the loopback interface
+    pub async fn start() -> Result<LocalSparkServer, SparkError> {
+        LocalSparkServer::start_with_context(SessionContext::new()).await
+    }
+
+    /// Start the server with an existing [SessionContext]
+    pub async fn start_with_context(ctx: SessionContext) -> Result<LocalSparkServer, SparkError> {
+        let (addr, shutdown) = spawn(LocalService::new(ctx.clone())).await?;
+
+        Ok(LocalSparkServer {
+            addr,
+            ctx,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// Serve on the address with a new [SessionContext] until the process exits
+    pub async fn serve(addr: SocketAddr) -> Result<(), SparkError> {
+        LocalSparkServer::serve_with_context(addr, SessionContext::new()).await
+    }
+
+    /// Serve on the address with an existing [SessionContext] until the process exits
+    pub async fn serve_with_context(
+        addr: SocketAddr,
+        ctx: SessionContext,
+    ) -> Result<(), SparkError> {
+        Server::builder()
+            .add_service(SparkConnectServiceServer::new(LocalService::new(ctx)))
+            .serve(addr)
+            .await?;
+
+        Ok(())
+    }
+
+    /// [SessionContext] used to execute the plans
+    pub fn context(&self) -> &SessionContext {
+        &self.ctx
+    }
+
+    /// Connection string of the server
+    pub fn connection_string(&self) -> String {
+        format!("sc://{}:{}/", self.addr.ip(), self.addr.port())
+    }
+
+    /// [SparkSessionBuilder] connected to the server
+    pub fn builder(&self) -> SparkSessionBuilder {
+        SparkSessionBuilder::remote(&self.connection_string())
+    }
+
+    /// Connect a new [SparkSession] to the server
+    pub async fn session(&self) -> Result<SparkSession, SparkError> {
+        self.builder().build().await
+    }
+}
+
+impl Drop for LocalSparkServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow::array::{ArrayRef, Int64Array, StringArray};
+
+    use crate::functions::*;
+
+    async fn setup() -> (LocalSparkServer, SparkSession) {
+        let server = LocalSparkServer::start().await.unwrap();
+
+        let name: ArrayRef = Arc::new(StringArray::from(vec!["Tom", "Alice", "Bob"]));
+        let age: ArrayRef = Arc::new(Int64Array::from(vec![14, 23, 16]));
+
+        let data = RecordBatch::try_from_iter(vec![("name", name), ("age", age)]).unwrap();
+
+        server.context().register_batch("people", data).unwrap();
+
+        let spark = server.session().await.unwrap();
+
+        (server, spark)
+    }
+
+    #[tokio::test]
+    async fn test_local_range() -> Result<(), SparkError> {
+        let (_server, spark) = setup().await;
+
+        let df = spark
+            .range(None, 10, 1, None)
+            .filter((col("id") % lit(2)).eq(lit(0)))
+            .select([col("id") * lit(10)]);
+
+        assert_eq!(5, df.count().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_local_range_huge() -> Result<(), SparkError> {
+        let (_server, spark) = setup().await;
+
+        let records = spark
+            .range(None, i64::MAX, 1, None)
+            .limit(3)
+            .collect()
+            .await?;
+
+        let expected: ArrayRef = Arc::new(Int64Array::from(vec![0, 1, 2]));
+
+        assert_eq!(&expected, records.column(0));
+        assert_eq!(20_000, spark.range(None, 20_000, 1, None).count().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_local_range_overflow() -> Result<(), SparkError> {
+        let (_server, spark) = setup().await;
+
+        let records = spark
+            .range(Some(i64::MAX - 3), i64::MAX, 2, None)
+            .collect()
+            .await?;
+
+        let expected: ArrayRef = Arc::new(Int64Array::from(vec![i64::MAX - 3, i64::MAX - 1]));
+        assert_eq!(&expected, records.column(0));
+
+        let records = spark
+            .range(Some(i64::MIN + 2), i64::MIN, -3, None)
+            .collect()
+            .await?;
+
+        let expected: ArrayRef = Arc::new(Int64Array::from(vec![i64::MIN + 2]));
+        assert_eq!(&expected, records.column(0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_local_table() -> Result<(), SparkError> {
+        let (_server, spark) = setup().await;
+
+        let df = spark
+            .table("people")?
+            .filter("age >= 16")
+            .sort([col("name").asc()]);
+
+        let records = df.clone().collect().await?;
+
+        assert_eq!(2, records.num_rows());
+        assert_eq!(vec!["name", "age"], df.columns().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_local_sql() -> Result<(), SparkError> {
+        let (_server, spark) = setup().await;
+
+        let df = spark.sql("SELECT name FROM people WHERE age < 20").await?;
+
+        assert_eq!(2, df.count().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_local_unimplemented() -> Result<(), SparkError> {
+        let (_server, spark) = setup().await;
+
+        let result = spark
+            .range(None, 10, 1, None)
+            .sample(0.0, 0.5, None, None)
+            .collect()
+            .await;
+
+        assert!(matches!(result, Err(SparkError::Unimplemented(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,903 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Translate Spark Connect relations and expressions into DataFusion plans
+
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Int64Array, StringArray};
+use arrow::datatypes::{DataType as ArrowDataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use arrow_ipc::reader::StreamReader;
+
+use datafusion::catalog::streaming::StreamingTable;
+use datafusion::common::{DFSchema, ScalarValue};
+use datafusion::dataframe::DataFrame as DFDataFrame;
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::SessionContext;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::logical_expr::registry::FunctionRegistry;
+use datafusion::logical_expr::{
+    binary_expr, Cast, Expr, ExprFunctionExt, LogicalPlanBuilder as DFPlanBuilder, Operator,
+    SortExpr,
+};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+use datafusion::prelude::{col, lit, CsvReadOptions, JoinType, NdJsonReadOptions};
+
+use futures_util::future::{BoxFuture, FutureExt};
+use futures_util::stream;
+
+use crate::errors::SparkError;
+use crate::spark;
+
+use spark::expression::literal::LiteralType;
+use spark::expression::ExprType;
+use spark::relation::RelType;
+
+/// Spark function names that are named differently in DataFusion
+const FUNCTION_ALIASES: &[(&str, &str)] = &[
+    ("mean", "avg"),
+    ("startswith", "starts_with"),
+    ("endswith", "ends_with"),
+    ("ceiling", "ceil"),
+    ("power", "pow"),
+    ("stddev", "stddev_samp"),
+    ("variance", "var_samp"),
+    ("count_distinct", "count"),
+];
+
+pub(super) fn datafusion_error(err: DataFusionError) -> SparkError {
+    SparkError::AnalysisException(err.to_string())
+}
+
+fn unimplemented(what: &str) -> SparkError {
+    SparkError::Unimplemented(format!("{} is not supported by the local backend", what))
+}
+
+fn missing(what: &str) -> SparkError {
+    SparkError::InvalidArgument(format!("{} is required", what))
+}
+
+/// Translates the relations built by the client into DataFusion [DFDataFrame]s
+#[derive(Clone)]
+pub(super) struct PlanTranslator {
+    ctx: SessionContext,
+}
+
+impl PlanTranslator {
+    pub(super) fn new(ctx: SessionContext) -> Self {
+        PlanTranslator { ctx }
+    }
+
+    pub(super) fn relation(
+        &self,
+        relation: spark::Relation,
+    ) -> BoxFuture<'_, Result<DFDataFrame, SparkError>> {
+        async move {
+            let rel_type = relation.rel_type.ok_or_else(|| missing("Relation type"))?;
+
+            self.rel_type(rel_type).await
+        }
+        .boxed()
+    }
+
+    async fn input(&self, input: Option<Box<spark::Relation>>) -> Result<DFDataFrame, SparkError> {
+        let input = input.ok_or_else(|| missing("Input relation"))?;
+
+        self.relation(*input).await
+    }
+
+    async fn rel_type(&self, rel_type: RelType) -> Result<DFDataFrame, SparkError> {
+        let df = match rel_type {
+            RelType::Read(read) => self.read(read).await?,
+            RelType::Sql(sql) => {
+                #[cfg(not(feature = "spark-4"))]
+                let has_args = !sql.args.is_empty() || !sql.pos_args.is_empty();
+                #[cfg(feature = "spark-4")]
+                let has_args = !sql.named_arguments.is_empty() || !sql.pos_arguments.is_empty();
+
+                if has_args {
+                    return Err(unimplemented("SQL with parameters"));
+                }
+
+                self.ctx.sql(&sql.query).await.map_err(datafusion_error)?
+            }
+            RelType::LocalRelation(local) => self.local_relation(local)?,
+            RelType::Range(range) => self.range(range)?,
+            RelType::Project(project) => {
+                let df = self.input(project.input).await?;
+
+                let exprs = self.exprs(project.expressions, df.schema())?;
+
+                df.select(exprs).map_err(datafusion_error)?
+            }
+            RelType::Filter(filter) => {
+                let df = self.input(filter.input).await?;
+
+                let condition = filter
+                    .condition
+                    .ok_or_else(|| missing("Filter condition"))?;
+                let condition = self.expr(condition, df.schema())?;
+
+                df.filter(condition).map_err(datafusion_error)?
+            }
+            RelType::Join(join) => self.join(*join).await?,
+            RelType::SetOp(set_op) => self.set_op(*set_op).await?,
+            RelType::Sort(sort) => {
+                let df = self.input(sort.input).await?;
+
+                let order = sort
+                    .order
+                    .into_iter()
+                    .map(|order| self.sort_order(order, df.schema()))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                df.sort(order).map_err(datafusion_error)?
+            }
+            RelType::Limit(limit) => self
+                .input(limit.input)
+                .await?
+                .limit(0, Some(limit.limit.max(0) as usize))
+                .map_err(datafusion_error)?,
+            RelType::Offset(offset) => self
+                .input(offset.input)
+                .await?
+                .limit(offset.offset.max(0) as usize, None)
+                .map_err(datafusion_error)?,
+            RelType::Tail(tail) => {
+                let df = self.input(tail.input).await?;
+
+                let count = df.clone().count().await.map_err(datafusion_error)?;
+                let skip = count.saturating_sub(tail.limit.max(0) as usize);
+
+                df.limit(skip, None).map_err(datafusion_error)?
+            }
+            RelType::Aggregate(aggregate) => self.aggregate(*aggregate).await?,
+            RelType::Deduplicate(dedup) => {
+                let df = self.input(dedup.input).await?;
+
+                if dedup.all_columns_as_keys.unwrap_or(false) || dedup.column_names.is_empty() {
+                    df.distinct().map_err(datafusion_error)?
+                } else {
+                    let on = dedup.column_names.iter().map(col).collect();
+                    let select = self.all_columns(df.schema());
+
+                    df.distinct_on(on, select, None).map_err(datafusion_error)?
+                }
+            }
+            RelType::SubqueryAlias(alias) => {
+                let df = self.input(alias.input).await?;
+
+                let plan = DFPlanBuilder::from(df.logical_plan().clone())
+                    .alias(alias.alias.as_str())
+                    .and_then(|builder| builder.build())
+                    .map_err(datafusion_error)?;
+
+                self.ctx
+                    .execute_logical_plan(plan)
+                    .await
+                    .map_err(datafusion_error)?
+            }
+            RelType::ToDf(to_df) => {
+                let df = self.input(to_df.input).await?;
+
+                let exprs = df
+                    .schema()
+                    .columns()
+                    .into_iter()
+                    .zip(to_df.column_names)
+                    .map(|(column, name)| Expr::Column(column).alias(name))
+                    .collect::<Vec<_>>();
+
+                df.select(exprs).map_err(datafusion_error)?
+            }
+            RelType::WithColumns(with_columns) => {
+                let mut df = self.input(with_columns.input).await?;
+
+                for alias in with_columns.aliases {
+                    let name = alias.name.join(".");
+                    let expr = alias.expr.ok_or_else(|| missing("Column expression"))?;
+                    let expr = self.expr(*expr, df.schema())?;
+
+                    df = df.with_column(&name, expr).map_err(datafusion_error)?;
+                }
+
+                df
+            }
+            RelType::WithColumnsRenamed(renamed) => {
+                let mut df = self.input(renamed.input).await?;
+
+                #[cfg(not(feature = "spark-4"))]
+                let renames = renamed.rename_columns_map;
+                #[cfg(feature = "spark-4")]
+                let renames = renamed
+                    .renames
+                    .into_iter()
+                    .map(|rename| (rename.col_name, rename.new_col_name));
+
+                for (old, new) in renames {
+                    df = df
+                        .with_column_renamed(old.as_str(), new.as_str())
+                        .map_err(datafusion_error)?;
+                }
+
+                df
+            }
+            RelType::Drop(drop) => {
+                let df = self.input(drop.input).await?;
+
+                let mut names = drop.column_names;
+
+                for column in drop.columns {
+                    match column.expr_type {
+                        Some(ExprType::UnresolvedAttribute(attr)) => {
+                            names.push(attr.unparsed_identifier)
+                        }
+                        _ => return Err(unimplemented("Dropping a column by expression")),
+                    }
+                }
+
+                let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+
+                df.drop_columns(&names).map_err(datafusion_error)?
+            }
+            RelType::ShowString(show) => self.show_string(*show).await?,
+            other => {
+                let name = format!("{:?}", other);
+                let name = name.split('(').next().unwrap_or_default();
+
+                return Err(unimplemented(&format!("Relation '{}'", name)));
+            }
+        };
+
+        Ok(df)
+    }
+
+    async fn read(&self, read: spark::Read) -> Result<DFDataFrame, SparkError> {
+        if read.is_streaming {
+            return Err(unimplemented("Streaming read"));
+        }
+
+        match read.read_type.ok_or_else(|| missing("Read type"))? {
+            spark::read::ReadType::NamedTable(table) => self
+                .ctx
+                .table(table.unparsed_identifier.as_str())
+                .await
+                .map_err(datafusion_error),
+            spark::read::ReadType::DataSource(source) => {
+                let format = source.format.unwrap_or_else(|| "parquet".to_string());
+
+                match format.to_lowercase().as_str() {
+                    "csv" => {
+                        let header = source
+                            .options
+                            .get("header")
+                            .map(|val| val.eq_ignore_ascii_case("true"))
+                            .unwrap_or(false);
+
+                        let mut options = CsvReadOptions::new().has_header(header);
+
+                        if let Some(delimiter) = source
+                            .options
+                            .get("delimiter")
+                            .or_else(|| source.options.get("sep"))
+                        {
+                            options =
+                                options.delimiter(*delimiter.as_bytes().first().unwrap_or(&b','));
+                        }
+
+                        self.ctx
+                            .read_csv(source.paths, options)
+                            .await
+                            .map_err(datafusion_error)
+                    }
+                    "json" => self
+                        .ctx
+                        .read_json(source.paths, NdJsonReadOptions::default())
+                        .await
+                        .map_err(datafusion_error),
+                    other => Err(unimplemented(&format!("Data source format '{}'", other))),
+                }
+            }
+        }
+    }
+
+    fn local_relation(&self, local: spark::LocalRelation) -> Result<DFDataFrame, SparkError> {
+        let data = match local.data {
+            Some(data) => data,
+            None => return self.ctx.read_empty().map_err(datafusion_error),
+        };
+
+        let reader = StreamReader::try_new(data.as_slice(), None)?;
+
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+
+        self.ctx.read_batches(batches).map_err(datafusion_error)
+    }
+
+    fn range(&self, range: spark::Range) -> Result<DFDataFrame, SparkError> {
+        if range.step == 0 {
+            return Err(SparkError::InvalidArgument(
+                "Range step can not be 0".to_string(),
+            ));
+        }
+
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "id",
+            ArrowDataType::Int64,
+            false,
+        )]));
+
+        let partition = RangePartition {
+            schema: schema.clone(),
+            start: range.start.unwrap_or(0),
+            end: range.end,
+            step: range.step,
+        };
+
+        let table =
+            StreamingTable::try_new(schema, vec![Arc::new(partition)]).map_err(datafusion_error)?;
+
+        self.ctx
+            .read_table(Arc::new(table))
+            .map_err(datafusion_error)
+    }
+
+    async fn join(&self, join: spark::Join) -> Result<DFDataFrame, SparkError> {
+        let left = self.input(join.left).await?;
+        let right = self.input(join.right).await?;
+
+        let join_type = match spark::join::JoinType::try_from(join.join_type)
+            .unwrap_or(spark::join::JoinType::Inner)
+        {
+            spark::join::JoinType::Unspecified
+            | spark::join::JoinType::Inner
+            | spark::join::JoinType::Cross => JoinType::Inner,
+            spark::join::JoinType::FullOuter => JoinType::Full,
+            spark::join::JoinType::LeftOuter => JoinType::Left,
+            spark::join::JoinType::RightOuter => JoinType::Right,
+            spark::join::JoinType::LeftAnti => JoinType::LeftAnti,
+            spark::join::JoinType::LeftSemi => JoinType::LeftSemi,
+        };
+
+        if !join.using_columns.is_empty() {
+            let columns: Vec<&str> = join.using_columns.iter().map(|c| c.as_str()).collect();
+
+            return left
+                .join(right, join_type, &columns, &columns, None)
+                .map_err(datafusion_error);
+        }
+
+        let on = match join.join_condition {
+            Some(condition) => {
+                let schema = left
+                    .schema()
+                    .join(right.schema())
+                    .map_err(datafusion_error)?;
+
+                vec![self.expr(condition, &schema)?]
+            }
+            None => vec![],
+        };
+
+        left.join_on(right, join_type, on).map_err(datafusion_error)
+    }
+
+    async fn set_op(&self, set_op: spark::SetOperation) -> Result<DFDataFrame, SparkError> {
+        if set_op.by_name.unwrap_or(false) {
+            return Err(unimplemented("Set operation by name"));
+        }
+
+        let left = self.input(set_op.left_input).await?;
+        let right = self.input(set_op.right_input).await?;
+
+        let is_all = set_op.is_all.unwrap_or(false);
+
+        let left_plan = left.logical_plan().clone();
+        let right_plan = right.logical_plan().clone();
+
+        let plan = match spark::set_operation::SetOpType::try_from(set_op.set_op_type)
+            .unwrap_or(spark::set_operation::SetOpType::Unspecified)
+        {
+            spark::set_operation::SetOpType::Union if is_all => {
+                return left.union(right).map_err(datafusion_error)
+            }
+            spark::set_operation::SetOpType::Union => {
+                return left.union_distinct(right).map_err(datafusion_error)
+            }
+            spark::set_operation::SetOpType::Intersect => {
+                DFPlanBuilder::intersect(left_plan, right_plan, is_all)
+            }
+            spark::set_operation::SetOpType::Except => {
+                DFPlanBuilder::except(left_plan, right_plan, is_all)
+            }
+            spark::set_operation::SetOpType::Unspecified => {
+                return Err(missing("Set operation type"))
+            }
+        }
+        .map_err(datafusion_error)?;
+
+        self.ctx
+            .execute_logical_plan(plan)
+            .await
+            .map_err(datafusion_error)
+    }
+
+    async fn aggregate(&self, aggregate: spark::Aggregate) -> Result<DFDataFrame, SparkError> {
+        match spark::aggregate::GroupType::try_from(aggregate.group_type)
+            .unwrap_or(spark::aggregate::GroupType::Groupby)
+        {
+            spark::aggregate::GroupType::Unspecified | spark::aggregate::GroupType::Groupby => {}
+            other => return Err(unimplemented(&format!("{:?} aggregation", other))),
+        }
+
+        let df = self.input(aggregate.input).await?;
+
+        let group = self.exprs(aggregate.grouping_expressions, df.schema())?;
+        let aggr = self.exprs(aggregate.aggregate_expressions, df.schema())?;
+
+        df.aggregate(group, aggr).map_err(datafusion_error)
+    }
+
+    async fn show_string(&self, show: spark::ShowString) -> Result<DFDataFrame, SparkError> {
+        if show.vertical {
+            return Err(unimplemented("Vertical show"));
+        }
+
+        let batches = self
+            .input(show.input)
+            .await?
+            .limit(0, Some(show.num_rows.max(0) as usize))
+            .map_err(datafusion_error)?
+            .collect()
+            .await
+            .map_err(datafusion_error)?;
+
+        let table = arrow::util::pretty::pretty_format_batches(&batches)?.to_string();
+
+        let show_string: ArrayRef = Arc::new(StringArray::from(vec![table]));
+
+        let batch = RecordBatch::try_from_iter(vec![("show_string", show_string)])?;
+
+        self.ctx.read_batch(batch).map_err(datafusion_error)
+    }
+
+    fn all_columns(&self, schema: &DFSchema) -> Vec<Expr> {
+        schema.columns().into_iter().map(Expr::Column).collect()
+    }
+
+    fn exprs(
+        &self,
+        exprs: Vec<spark::Expression>,
+        schema: &DFSchema,
+    ) -> Result<Vec<Expr>, SparkError> {
+        let mut translated = vec![];
+
+        for expr in exprs {
+            match expr.expr_type {
+                // a star is expanded to all of the columns of the input
+                Some(ExprType::UnresolvedStar(star)) if star.unparsed_target.is_none() => {
+                    translated.extend(self.all_columns(schema))
+                }
+                _ => translated.push(self.expr(expr, schema)?),
+            }
+        }
+
+        Ok(translated)
+    }
+
+    fn sort_order(
+        &self,
+        order: spark::expression::SortOrder,
+        schema: &DFSchema,
+    ) -> Result<SortExpr, SparkError> {
+        use spark::expression::sort_order::{NullOrdering, SortDirection};
+
+        let child = order.child.ok_or_else(|| missing("Sort expression"))?;
+        let expr = self.expr(*child, schema)?;
+
+        let asc = !matches!(
+            SortDirection::try_from(order.direction),
+            Ok(SortDirection::Descending)
+        );
+
+        // spark sorts nulls first in ascending order, and last in descending order
+        let nulls_first = match NullOrdering::try_from(order.null_ordering) {
+            Ok(NullOrdering::SortNullsFirst) => true,
+            Ok(NullOrdering::SortNullsLast) => false,
+            _ => asc,
+        };
+
+        Ok(expr.sort(asc, nulls_first))
+    }
+
+    pub(super) fn expr(
+        &self,
+        expr: spark::Expression,
+        schema: &DFSchema,
+    ) -> Result<Expr, SparkError> {
+        let expr_type = expr.expr_type.ok_or_else(|| missing("Expression type"))?;
+
+        let expr = match expr_type {
+            ExprType::Literal(literal) => lit(self.literal(literal)?),
+            ExprType::UnresolvedAttribute(attr) => col(attr.unparsed_identifier.as_str()),
+            ExprType::UnresolvedFunction(func) => self.function(func, schema)?,
+            ExprType::ExpressionString(expr) => self
+                .ctx
+                .parse_sql_expr(&expr.expression, schema)
+                .map_err(datafusion_error)?,
+            ExprType::Alias(alias) => {
+                let expr = alias.expr.ok_or_else(|| missing("Alias expression"))?;
+
+                self.expr(*expr, schema)?.alias(alias.name.join("."))
+            }
+            ExprType::Cast(cast) => {
+                let expr = cast.expr.ok_or_else(|| missing("Cast expression"))?;
+                let expr = self.expr(*expr, schema)?;
+
+                let data_type = match cast.cast_to_type {
+                    Some(spark::expression::cast::CastToType::TypeStr(name)) => {
+                        arrow_type_from_str(&name)?
+                    }
+                    Some(spark::expression::cast::CastToType::Type(data_type)) => {
+                        arrow_type(&data_type)?
+                    }
+                    None => return Err(missing("Cast type")),
+                };
+
+                Expr::Cast(Cast::new(Box::new(expr), data_type))
+            }
+            ExprType::SortOrder(_) => {
+                return Err(unimplemented("Sort order outside of a sort"));
+            }
+            other => {
+                let name = format!("{:?}", other);
+                let name = name.split('(').next().unwrap_or_default();
+
+                return Err(unimplemented(&format!("Expression '{}'", name)));
+            }
+        };
+
+        Ok(expr)
+    }
+
+    fn function(
+        &self,
+        func: spark::expression::UnresolvedFunction,
+        schema: &DFSchema,
+    ) -> Result<Expr, SparkError> {
+        let name = func.function_name.as_str();
+
+        // aliases are not allowed within function arguments, e.g. `count(lit(1).alias("count"))`
+        let mut args = self
+            .exprs(func.arguments, schema)?
+            .into_iter()
+            .map(|arg| match arg {
+                Expr::Alias(alias) => *alias.expr,
+                arg => arg,
+            })
+            .collect::<Vec<_>>();
+
+        let binary_op = match name {
+            "+" => Some(Operator::Plus),
+            "-" => Some(Operator::Minus),
+            "*" => Some(Operator::Multiply),
+            "/" => Some(Operator::Divide),
+            "%" => Some(Operator::Modulo),
+            "==" => Some(Operator::Eq),
+            "!=" => Some(Operator::NotEq),
+            "<" => Some(Operator::Lt),
+            "<=" => Some(Operator::LtEq),
+            ">" => Some(Operator::Gt),
+            ">=" => Some(Operator::GtEq),
+            "and" => Some(Operator::And),
+            "or" => Some(Operator::Or),
+            "&" => Some(Operator::BitwiseAnd),
+            "|" => Some(Operator::BitwiseOr),
+            "^" => Some(Operator::BitwiseXor),
+            _ => None,
+        };
+
+        if let Some(op) = binary_op {
+            if args.len() != 2 {
+                return Err(SparkError::InvalidArgument(format!(
+                    "'{}' requires 2 arguments",
+                    name
+                )));
+            }
+
+            let right = args.pop().unwrap();
+            let left = args.pop().unwrap();
+
+            return Ok(binary_expr(left, op, right));
+        }
+
+        let unary = |mut args: Vec<Expr>| {
+            args.pop().ok_or_else(|| {
+                SparkError::InvalidArgument(format!("'{}' requires an argument", name))
+            })
+        };
+
+        match name {
+            "not" => return Ok(!unary(args)?),
+            "negative" => return Ok(Expr::Negative(Box::new(unary(args)?))),
+            "isnull" => return Ok(unary(args)?.is_null()),
+            "isnotnull" => return Ok(unary(args)?.is_not_null()),
+            "in" if !args.is_empty() => {
+                let expr = args.remove(0);
+                return Ok(expr.in_list(args, false));
+            }
+            "like" | "ilike" if args.len() == 2 => {
+                let pattern = args.pop().unwrap();
+                let expr = args.pop().unwrap();
+
+                return Ok(match name {
+                    "like" => expr.like(pattern),
+                    _ => expr.ilike(pattern),
+                });
+            }
+            _ => {}
+        }
+
+        let name = FUNCTION_ALIASES
+            .iter()
+            .find(|(spark_name, _)| spark_name.eq_ignore_ascii_case(name))
+            .map(|(_, df_name)| *df_name)
+            .unwrap_or(name)
+            .to_lowercase();
+
+        if let Ok(udaf) = self.ctx.udaf(&name) {
+            let expr = udaf.call(args);
+
+            if func.is_distinct || func.function_name == "count_distinct" {
+                return expr.distinct().build().map_err(datafusion_error);
+            }
+
+            return Ok(expr);
+        }
+
+        match self.ctx.udf(&name) {
+            Ok(udf) => Ok(udf.call(args)),
+            Err(_) => Err(unimplemented(&format!("Function '{}'", func.function_name))),
+        }
+    }
+
+    fn literal(&self, literal: spark::expression::Literal) -> Result<ScalarValue, SparkError> {
+        let literal_type = literal
+            .literal_type
+            .ok_or_else(|| missing("Literal type"))?;
+
+        let value = match literal_type {
+            LiteralType::Null(_) => ScalarValue::Null,
+            LiteralType::Binary(val) => ScalarValue::Binary(Some(val)),
+            LiteralType::Boolean(val) => ScalarValue::Boolean(Some(val)),
+            LiteralType::Byte(val) => ScalarValue::Int8(Some(val as i8)),
+            LiteralType::Short(val) => ScalarValue::Int16(Some(val as i16)),
+            LiteralType::Integer(val) => ScalarValue::Int32(Some(val)),
+            LiteralType::Long(val) => ScalarValue::Int64(Some(val)),
+            LiteralType::Float(val) => ScalarValue::Float32(Some(val)),
+            LiteralType::Double(val) => ScalarValue::Float64(Some(val)),
+            LiteralType::String(val) => ScalarValue::Utf8(Some(val)),
+            LiteralType::Date(val) => ScalarValue::Date32(Some(val)),
+            LiteralType::Timestamp(val) => {
+                ScalarValue::TimestampMicrosecond(Some(val), Some(Arc::from("UTC")))
+            }
+            LiteralType::TimestampNtz(val) => ScalarValue::TimestampMicrosecond(Some(val), None),
+            other => {
+                let name = format!("{:?}", other);
+                let name = name.split('(').next().unwrap_or_default();
+
+                return Err(unimplemented(&format!("Literal '{}'", name)));
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+/// Arrow type for a Spark type name used in `Column::cast`
+/// The `id` column of a [spark::Range], generated one batch at a time when the plan runs
+#[derive(Debug)]
+struct RangePartition {
+    schema: SchemaRef,
+    start: i64,
+    end: i64,
+    step: i64,
+}
+
+impl PartitionStream for RangePartition {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let batch_size = ctx.session_config().batch_size();
+        let schema = self.schema.clone();
+        let (end, step) = (self.end, self.step);
+
+        // the next value stays `None` once adding the step overflows an i64
+        let mut next = Some(self.start);
+
+        let batches = std::iter::from_fn(move || {
+            let mut values = Vec::with_capacity(batch_size);
+
+            while let Some(current) =
+                next.filter(|c| (step > 0 && *c < end) || (step < 0 && *c > end))
+            {
+                values.push(current);
+                next = current.checked_add(step);
+
+                if values.len() == batch_size {
+                    break;
+                }
+            }
+
+            if values.is_empty() {
+                return None;
+            }
+
+            let id: ArrayRef = Arc::new(Int64Array::from(values));
+
+            Some(RecordBatch::try_new(schema.clone(), vec![id]).map_err(DataFusionError::from))
+        });
+
+        Box::pin(RecordBatchStreamAdapter::new(
+            self.schema.clone(),
+            stream::iter(batches),
+        ))
+    }
+}
+
+fn arrow_type_from_str(name: &str) -> Result<ArrowDataType, SparkError> {
+    let data_type = match name.trim().to_lowercase().as_str() {
+        "boolean" | "bool" => ArrowDataType::Boolean,
+        "tinyint" | "byte" => ArrowDataType::Int8,
+        "smallint" | "short" => ArrowDataType::Int16,
+        "int" | "integer" => ArrowDataType::Int32,
+        "bigint" | "long" => ArrowDataType::Int64,
+        "float" | "real" => ArrowDataType::Float32,
+        "double" => ArrowDataType::Float64,
+        "string" | "str" => ArrowDataType::Utf8,
+        "binary" => ArrowDataType::Binary,
+        "date" => ArrowDataType::Date32,
+        "timestamp" => ArrowDataType::Timestamp(TimeUnit::Microsecond, Some(Arc::from("UTC"))),
+        "timestamp_ntz" => ArrowDataType::Timestamp(TimeUnit::Microsecond, None),
+        other => return Err(unimplemented(&format!("Cast to '{}'", other))),
+    };
+
+    Ok(data_type)
+}
+
+/// Arrow type for a Spark [spark::DataType]
+fn arrow_type(data_type: &spark::DataType) -> Result<ArrowDataType, SparkError> {
+    use spark::data_type::Kind;
+
+    let kind = data_type
+        .kind
+        .as_ref()
+        .ok_or_else(|| missing("Data type"))?;
+
+    let data_type = match kind {
+        Kind::Null(_) => ArrowDataType::Null,
+        Kind::Binary(_) => ArrowDataType::Binary,
+        Kind::Boolean(_) => ArrowDataType::Boolean,
+        Kind::Byte(_) => ArrowDataType::Int8,
+        Kind::Short(_) => ArrowDataType::Int16,
+        Kind::Integer(_) => ArrowDataType::Int32,
+        Kind::Long(_) => ArrowDataType::Int64,
+        Kind::Float(_) => ArrowDataType::Float32,
+        Kind::Double(_) => ArrowDataType::Float64,
+        Kind::String(_) | Kind::Char(_) | Kind::VarChar(_) => ArrowDataType::Utf8,
+        Kind::Date(_) => ArrowDataType::Date32,
+        Kind::Timestamp(_) => {
+            ArrowDataType::Timestamp(TimeUnit::Microsecond, Some(Arc::from("UTC")))
+        }
+        Kind::TimestampNtz(_) => ArrowDataType::Timestamp(TimeUnit::Microsecond, None),
+        Kind::Array(array) => {
+            let element = array
+                .element_type
+                .as_ref()
+                .ok_or_else(|| missing("Array element type"))?;
+
+            ArrowDataType::List(Arc::new(Field::new(
+                "element",
+                arrow_type(element)?,
+                array.contains_null,
+            )))
+        }
+        other => {
+            let name = format!("{:?}", other);
+            let name = name.split('(').next().unwrap_or_default();
+
+            return Err(unimplemented(&format!("Data type '{}'", name)));
+        }
+    };
+
+    Ok(data_type)
+}
+
+/// Spark [spark::DataType] for an Arrow type, used for schema responses
+pub(super) fn spark_type(data_type: &ArrowDataType) -> Result<spark::DataType, SparkError> {
+    use spark::data_type as dt;
+    use spark::data_type::Kind;
+
+    let kind = match data_type {
+        ArrowDataType::Null => Kind::Null(dt::Null::default()),
+        ArrowDataType::Boolean => Kind::Boolean(dt::Boolean::default()),
+        ArrowDataType::Int8 | ArrowDataType::UInt8 => Kind::Byte(dt::Byte::default()),
+        ArrowDataType::Int16 | ArrowDataType::UInt16 => Kind::Short(dt::Short::default()),
+        ArrowDataType::Int32 | ArrowDataType::UInt32 => Kind::Integer(dt::Integer::default()),
+        ArrowDataType::Int64 | ArrowDataType::UInt64 => Kind::Long(dt::Long::default()),
+        ArrowDataType::Float32 => Kind::Float(dt::Float::default()),
+        ArrowDataType::Float64 => Kind::Double(dt::Double::default()),
+        ArrowDataType::Utf8 | ArrowDataType::LargeUtf8 | ArrowDataType::Utf8View => {
+            Kind::String(dt::String::default())
+        }
+        ArrowDataType::Binary | ArrowDataType::LargeBinary | ArrowDataType::BinaryView => {
+            Kind::Binary(dt::Binary::default())
+        }
+        ArrowDataType::Date32 | ArrowDataType::Date64 => Kind::Date(dt::Date::default()),
+        ArrowDataType::Timestamp(_, Some(_)) => Kind::Timestamp(dt::Timestamp::default()),
+        ArrowDataType::Timestamp(_, None) => Kind::TimestampNtz(dt::TimestampNtz::default()),
+        ArrowDataType::Decimal128(precision, scale)
+        | ArrowDataType::Decimal256(precision, scale) => Kind::Decimal(dt::Decimal {
+            scale: Some(*scale as i32),
+            precision: Some(*precision as i32),
+            type_variation_reference: 0,
+        }),
+        ArrowDataType::List(field) | ArrowDataType::LargeList(field) => {
+            Kind::Array(Box::new(dt::Array {
+                element_type: Some(Box::new(spark_type(field.data_type())?)),
+                contains_null: field.is_nullable(),
+                type_variation_reference: 0,
+            }))
+        }
+        ArrowDataType::Struct(fields) => {
+            let fields = fields
+                .iter()
+                .map(|field| {
+                    Ok(dt::StructField {
+                        name: field.name().to_string(),
+                        data_type: Some(spark_type(field.data_type())?),
+                        nullable: field.is_nullable(),
+                        metadata: None,
+                    })
+                })
+                .collect::<Result<Vec<_>, SparkError>>()?;
+
+            Kind::Struct(dt::Struct {
+                fields,
+                type_variation_reference: 0,
+            })
+        }
+        other => return Err(unimplemented(&format!("Arrow type '{}'", other))),
+    };
+
+    Ok(spark::DataType { kind: Some(kind) })
+}
+
+/// Spark struct type for the fields of a DataFusion schema
+pub(super) fn spark_schema(schema: &DFSchema) -> Result<spark::DataType, SparkError> {
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|field| field.as_ref().clone())
+        .collect::<Vec<Field>>();
+
+    spark_type(&ArrowDataType::Struct(fields.into()))
+}
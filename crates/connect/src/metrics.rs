@@ -0,0 +1,615 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Execution metrics of the physical plan operators of a query, and the named
+//! metrics observed with `DataFrame::observe`
+
+use std::collections::HashMap;
+#[cfg(feature = "spark-4")]
+use std::sync::{Arc, Mutex};
+
+use crate::spark;
+
+/// Kind of a [MetricValue], which determines its unit
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MetricType {
+    /// Count, e.g. `number of output rows`
+    Sum,
+    /// Size in bytes
+    Size,
+    /// Duration in milliseconds
+    Timing,
+    /// Duration in nanoseconds
+    NsTiming,
+    /// Average, e.g. the average hash probes per key
+    Average,
+    /// Metric type not known by this client
+    Other(String),
+}
+
+impl From<&str> for MetricType {
+    fn from(metric_type: &str) -> Self {
+        match metric_type {
+            "sum" => MetricType::Sum,
+            "size" => MetricType::Size,
+            "timing" => MetricType::Timing,
+            "nsTiming" => MetricType::NsTiming,
+            "average" => MetricType::Average,
+            other => MetricType::Other(other.to_string()),
+        }
+    }
+}
+
+/// Single execution metric of a plan operator
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MetricValue {
+    pub name: String,
+    pub value: i64,
+    pub metric_type: MetricType,
+}
+
+impl From<spark::execute_plan_response::metrics::MetricValue> for MetricValue {
+    fn from(value: spark::execute_plan_response::metrics::MetricValue) -> Self {
+        MetricValue {
+            metric_type: MetricType::from(value.metric_type.as_str()),
+            name: value.name,
+            value: value.value,
+        }
+    }
+}
+
+/// Execution metrics of a single operator in the physical plan
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlanMetrics {
+    /// Name of the operator, e.g. `HashAggregate`
+    pub name: String,
+    /// Id of the operator within the physical plan
+    pub plan_id: i64,
+    /// Id of the parent operator, or `None` for the root of the plan
+    pub parent: Option<i64>,
+    /// Metrics of the operator by key
+    pub metrics: HashMap<String, MetricValue>,
+}
+
+impl PlanMetrics {
+    /// Metric with the key, e.g. `numOutputRows`
+    pub fn get(&self, key: &str) -> Option<&MetricValue> {
+        self.metrics.get(key)
+    }
+}
+
+impl From<spark::execute_plan_response::metrics::MetricObject> for PlanMetrics {
+    fn from(object: spark::execute_plan_response::metrics::MetricObject) -> Self {
+        PlanMetrics {
+            name: object.name,
+            plan_id: object.plan_id,
+            // the root operator is sent with a negative parent id
+            parent: (object.parent >= 0).then_some(object.parent),
+            metrics: object
+                .execution_metrics
+                .into_iter()
+                .map(|(key, value)| (key, value.into()))
+                .collect(),
+        }
+    }
+}
+
+/// Execution metrics of the physical plan of a query, as reported by the server once
+/// the query completes
+///
+/// The operators form a tree, from the [root](QueryMetrics::root) down through their
+/// [children](QueryMetrics::children).
+///
+/// # Example
+/// ```rust
+/// let (records, metrics) = df.collect_with_metrics().await?;
+///
+/// for node in metrics.nodes() {
+///     if let Some(rows) = node.get("numOutputRows") {
+///         println!("{} ({}): {} rows", node.name, node.plan_id, rows.value);
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueryMetrics {
+    nodes: Vec<PlanMetrics>,
+}
+
+impl QueryMetrics {
+    /// All operators of the plan, with each parent before its children
+    pub fn nodes(&self) -> &[PlanMetrics] {
+        &self.nodes
+    }
+
+    /// Returns `true` if the server did not report any metrics
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Root operator of the plan
+    pub fn root(&self) -> Option<&PlanMetrics> {
+        self.nodes.iter().find(|node| node.parent.is_none())
+    }
+
+    /// Operator with the plan id
+    pub fn node(&self, plan_id: i64) -> Option<&PlanMetrics> {
+        self.nodes.iter().find(|node| node.plan_id == plan_id)
+    }
+
+    /// Direct children of the operator with the plan id
+    pub fn children(&self, plan_id: i64) -> impl Iterator<Item = &PlanMetrics> {
+        self.nodes
+            .iter()
+            .filter(move |node| node.parent == Some(plan_id))
+    }
+}
+
+impl From<spark::execute_plan_response::Metrics> for QueryMetrics {
+    fn from(metrics: spark::execute_plan_response::Metrics) -> Self {
+        QueryMetrics {
+            nodes: metrics.metrics.into_iter().map(PlanMetrics::from).collect(),
+        }
+    }
+}
+
+/// Value of an observed metric
+#[derive(Clone, Debug, PartialEq)]
+pub enum ObservedValue {
+    Null,
+    Boolean(bool),
+    /// Byte, short, integer or long value
+    Long(i64),
+    /// Float or double value
+    Double(f64),
+    /// String representation of a decimal value
+    Decimal(String),
+    String(String),
+    Binary(Vec<u8>),
+    /// Days since the UNIX epoch
+    Date(i32),
+    /// Microseconds since the UNIX epoch
+    Timestamp(i64),
+    Interval {
+        months: i32,
+        days: i32,
+        microseconds: i64,
+    },
+    Array(Vec<ObservedValue>),
+    Map(Vec<(ObservedValue, ObservedValue)>),
+    Struct(Vec<ObservedValue>),
+}
+
+impl ObservedValue {
+    /// Value as an `i64`, if it is an integral value
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            ObservedValue::Long(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Value as an `f64`, if it is a numeric value
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            ObservedValue::Long(value) => Some(*value as f64),
+            ObservedValue::Double(value) => Some(*value),
+            ObservedValue::Decimal(value) => value.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl From<spark::expression::Literal> for ObservedValue {
+    fn from(literal: spark::expression::Literal) -> Self {
+        use spark::expression::literal::LiteralType;
+
+        let Some(literal_type) = literal.literal_type else {
+            return ObservedValue::Null;
+        };
+
+        let collect = |elements: Vec<spark::expression::Literal>| {
+            elements.into_iter().map(ObservedValue::from).collect()
+        };
+
+        match literal_type {
+            LiteralType::Null(_) => ObservedValue::Null,
+            LiteralType::Binary(value) => ObservedValue::Binary(value),
+            LiteralType::Boolean(value) => ObservedValue::Boolean(value),
+            LiteralType::Byte(value) | LiteralType::Short(value) | LiteralType::Integer(value) => {
+                ObservedValue::Long(value as i64)
+            }
+            LiteralType::Long(value) => ObservedValue::Long(value),
+            LiteralType::Float(value) => ObservedValue::Double(value as f64),
+            LiteralType::Double(value) => ObservedValue::Double(value),
+            LiteralType::Decimal(value) => ObservedValue::Decimal(value.value),
+            LiteralType::String(value) => ObservedValue::String(value),
+            LiteralType::Date(value) => ObservedValue::Date(value),
+            LiteralType::Timestamp(value) | LiteralType::TimestampNtz(value) => {
+                ObservedValue::Timestamp(value)
+            }
+            LiteralType::CalendarInterval(value) => ObservedValue::Interval {
+                months: value.months,
+                days: value.days,
+                microseconds: value.microseconds,
+            },
+            LiteralType::YearMonthInterval(months) => ObservedValue::Interval {
+                months,
+                days: 0,
+                microseconds: 0,
+            },
+            LiteralType::DayTimeInterval(microseconds) => ObservedValue::Interval {
+                months: 0,
+                days: 0,
+                microseconds,
+            },
+            LiteralType::Array(array) => ObservedValue::Array(collect(array.elements)),
+            LiteralType::Map(map) => ObservedValue::Map(
+                map.keys
+                    .into_iter()
+                    .map(ObservedValue::from)
+                    .zip(map.values.into_iter().map(ObservedValue::from))
+                    .collect(),
+            ),
+            LiteralType::Struct(value) => ObservedValue::Struct(collect(value.elements)),
+            #[cfg(feature = "spark-4")]
+            LiteralType::SpecializedArray(array) => {
+                use spark::expression::literal::specialized_array::ValueType;
+
+                let values = match array.value_type {
+                    Some(ValueType::Bools(v)) => {
+                        v.values.into_iter().map(ObservedValue::Boolean).collect()
+                    }
+                    Some(ValueType::Ints(v)) => v
+                        .values
+                        .into_iter()
+                        .map(|v| ObservedValue::Long(v as i64))
+                        .collect(),
+                    Some(ValueType::Longs(v)) => {
+                        v.values.into_iter().map(ObservedValue::Long).collect()
+                    }
+                    Some(ValueType::Floats(v)) => v
+                        .values
+                        .into_iter()
+                        .map(|v| ObservedValue::Double(v as f64))
+                        .collect(),
+                    Some(ValueType::Doubles(v)) => {
+                        v.values.into_iter().map(ObservedValue::Double).collect()
+                    }
+                    Some(ValueType::Strings(v)) => {
+                        v.values.into_iter().map(ObservedValue::String).collect()
+                    }
+                    None => vec![],
+                };
+
+                ObservedValue::Array(values)
+            }
+            // nanoseconds since midnight
+            #[cfg(feature = "spark-4")]
+            LiteralType::Time(time) => ObservedValue::Long(time.nano),
+            #[cfg(feature = "spark-4")]
+            LiteralType::TimestampNtzNanos(value) => ObservedValue::Timestamp(value.epoch_micros),
+            #[cfg(feature = "spark-4")]
+            LiteralType::TimestampLtzNanos(value) => ObservedValue::Timestamp(value.epoch_micros),
+        }
+    }
+}
+
+impl From<serde_json::Value> for ObservedValue {
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => ObservedValue::Null,
+            serde_json::Value::Bool(value) => ObservedValue::Boolean(value),
+            serde_json::Value::Number(value) => match value.as_i64() {
+                Some(value) => ObservedValue::Long(value),
+                None => ObservedValue::Double(value.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_json::Value::String(value) => ObservedValue::String(value),
+            serde_json::Value::Array(values) => {
+                ObservedValue::Array(values.into_iter().map(ObservedValue::from).collect())
+            }
+            serde_json::Value::Object(values) => {
+                ObservedValue::Struct(values.into_iter().map(|(_, v)| v.into()).collect())
+            }
+        }
+    }
+}
+
+/// Metrics observed by `DataFrame::observe` under a name
+///
+/// The values are in the order of the observed expressions, which is also the order of
+/// the fields in the progress of a streaming query. Spark 3.5 only sends the names of
+/// the values in the progress of a streaming query, so `keys` is empty for metrics of a
+/// batch query, unless the crate is built with the `spark-4` feature.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObservedMetrics {
+    pub name: String,
+    pub keys: Vec<String>,
+    pub values: Vec<ObservedValue>,
+}
+
+impl ObservedMetrics {
+    /// Value with the name, if the names were sent by the server
+    pub fn get(&self, key: &str) -> Option<&ObservedValue> {
+        let idx = self.keys.iter().position(|k| k == key)?;
+        self.values.get(idx)
+    }
+
+    /// Parse the `observedMetrics` object of a streaming query progress
+    pub(crate) fn from_progress(progress: &serde_json::Value) -> HashMap<String, ObservedMetrics> {
+        let Some(observed) = progress
+            .get("observedMetrics")
+            .and_then(|metrics| metrics.as_object())
+        else {
+            return HashMap::new();
+        };
+
+        observed
+            .iter()
+            .map(|(name, row)| {
+                let (keys, values) = row
+                    .as_object()
+                    .map(|row| {
+                        row.iter()
+                            .map(|(key, value)| (key.clone(), ObservedValue::from(value.clone())))
+                            .unzip()
+                    })
+                    .unwrap_or_default();
+
+                let metrics = ObservedMetrics {
+                    name: name.clone(),
+                    keys,
+                    values,
+                };
+
+                (name.clone(), metrics)
+            })
+            .collect()
+    }
+}
+
+impl From<spark::execute_plan_response::ObservedMetrics> for ObservedMetrics {
+    fn from(metrics: spark::execute_plan_response::ObservedMetrics) -> Self {
+        ObservedMetrics {
+            name: metrics.name,
+            #[cfg(not(feature = "spark-4"))]
+            keys: vec![],
+            #[cfg(feature = "spark-4")]
+            keys: metrics.keys,
+            values: metrics
+                .values
+                .into_iter()
+                .map(ObservedValue::from)
+                .collect(),
+        }
+    }
+}
+
+/// Progress of a single stage of an operation
+#[cfg(feature = "spark-4")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StageProgress {
+    pub stage_id: i64,
+    pub num_tasks: i64,
+    pub num_completed_tasks: i64,
+    /// Bytes read by the tasks of the stage so far
+    pub input_bytes_read: i64,
+    pub done: bool,
+}
+
+#[cfg(feature = "spark-4")]
+impl From<spark::execute_plan_response::execution_progress::StageInfo> for StageProgress {
+    fn from(stage: spark::execute_plan_response::execution_progress::StageInfo) -> Self {
+        StageProgress {
+            stage_id: stage.stage_id,
+            num_tasks: stage.num_tasks,
+            num_completed_tasks: stage.num_completed_tasks,
+            input_bytes_read: stage.input_bytes_read,
+            done: stage.done,
+        }
+    }
+}
+
+/// Progress of a running operation, which Spark 4 reports periodically until the
+/// operation completes
+#[cfg(feature = "spark-4")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExecutionProgress {
+    pub operation_id: String,
+    pub stages: Vec<StageProgress>,
+    /// Tasks of the operation currently running on the executors
+    pub num_inflight_tasks: i64,
+}
+
+#[cfg(feature = "spark-4")]
+impl ExecutionProgress {
+    pub(crate) fn new(
+        operation_id: String,
+        progress: spark::execute_plan_response::ExecutionProgress,
+    ) -> Self {
+        ExecutionProgress {
+            operation_id,
+            stages: progress
+                .stages
+                .into_iter()
+                .map(StageProgress::from)
+                .collect(),
+            num_inflight_tasks: progress.num_inflight_tasks,
+        }
+    }
+
+    /// Tasks of all stages, and the tasks completed so far
+    pub fn tasks(&self) -> (i64, i64) {
+        self.stages
+            .iter()
+            .fold((0, 0), |(total, completed), stage| {
+                (
+                    total + stage.num_tasks,
+                    completed + stage.num_completed_tasks,
+                )
+            })
+    }
+}
+
+#[cfg(feature = "spark-4")]
+type ProgressHandler = Arc<dyn Fn(&ExecutionProgress) + Send + Sync>;
+
+/// Handlers registered with `SparkSession::register_progress_handler`, shared by the
+/// clones of a session
+#[cfg(feature = "spark-4")]
+#[derive(Clone, Default)]
+pub(crate) struct ProgressHandlers(Arc<Mutex<Vec<ProgressHandler>>>);
+
+#[cfg(feature = "spark-4")]
+impl ProgressHandlers {
+    pub(crate) fn register(&self, handler: ProgressHandler) {
+        self.0.lock().unwrap().push(handler);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+
+    /// Call every handler, without holding the lock so that a handler can register another
+    pub(crate) fn notify(&self, progress: &ExecutionProgress) {
+        let handlers = self.0.lock().unwrap().clone();
+
+        for handler in handlers {
+            handler(progress);
+        }
+    }
+}
+
+#[cfg(feature = "spark-4")]
+impl std::fmt::Debug for ProgressHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProgressHandlers")
+            .field("handlers", &self.0.lock().unwrap().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use spark::execute_plan_response::metrics;
+
+    fn metric_object(name: &str, plan_id: i64, parent: i64, rows: i64) -> metrics::MetricObject {
+        metrics::MetricObject {
+            name: name.to_string(),
+            plan_id,
+            parent,
+            execution_metrics: HashMap::from([(
+                "numOutputRows".to_string(),
+                metrics::MetricValue {
+                    name: "number of output rows".to_string(),
+                    value: rows,
+                    metric_type: "sum".to_string(),
+                },
+            )]),
+        }
+    }
+
+    #[test]
+    fn test_query_metrics_tree() {
+        let metrics = QueryMetrics::from(spark::execute_plan_response::Metrics {
+            metrics: vec![
+                metric_object("HashAggregate", 3, -1, 1),
+                metric_object("Exchange", 2, 3, 4),
+                metric_object("Range", 0, 2, 100),
+            ],
+        });
+
+        let root = metrics.root().unwrap();
+
+        assert_eq!("HashAggregate", root.name);
+        assert_eq!(None, root.parent);
+
+        let children: Vec<&str> = metrics.children(3).map(|node| node.name.as_str()).collect();
+        assert_eq!(vec!["Exchange"], children);
+
+        let rows = metrics.node(0).unwrap().get("numOutputRows").unwrap();
+
+        assert_eq!(100, rows.value);
+        assert_eq!(MetricType::Sum, rows.metric_type);
+        assert_eq!("number of output rows", rows.name);
+    }
+
+    #[test]
+    fn test_metric_type() {
+        assert_eq!(MetricType::Size, MetricType::from("size"));
+        assert_eq!(MetricType::NsTiming, MetricType::from("nsTiming"));
+        assert_eq!(
+            MetricType::Other("custom".to_string()),
+            MetricType::from("custom")
+        );
+    }
+
+    #[test]
+    fn test_observed_metrics_from_literals() {
+        use spark::expression::literal::LiteralType;
+        use spark::expression::Literal;
+
+        let metrics = ObservedMetrics::from(spark::execute_plan_response::ObservedMetrics {
+            name: "dq".to_string(),
+            values: vec![
+                Literal::from(LiteralType::Long(10)),
+                Literal::from(LiteralType::Integer(2)),
+                Literal::from(LiteralType::Decimal(spark::expression::literal::Decimal {
+                    value: "12.50".to_string(),
+                    precision: None,
+                    scale: None,
+                })),
+                Literal::default(),
+            ],
+            #[cfg(feature = "spark-4")]
+            keys: vec![],
+            #[cfg(feature = "spark-4")]
+            plan_id: 0,
+            #[cfg(feature = "spark-4")]
+            root_error_idx: None,
+            #[cfg(feature = "spark-4")]
+            errors: vec![],
+        });
+
+        assert_eq!("dq", metrics.name);
+        assert_eq!(Some(10), metrics.values[0].as_i64());
+        assert_eq!(Some(2), metrics.values[1].as_i64());
+        assert_eq!(Some(12.5), metrics.values[2].as_f64());
+        assert_eq!(ObservedValue::Null, metrics.values[3]);
+    }
+
+    #[test]
+    fn test_observed_metrics_from_progress() {
+        let progress = serde_json::json!({
+            "batchId": 1,
+            "observedMetrics": {
+                "dq": {"rows": 10, "null_names": 0, "avg_age": 21.5}
+            }
+        });
+
+        let observed = ObservedMetrics::from_progress(&progress);
+        let metrics = observed.get("dq").unwrap();
+
+        assert_eq!(Some(10), metrics.get("rows").and_then(|v| v.as_i64()));
+        assert_eq!(Some(0), metrics.get("null_names").and_then(|v| v.as_i64()));
+        assert_eq!(Some(21.5), metrics.get("avg_age").and_then(|v| v.as_f64()));
+
+        assert_eq!(vec!["rows", "null_names", "avg_age"], metrics.keys);
+        assert_eq!(Some(21.5), metrics.values[2].as_f64());
+    }
+}
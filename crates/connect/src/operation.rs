@@ -0,0 +1,313 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Handles to operations that run on the server in the background
+
+use arrow::record_batch::RecordBatch;
+
+use serde_json::json;
+
+use crate::client::{concat_or_empty, RecordBatchStream, SparkClient};
+use crate::errors::SparkError;
+use crate::spark;
+
+/// Status of an operation as observed locally by its [OperationHandle]
+///
+/// Spark 3.5 does not offer an RPC to query the status of an operation, so the
+/// status only reflects what the handle has observed so far. A handle resumed with
+/// `SparkSession::resume_operation` starts as [OperationStatus::Running], whatever the
+/// state of the operation on the server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OperationStatus {
+    /// Submitted to the server, and not yet observed to be complete
+    Running,
+    /// All results were received from the server
+    Succeeded,
+    /// The operation returned an error
+    Failed,
+    /// The operation was interrupted through the handle
+    Interrupted,
+}
+
+/// Handle to an operation submitted with `DataFrame::submit`
+///
+/// The operation keeps running on the server while the handle is held, and the results can be
+/// collected later with [OperationHandle::wait] or [OperationHandle::reattach]. A handle can be
+/// serialized and resumed in another process connected to the same session with
+/// `SparkSession::resume_operation`.
+///
+/// # Example
+/// ```rust
+/// let mut handle = df.submit().await?;
+///
+/// let serialized = handle.serialize();
+///
+/// let records = handle.wait().await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct OperationHandle {
+    client: SparkClient,
+    operation_id: String,
+    tags: Vec<String>,
+    status: OperationStatus,
+}
+
+impl OperationHandle {
+    pub(crate) fn new(mut client: SparkClient, operation_id: String) -> OperationHandle {
+        // results of a detached operation can only be fetched by reattaching
+        client.use_reattachable_execute = true;
+
+        OperationHandle {
+            tags: client.get_tags().clone(),
+            client,
+            operation_id,
+            status: OperationStatus::Running,
+        }
+    }
+
+    /// Resume a handle serialized with [OperationHandle::serialize]
+    ///
+    /// The client must be connected to the same session the operation was submitted on
+    pub(crate) fn deserialize(
+        client: SparkClient,
+        serialized: &str,
+    ) -> Result<OperationHandle, SparkError> {
+        let value: serde_json::Value = serde_json::from_str(serialized)?;
+
+        let field = |name: &str| {
+            value[name].as_str().map(|v| v.to_string()).ok_or_else(|| {
+                SparkError::InvalidArgument(format!("Operation handle is missing '{}'", name))
+            })
+        };
+
+        let session_id = field("session_id")?;
+
+        if session_id != client.session_id() {
+            return Err(SparkError::SessionNotSameException(format!(
+                "Operation handle belongs to session {}",
+                session_id
+            )));
+        }
+
+        let mut handle = OperationHandle::new(client, field("operation_id")?);
+
+        handle.tags = value["tags"]
+            .as_array()
+            .map(|tags| {
+                tags.iter()
+                    .filter_map(|tag| tag.as_str().map(|t| t.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(handle)
+    }
+
+    /// Serialize the handle so it can be resumed from another process
+    pub fn serialize(&self) -> String {
+        json!({
+            "session_id": self.client.session_id(),
+            "operation_id": self.operation_id,
+            "tags": self.tags,
+        })
+        .to_string()
+    }
+
+    /// Id of the operation on the server
+    pub fn operation_id(&self) -> &str {
+        &self.operation_id
+    }
+
+    /// Tags assigned to the operation when it was submitted
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Status of the operation as observed by this handle, without asking the server
+    ///
+    /// Use [OperationHandle::wait] or [OperationHandle::reattach] to find out the outcome
+    /// of an operation submitted by another handle.
+    pub fn local_status(&self) -> OperationStatus {
+        self.status
+    }
+
+    /// Wait for the operation to complete and return all of its results
+    pub async fn wait(&mut self) -> Result<RecordBatch, SparkError> {
+        let handler = match self.client.reattach_and_fetch(&self.operation_id).await {
+            Ok(handler) => handler,
+            Err(err) => {
+                self.status = OperationStatus::Failed;
+                return Err(err);
+            }
+        };
+
+        self.status = OperationStatus::Succeeded;
+
+        concat_or_empty(&handler.batches)
+    }
+
+    /// Reattach to the operation and return a [RecordBatchStream] over its remaining results
+    pub async fn reattach(&self) -> Result<RecordBatchStream, SparkError> {
+        self.client
+            .clone()
+            .reattach_arrow_stream(&self.operation_id)
+            .await
+    }
+
+    /// Interrupt the operation on the server and release its results.
+    ///
+    /// Returns the ids of the interrupted operations
+    pub async fn interrupt(&mut self) -> Result<Vec<String>, SparkError> {
+        let resp = self
+            .client
+            .interrupt_request(
+                spark::interrupt_request::InterruptType::OperationId,
+                Some(self.operation_id.clone()),
+            )
+            .await?;
+
+        self.client.release_operation(&self.operation_id).await?;
+
+        self.status = OperationStatus::Interrupted;
+
+        Ok(resp.interrupted_ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::functions::*;
+    use crate::SparkSessionBuilder;
+
+    async fn setup() -> crate::SparkSession {
+        println!("SparkSession Setup");
+
+        let connection = "sc://127.0.0.1:15002/;user_id=rust_operation;session_id=1c9e8a41-51b8-43e4-8a4c-3c5d5c6cf1de";
+
+        SparkSessionBuilder::remote(connection)
+            .build()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_operation_wait() -> Result<(), SparkError> {
+        let spark = setup().await;
+
+        let mut handle = spark.range(None, 100, 1, Some(8)).submit().await?;
+
+        assert_eq!(OperationStatus::Running, handle.local_status());
+
+        let records = handle.wait().await?;
+
+        assert_eq!(100, records.num_rows());
+        assert_eq!(OperationStatus::Succeeded, handle.local_status());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_operation_resume() -> Result<(), SparkError> {
+        let mut spark = setup().await;
+
+        spark.add_tag("resumable")?;
+
+        let handle = spark.range(None, 100, 1, Some(8)).submit().await?;
+
+        let mut resumed = spark.resume_operation(&handle.serialize())?;
+
+        assert_eq!(handle.operation_id(), resumed.operation_id());
+        assert_eq!(vec!["resumable".to_string()], resumed.tags());
+
+        let records = resumed.wait().await?;
+
+        assert_eq!(100, records.num_rows());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_operation_resume_mock() -> Result<(), SparkError> {
+        use crate::testing::{MockResponse, MockSparkServer};
+
+        let server = MockSparkServer::start().await?;
+
+        let batch = mock_data();
+
+        // the submitted stream closes right away, so the results are only sent on reattach
+        server.push_execute([MockResponse::Disconnect, MockResponse::Batch(batch.clone())]);
+
+        let mut spark = server.session().await?;
+
+        spark.add_tag("resumable")?;
+
+        let handle = spark.range(None, 3, 1, None).submit().await?;
+
+        let serialized = handle.serialize();
+
+        // a new client connected to the same session, e.g. in another process
+        let other = SparkSessionBuilder::remote(&format!(
+            "{};session_id={}",
+            server.connection_string(),
+            spark.session_id()
+        ))
+        .build()
+        .await?;
+
+        let mut resumed = other.resume_operation(&serialized)?;
+
+        assert_eq!(handle.operation_id(), resumed.operation_id());
+        assert_eq!(vec!["resumable".to_string()], resumed.tags());
+        assert_eq!(OperationStatus::Running, resumed.local_status());
+
+        let records = resumed.wait().await?;
+
+        assert_eq!(batch, records);
+        assert_eq!(OperationStatus::Succeeded, resumed.local_status());
+
+        let requests = server.requests();
+
+        assert_eq!(1, requests.execute.len());
+        assert_eq!(handle.operation_id(), requests.reattach[0].operation_id);
+        Ok(())
+    }
+
+    fn mock_data() -> RecordBatch {
+        let id: arrow::array::ArrayRef = Arc::new(arrow::array::Int64Array::from(vec![0, 1, 2]));
+
+        RecordBatch::try_from_iter(vec![("id", id)]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_operation_interrupt() -> Result<(), SparkError> {
+        let spark = setup().await;
+
+        let mut handle = spark
+            .range(None, 1_000_000_000, 1, Some(1))
+            .select([sum(col("id") * col("id"))])
+            .submit()
+            .await?;
+
+        let interrupted = handle.interrupt().await?;
+
+        assert_eq!(vec![handle.operation_id().to_string()], interrupted);
+        assert_eq!(OperationStatus::Interrupted, handle.local_status());
+        Ok(())
+    }
+}
@@ -17,7 +17,6 @@
 
 //! Logical Plan representation
 
-use std::collections::HashMap;
 use std::sync::atomic::AtomicI64;
 use std::sync::atomic::Ordering::SeqCst;
 
@@ -127,6 +126,8 @@ impl LogicalPlanBuilder {
             grouping_expressions: grouping_cols,
             aggregate_expressions: VecExpression::from_iter(agg_expression).expr,
             pivot,
+            #[cfg(feature = "spark-4")]
+            grouping_sets: vec![],
         };
 
         let agg_rel = RelType::Aggregate(Box::new(agg));
@@ -154,6 +155,19 @@ impl LogicalPlanBuilder {
         LogicalPlanBuilder::from(RelType::Unpivot(Box::new(unpivot)))
     }
 
+    #[cfg(feature = "spark-4")]
+    pub fn table_valued_function(
+        function_name: &str,
+        arguments: Vec<spark::Expression>,
+    ) -> LogicalPlanBuilder {
+        let tvf = spark::UnresolvedTableValuedFunction {
+            function_name: function_name.to_string(),
+            arguments,
+        };
+
+        LogicalPlanBuilder::from(RelType::UnresolvedTableValuedFunction(tvf))
+    }
+
     pub fn local_relation(batch: &RecordBatch) -> Result<LogicalPlanBuilder, SparkError> {
         let serialized = serialize(batch)?;
 
@@ -167,6 +181,15 @@ impl LogicalPlanBuilder {
         Ok(LogicalPlanBuilder::from(local_rel))
     }
 
+    /// Reference a serialized [spark::LocalRelation] already uploaded to the session cache
+    pub fn cached_local_relation(hash: &str) -> LogicalPlanBuilder {
+        let cached_rel = spark::CachedLocalRelation {
+            hash: hash.to_string(),
+        };
+
+        LogicalPlanBuilder::from(RelType::CachedLocalRelation(cached_rel))
+    }
+
     pub fn corr(self, col1: impl AsRef<str>, col2: impl AsRef<str>) -> LogicalPlanBuilder {
         let corr = spark::StatCorr {
             input: self.relation_input(),
@@ -352,6 +375,16 @@ impl LogicalPlanBuilder {
         LogicalPlanBuilder::from(to_df_rel)
     }
 
+    #[cfg(feature = "spark-4")]
+    pub fn transpose(self, index_columns: Vec<spark::Expression>) -> LogicalPlanBuilder {
+        let transpose = spark::Transpose {
+            input: self.relation_input(),
+            index_columns,
+        };
+
+        LogicalPlanBuilder::from(RelType::Transpose(Box::new(transpose)))
+    }
+
     fn set_operation(
         self,
         other: LogicalPlanBuilder,
@@ -546,6 +579,26 @@ impl LogicalPlanBuilder {
         LogicalPlanBuilder::from(join_rel)
     }
 
+    #[cfg(feature = "spark-4")]
+    pub fn lateral_join<T>(
+        self,
+        right: LogicalPlanBuilder,
+        join_condition: Option<T>,
+        join_type: spark::join::JoinType,
+    ) -> LogicalPlanBuilder
+    where
+        T: Into<spark::Expression>,
+    {
+        let lateral_join = spark::LateralJoin {
+            left: self.relation_input(),
+            right: right.relation_input(),
+            join_condition: join_condition.map(|join| join.into()),
+            join_type: join_type.into(),
+        };
+
+        LogicalPlanBuilder::from(RelType::LateralJoin(Box::new(lateral_join)))
+    }
+
     pub fn limit(self, limit: i32) -> LogicalPlanBuilder {
         let limit_expr = RelType::Limit(Box::new(spark::Limit {
             input: self.relation_input(),
@@ -555,6 +608,22 @@ impl LogicalPlanBuilder {
         LogicalPlanBuilder::from(limit_expr)
     }
 
+    pub fn observe<I, S>(self, name: &str, exprs: I) -> LogicalPlanBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<Column>,
+    {
+        let collect_metrics = spark::CollectMetrics {
+            input: self.relation_input(),
+            name: name.to_string(),
+            metrics: VecExpression::from_iter(exprs).expr,
+        };
+
+        let metrics_rel = RelType::CollectMetrics(Box::new(collect_metrics));
+
+        LogicalPlanBuilder::from(metrics_rel)
+    }
+
     pub fn offset(self, num: i32) -> LogicalPlanBuilder {
         let offset_expr = RelType::Offset(Box::new(spark::Offset {
             input: self.relation_input(),
@@ -687,12 +756,12 @@ impl LogicalPlanBuilder {
     {
         let expressions = cols
             .into_iter()
-            .map(|col| spark::Expression {
-                expr_type: Some(spark::expression::ExprType::ExpressionString(
+            .map(|col| {
+                spark::Expression::from(spark::expression::ExprType::ExpressionString(
                     spark::expression::ExpressionString {
                         expression: col.as_ref().to_string(),
                     },
-                )),
+                ))
             })
             .collect();
 
@@ -798,14 +867,27 @@ impl LogicalPlanBuilder {
         K: AsRef<str>,
         V: AsRef<str>,
     {
-        let rename_columns_map: HashMap<String, String> = cols
-            .into_iter()
-            .map(|(k, v)| (k.as_ref().to_string(), v.as_ref().to_string()))
-            .collect();
+        #[cfg(not(feature = "spark-4"))]
+        let rename_expr = RelType::WithColumnsRenamed(Box::new(spark::WithColumnsRenamed {
+            input: self.relation_input(),
+            rename_columns_map: cols
+                .into_iter()
+                .map(|(k, v)| (k.as_ref().to_string(), v.as_ref().to_string()))
+                .collect(),
+        }));
 
+        // Spark 4 deprecates the map for a list, which keeps the order of the renames
+        #[cfg(feature = "spark-4")]
         let rename_expr = RelType::WithColumnsRenamed(Box::new(spark::WithColumnsRenamed {
             input: self.relation_input(),
-            rename_columns_map,
+            renames: cols
+                .into_iter()
+                .map(|(k, v)| spark::with_columns_renamed::Rename {
+                    col_name: k.as_ref().to_string(),
+                    new_col_name: v.as_ref().to_string(),
+                })
+                .collect(),
+            ..Default::default()
         }));
 
         LogicalPlanBuilder::from(rename_expr)
@@ -831,10 +913,7 @@ impl From<spark::relation::RelType> for LogicalPlanBuilder {
         let plan_id = LogicalPlanBuilder::next_plan_id();
 
         let relation = Relation {
-            common: Some(RelationCommon {
-                source_info: "".to_string(),
-                plan_id: Some(plan_id),
-            }),
+            common: Some(relation_common(plan_id)),
             rel_type: Some(rel_type),
         };
 
@@ -842,6 +921,24 @@ impl From<spark::relation::RelType> for LogicalPlanBuilder {
     }
 }
 
+/// Common fields of a relation with the plan id
+#[cfg(not(feature = "spark-4"))]
+pub(crate) fn relation_common(plan_id: i64) -> RelationCommon {
+    RelationCommon {
+        source_info: "".to_string(),
+        plan_id: Some(plan_id),
+    }
+}
+
+/// Common fields of a relation with the plan id, Spark 4 deprecates the source info
+#[cfg(feature = "spark-4")]
+pub(crate) fn relation_common(plan_id: i64) -> RelationCommon {
+    RelationCommon {
+        plan_id: Some(plan_id),
+        ..Default::default()
+    }
+}
+
 pub(crate) fn sort_order<I, S>(cols: I) -> Vec<spark::expression::SortOrder>
 where
     I: IntoIterator<Item = S>,
@@ -22,7 +22,7 @@ use std::collections::HashMap;
 use crate::column::Column;
 use crate::errors::SparkError;
 use crate::expressions::VecExpression;
-use crate::plan::LogicalPlanBuilder;
+use crate::plan::{relation_common, LogicalPlanBuilder};
 use crate::session::SparkSession;
 use crate::spark;
 use crate::types::{SparkDataType, StructType};
@@ -456,14 +456,13 @@ impl DataFrameReader {
                 options: self.read_options,
                 paths: paths.into_iter().map(|p| p.to_string()).collect(),
                 predicates: vec![],
+                #[cfg(feature = "spark-4")]
+                source_name: None,
             })),
         }));
 
         let relation = spark::Relation {
-            common: Some(spark::RelationCommon {
-                source_info: "NA".to_string(),
-                plan_id: Some(1),
-            }),
+            common: Some(relation_common(1)),
             rel_type: read_type,
         };
 
@@ -492,10 +491,7 @@ impl DataFrameReader {
         }));
 
         let relation = spark::Relation {
-            common: Some(spark::RelationCommon {
-                source_info: "NA".to_string(),
-                plan_id: Some(1),
-            }),
+            common: Some(relation_common(1)),
             rel_type: read_type,
         };
 
@@ -677,6 +673,10 @@ impl DataFrameWriter {
             bucket_by: self.bucket_by,
             options: self.write_options,
             save_type: Some(spark::write_operation::SaveType::Path(path.to_string())),
+            #[cfg(feature = "spark-4")]
+            clustering_columns: vec![],
+            #[cfg(feature = "spark-4")]
+            with_schema_evolution: false,
         });
 
         let plan = LogicalPlanBuilder::plan_cmd(write_command);
@@ -703,6 +703,10 @@ impl DataFrameWriter {
                     save_method,
                 },
             )),
+            #[cfg(feature = "spark-4")]
+            clustering_columns: vec![],
+            #[cfg(feature = "spark-4")]
+            with_schema_evolution: false,
         });
 
         let plan = LogicalPlanBuilder::plan_cmd(write_command);
@@ -853,6 +857,10 @@ impl DataFrameWriterV2 {
             table_properties: self.properties,
             mode: 0,
             overwrite_condition: self.overwrite_condition,
+            #[cfg(feature = "spark-4")]
+            clustering_columns: vec![],
+            #[cfg(feature = "spark-4")]
+            with_schema_evolution: false,
         };
 
         builder.set_mode(mode);
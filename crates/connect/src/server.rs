@@ -0,0 +1,57 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Spawn a Spark Connect gRPC service in the background
+
+use std::net::SocketAddr;
+
+use futures_util::stream;
+
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+use tonic::transport::Server;
+
+use crate::errors::SparkError;
+use crate::spark::spark_connect_service_server::{SparkConnectService, SparkConnectServiceServer};
+
+/// Serve the Spark Connect service on an ephemeral port of the loopback interface,
+/// until the returned sender is used or dropped
+pub(crate) async fn spawn<S>(service: S) -> Result<(SocketAddr, oneshot::Sender<()>), SparkError>
+where
+    S: SparkConnectService,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let incoming = stream::unfold(listener, |listener| async move {
+        let conn = listener.accept().await.map(|(stream, _)| stream);
+        Some((conn, listener))
+    });
+
+    let (shutdown, signal) = oneshot::channel::<()>();
+
+    let server = Server::builder()
+        .add_service(SparkConnectServiceServer::new(service))
+        .serve_with_incoming_shutdown(incoming, async {
+            let _ = signal.await;
+        });
+
+    tokio::spawn(server);
+
+    Ok((addr, shutdown))
+}
@@ -18,15 +18,32 @@
 //! Spark Session containing the remote gRPC client
 
 use std::collections::HashMap;
-use std::sync::Arc;
+#[cfg(any(test, feature = "testing"))]
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::client::{ChannelBuilder, Config, HeadersLayer, SparkClient, SparkConnectClient};
+use crate::client::{
+    ChannelBuilder, Config, CredentialProvider, HeadersLayer, RetryPolicy, SparkClient,
+    SparkConnectClient, TraceContextLayer,
+};
 
+#[cfg(any(test, feature = "testing"))]
+use crate::client::RecordLayer;
+
+use crate::artifacts::{Artifact, ArtifactManager};
 use crate::catalog::Catalog;
+#[cfg(feature = "spark-4")]
+use crate::column::Column;
 use crate::conf::RunTimeConfig;
 use crate::dataframe::{DataFrame, DataFrameReader};
 use crate::errors::SparkError;
-use crate::plan::LogicalPlanBuilder;
+use crate::extension::{CommandExtension, ExtensionRegistry, ExtensionResponse};
+#[cfg(feature = "spark-4")]
+use crate::metrics::ExecutionProgress;
+use crate::metrics::ObservedMetrics;
+use crate::operation::OperationHandle;
+use crate::plan::{serialize, LogicalPlanBuilder};
 use crate::streaming::{DataStreamReader, StreamingQueryManager};
 
 use crate::spark;
@@ -34,12 +51,171 @@ use spark::spark_connect_service_client::SparkConnectServiceClient;
 
 use arrow::record_batch::RecordBatch;
 
+use prost::Message;
+
+use uuid::Uuid;
+
+use tokio::runtime::Handle;
 use tokio::sync::RwLock;
 
 use tower::ServiceBuilder;
 
 use tonic::transport::Channel;
 
+/// Default size in bytes above which local data is cached on the server
+const DEFAULT_LOCAL_RELATION_CACHE_THRESHOLD: &str = "64m";
+
+/// Parse a Spark byte size string, e.g. `67108864`, `67108864b` or `64mb`
+fn parse_byte_size(value: &str) -> Option<usize> {
+    let value = value.trim().to_lowercase();
+
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+
+    let (size, unit) = value.split_at(split);
+
+    let multiplier: usize = match unit.trim() {
+        "" | "b" => 1,
+        "k" | "kb" => 1 << 10,
+        "m" | "mb" => 1 << 20,
+        "g" | "gb" => 1 << 30,
+        "t" | "tb" => 1 << 40,
+        _ => return None,
+    };
+
+    size.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+/// Argument of a parameterized SQL query, see [SparkSession::sql_with_args]
+#[derive(Clone, Debug)]
+pub enum SqlArgument {
+    /// Value bound to a parameter marker of the query
+    Literal(spark::expression::Literal),
+    /// [DataFrame] referenced as a table by a placeholder of the query
+    Table(DataFrame),
+}
+
+impl<T> From<T> for SqlArgument
+where
+    T: Into<spark::expression::Literal>,
+{
+    fn from(value: T) -> Self {
+        SqlArgument::Literal(value.into())
+    }
+}
+
+impl From<DataFrame> for SqlArgument {
+    fn from(df: DataFrame) -> Self {
+        SqlArgument::Table(df)
+    }
+}
+
+/// Temporary views registered for the table arguments of a query
+///
+/// The views are dropped once the last [DataFrame] built from the query is dropped, since
+/// the relation of a query is analyzed again each time it is executed.
+#[derive(Debug)]
+struct TempViews {
+    spark_session: SparkSession,
+    names: Vec<String>,
+}
+
+impl Drop for TempViews {
+    fn drop(&mut self) {
+        let catalog = Catalog::new(self.spark_session.clone());
+        let names = std::mem::take(&mut self.names);
+
+        // without a runtime the views can not be dropped, they are removed with the session
+        if let Ok(handle) = Handle::try_current() {
+            handle.spawn(async move {
+                for name in names {
+                    let _ = catalog.clone().drop_temp_view(&name).await;
+                }
+            });
+        }
+    }
+}
+
+/// Length of the string literal, quoted identifier, comment or `${var}` substitution at
+/// the start of the query text, in which placeholders are not replaced
+fn quoted_len(sql: &str) -> Option<usize> {
+    let bytes = sql.as_bytes();
+
+    let end = |from: usize, close: &str| {
+        sql[from..]
+            .find(close)
+            .map_or(sql.len(), |idx| from + idx + close.len())
+    };
+
+    match bytes {
+        [quote @ (b'\'' | b'"'), ..] => {
+            let mut idx = 1;
+            while idx < bytes.len() {
+                match bytes[idx] {
+                    b'\\' => idx += 2,
+                    byte if byte == *quote => return Some(idx + 1),
+                    _ => idx += 1,
+                }
+            }
+            Some(bytes.len())
+        }
+        [b'`', ..] => Some(end(1, "`")),
+        [b'-', b'-', ..] => Some(end(2, "\n")),
+        [b'/', b'*', ..] => Some(end(2, "*/")),
+        [b'$', b'{', ..] => Some(end(2, "}")),
+        _ => None,
+    }
+}
+
+/// Replace each `{placeholder}` of the query with the view of its table argument
+///
+/// Placeholders within string literals, quoted identifiers, comments and `${var}`
+/// substitutions are left as is.
+fn substitute_tables(sql: &str, views: &HashMap<String, String>) -> Result<String, SparkError> {
+    let mut output = String::with_capacity(sql.len());
+    let mut rest = sql;
+
+    while let Some(idx) = rest.find(['\'', '"', '`', '-', '/', '$', '{']) {
+        output.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+
+        if let Some(len) = quoted_len(rest) {
+            output.push_str(&rest[..len]);
+            rest = &rest[len..];
+            continue;
+        }
+
+        let placeholder = rest
+            .strip_prefix('{')
+            .and_then(|inner| inner.find('}').map(|len| &inner[..len]))
+            .filter(|name| !name.is_empty())
+            .filter(|name| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+
+        match placeholder {
+            Some(name) => {
+                let view = views.get(name).ok_or_else(|| {
+                    SparkError::InvalidArgument(format!(
+                        "No table argument for the placeholder '{{{}}}'",
+                        name
+                    ))
+                })?;
+
+                output.push_str(view);
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                output.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    output.push_str(rest);
+
+    Ok(output)
+}
+
 /// SparkSessionBuilder creates a remote Spark Session a connection string.
 ///
 /// The connection string is define based on the requirements from [Spark Documentation](https://github.com/apache/spark/blob/master/connector/connect/docs/client-connection-string.md)
@@ -47,6 +223,11 @@ use tonic::transport::Channel;
 pub struct SparkSessionBuilder {
     pub channel_builder: ChannelBuilder,
     configs: HashMap<String, String>,
+    retry_policy: RetryPolicy,
+    #[cfg(any(test, feature = "testing"))]
+    fixtures: Option<PathBuf>,
+    extensions: ExtensionRegistry,
+    trace_context: bool,
 }
 
 /// Default connects a Spark cluster running at `sc://127.0.0.1:15002/`
@@ -57,6 +238,11 @@ impl Default for SparkSessionBuilder {
         Self {
             channel_builder,
             configs: HashMap::new(),
+            retry_policy: RetryPolicy::default(),
+            #[cfg(any(test, feature = "testing"))]
+            fixtures: None,
+            extensions: ExtensionRegistry::default(),
+            trace_context: false,
         }
     }
 }
@@ -68,6 +254,11 @@ impl SparkSessionBuilder {
         Self {
             channel_builder,
             configs: HashMap::new(),
+            retry_policy: RetryPolicy::default(),
+            #[cfg(any(test, feature = "testing"))]
+            fixtures: None,
+            extensions: ExtensionRegistry::default(),
+            trace_context: false,
         }
     }
 
@@ -76,6 +267,11 @@ impl SparkSessionBuilder {
         Self {
             channel_builder: config.into(),
             configs: HashMap::new(),
+            retry_policy: RetryPolicy::default(),
+            #[cfg(any(test, feature = "testing"))]
+            fixtures: None,
+            extensions: ExtensionRegistry::default(),
+            trace_context: false,
         }
     }
 
@@ -92,6 +288,52 @@ impl SparkSessionBuilder {
         self
     }
 
+    /// Sets the [RetryPolicy] used when a request fails with a transient gRPC error.
+    ///
+    /// Interrupted result streams are resumed from the last received response when
+    /// reattachable execution is enabled, so commands are never executed twice.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Sets the [CredentialProvider] queried for a bearer token on each request,
+    /// replacing any `token` from the connection string
+    pub fn credentials(mut self, provider: impl CredentialProvider + 'static) -> Self {
+        self.channel_builder.set_credentials(Arc::new(provider));
+        self
+    }
+
+    /// Records every request and response of the session to a fixture file.
+    ///
+    /// The fixtures can be served back by a `ReplaySparkServer` (`feature = 'testing'`)
+    /// to run the same queries without a Spark cluster.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn record_fixtures(mut self, path: impl Into<PathBuf>) -> Self {
+        self.fixtures = Some(path.into());
+        self
+    }
+
+    /// Sets the [ExtensionRegistry] used to decode the extension responses of server plugins
+    /// returned by `SparkSession::execute_extension`
+    pub fn extensions(mut self, extensions: ExtensionRegistry) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// Propagates the OpenTelemetry context of the current `tracing` span to the server.
+    ///
+    /// The headers of the global text map propagator, e.g. the W3C `traceparent` and
+    /// `tracestate` of a `TraceContextPropagator`, are added to each request, and the trace
+    /// id is added as a `trace-id-<trace id>` tag to each operation, so it is listed with
+    /// the job in the Spark UI. Spans are linked to OpenTelemetry by a `tracing_opentelemetry`
+    /// layer.
+    #[cfg(feature = "otel")]
+    pub fn trace_context(mut self) -> Self {
+        self.trace_context = true;
+        self
+    }
+
     /// Sets a name for the application, which will be shown in the Spark web UI.
     pub fn app_name(mut self, name: &str) -> Self {
         self.configs
@@ -99,22 +341,64 @@ impl SparkSessionBuilder {
         self
     }
 
+    #[cfg(any(test, feature = "testing"))]
+    fn record_layer(
+        &self,
+        channel: Channel,
+    ) -> Result<crate::client::RecordMiddleware<Channel>, SparkError> {
+        let record = match &self.fixtures {
+            Some(path) => RecordLayer::new(path)?,
+            None => RecordLayer::disabled(),
+        };
+
+        Ok(tower::Layer::layer(&record, channel))
+    }
+
+    #[cfg(not(any(test, feature = "testing")))]
+    fn record_layer(&self, channel: Channel) -> Result<Channel, SparkError> {
+        Ok(channel)
+    }
+
     async fn create_client(&self) -> Result<SparkSession, SparkError> {
-        let channel = Channel::from_shared(self.channel_builder.endpoint())?
-            .connect()
-            .await?;
+        let endpoint = self
+            .channel_builder
+            .configure_endpoint(Channel::from_shared(self.channel_builder.endpoint())?);
+
+        #[cfg(feature = "tls")]
+        let endpoint = match self.channel_builder.tls_config()? {
+            Some(tls) => endpoint.tls_config(tls)?,
+            None => endpoint,
+        };
+
+        #[cfg(not(feature = "tls"))]
+        self.channel_builder.tls_config()?;
+
+        let channel = endpoint.connect().await?;
+
+        let trace_context = match self.trace_context {
+            true => TraceContextLayer::new(),
+            false => TraceContextLayer::disabled(),
+        };
 
         let channel = ServiceBuilder::new()
-            .layer(HeadersLayer::new(
-                self.channel_builder.headers().unwrap_or_default(),
-            ))
-            .service(channel);
+            .layer(trace_context)
+            .layer(
+                HeadersLayer::new(self.channel_builder.headers().unwrap_or_default())
+                    .credentials(self.channel_builder.credentials()),
+            )
+            .service(self.record_layer(channel)?);
 
-        let client = SparkConnectServiceClient::new(channel);
+        let client = self
+            .channel_builder
+            .configure_client(SparkConnectServiceClient::new(channel))?;
 
-        let spark_connnect_client =
+        let mut spark_connnect_client =
             SparkConnectClient::new(Arc::new(RwLock::new(client)), self.channel_builder.clone());
 
+        spark_connnect_client.set_retry_policy(self.retry_policy.clone());
+        spark_connnect_client.set_extensions(self.extensions.clone());
+        spark_connnect_client.set_trace_context(self.trace_context);
+
         let mut rt_config = RunTimeConfig::new(&spark_connnect_client);
 
         rt_config.set_configs(&self.configs).await?;
@@ -136,6 +420,8 @@ impl SparkSessionBuilder {
 pub struct SparkSession {
     client: SparkClient,
     session_id: String,
+    local_relation_cache_threshold: Arc<Mutex<Option<usize>>>,
+    temp_views: Option<Arc<TempViews>>,
 }
 
 impl SparkSession {
@@ -143,6 +429,8 @@ impl SparkSession {
         Self {
             session_id: client.session_id(),
             client,
+            local_relation_cache_threshold: Arc::new(Mutex::new(None)),
+            temp_views: None,
         }
     }
 
@@ -185,6 +473,21 @@ impl SparkSession {
         DataFrameReader::new(self.session()).table(name, None)
     }
 
+    /// Returns a [DataFrame] of the rows returned by the table-valued function `name`,
+    /// e.g. `explode` or `range`, called with the given arguments
+    #[cfg(feature = "spark-4")]
+    pub fn table_function<I>(&self, name: &str, args: I) -> DataFrame
+    where
+        I: IntoIterator<Item: Into<Column>>,
+    {
+        let args = args.into_iter().map(|arg| arg.into().into()).collect();
+
+        DataFrame::new(
+            self.session(),
+            LogicalPlanBuilder::table_valued_function(name, args),
+        )
+    }
+
     /// Interface through which the user may create, drop, alter or query underlying databases,
     /// tables, functions, etc.
     pub fn catalog(&self) -> Catalog {
@@ -193,11 +496,174 @@ impl SparkSession {
 
     /// Returns a [DataFrame] representing the result of the given query
     pub async fn sql(&self, sql_query: &str) -> Result<DataFrame, SparkError> {
-        let sql_cmd = spark::command::CommandType::SqlCommand(spark::SqlCommand {
-            sql: sql_query.to_string(),
-            args: HashMap::default(),
-            pos_args: vec![],
-        });
+        self.sql_command(sql_query.to_string(), HashMap::default(), vec![])
+            .await
+    }
+
+    /// Returns a [DataFrame] representing the result of a query with named parameters
+    ///
+    /// Literal arguments are bound on the server to the `:name` markers of the query. A
+    /// [DataFrame] argument is registered as a temporary view, which replaces the `{name}`
+    /// placeholders in the query text outside of string literals, quoted identifiers and
+    /// comments. The views are dropped with the last [DataFrame] built from the query, and
+    /// a placeholder without a table argument returns [SparkError::InvalidArgument].
+    ///
+    /// # Example:
+    ///
+    /// ```rust
+    /// let df = spark
+    ///     .sql_with_args(
+    ///         "SELECT * FROM {people} WHERE age > :min_age",
+    ///         [("min_age", SqlArgument::from(20)), ("people", people.into())],
+    ///     )
+    ///     .await?;
+    /// ```
+    pub async fn sql_with_args<I, K, V>(
+        &self,
+        sql_query: &str,
+        args: I,
+    ) -> Result<DataFrame, SparkError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: Into<SqlArgument>,
+    {
+        let mut named = HashMap::new();
+        let mut tables = vec![];
+
+        for (name, arg) in args {
+            match arg.into() {
+                SqlArgument::Literal(literal) => {
+                    named.insert(name.as_ref().to_string(), literal);
+                }
+                SqlArgument::Table(df) => tables.push((name.as_ref().to_string(), df)),
+            }
+        }
+
+        let (sql, session) = self.sql_tables(sql_query, tables).await?;
+
+        session.sql_command(sql, named, vec![]).await
+    }
+
+    /// Returns a [DataFrame] representing the result of a query with positional parameters
+    ///
+    /// Literal arguments are bound on the server to the `?` markers of the query, in order.
+    /// A [DataFrame] argument is registered as a temporary view, which replaces the `{index}`
+    /// placeholders in the query text, where `index` is the position of the argument. The
+    /// placeholders and views are handled as in [SparkSession::sql_with_args].
+    ///
+    /// # Example:
+    ///
+    /// ```rust
+    /// let df = spark
+    ///     .sql_with_pos_args(
+    ///         "SELECT * FROM {0} WHERE age > ? AND name = ?",
+    ///         [SqlArgument::from(people), 20.into(), "Alice".into()],
+    ///     )
+    ///     .await?;
+    /// ```
+    pub async fn sql_with_pos_args<I, V>(
+        &self,
+        sql_query: &str,
+        args: I,
+    ) -> Result<DataFrame, SparkError>
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<SqlArgument>,
+    {
+        let mut pos_args = vec![];
+        let mut tables = vec![];
+
+        for (idx, arg) in args.into_iter().enumerate() {
+            match arg.into() {
+                SqlArgument::Literal(literal) => pos_args.push(literal),
+                SqlArgument::Table(df) => tables.push((idx.to_string(), df)),
+            }
+        }
+
+        let (sql, session) = self.sql_tables(sql_query, tables).await?;
+
+        session.sql_command(sql, HashMap::default(), pos_args).await
+    }
+
+    /// Register each [DataFrame] argument as a temporary view, and replace its `{placeholder}`
+    /// in the query with the name of the view
+    ///
+    /// Returns the query with a session that owns the views, so they are dropped with the
+    /// last [DataFrame] of the query.
+    async fn sql_tables(
+        &self,
+        sql: &str,
+        tables: Vec<(String, DataFrame)>,
+    ) -> Result<(String, SparkSession), SparkError> {
+        let views: HashMap<String, String> = tables
+            .iter()
+            .map(|(placeholder, _)| {
+                let view = format!("spark_connect_rs_{}", Uuid::new_v4().simple());
+                (placeholder.clone(), view)
+            })
+            .collect();
+
+        // placeholders are checked before any view is registered
+        let sql = substitute_tables(sql, &views)?;
+
+        if tables.is_empty() {
+            return Ok((sql, self.session()));
+        }
+
+        let mut temp_views = TempViews {
+            spark_session: self.session(),
+            names: vec![],
+        };
+
+        for (placeholder, df) in tables {
+            let view = &views[&placeholder];
+
+            df.create_or_replace_temp_view(view).await?;
+            temp_views.names.push(view.clone());
+        }
+
+        let mut session = self.session();
+        session.temp_views = Some(Arc::new(temp_views));
+
+        Ok((sql, session))
+    }
+
+    async fn sql_command(
+        &self,
+        sql: String,
+        args: HashMap<String, spark::expression::Literal>,
+        pos_args: Vec<spark::expression::Literal>,
+    ) -> Result<DataFrame, SparkError> {
+        #[cfg(not(feature = "spark-4"))]
+        let sql_cmd = spark::SqlCommand {
+            sql,
+            args,
+            pos_args,
+        };
+
+        // Spark 4 deprecates the query fields of the command for a SQL relation
+        #[cfg(feature = "spark-4")]
+        let sql_cmd = spark::SqlCommand {
+            input: Some(spark::Relation {
+                common: None,
+                rel_type: Some(spark::relation::RelType::Sql(spark::Sql {
+                    query: sql,
+                    named_arguments: args
+                        .into_iter()
+                        .map(|(name, arg)| (name, Column::from(arg).into()))
+                        .collect(),
+                    pos_arguments: pos_args
+                        .into_iter()
+                        .map(|arg| Column::from(arg).into())
+                        .collect(),
+                    ..Default::default()
+                })),
+            }),
+            ..Default::default()
+        };
+
+        let sql_cmd = spark::command::CommandType::SqlCommand(sql_cmd);
 
         let plan = LogicalPlanBuilder::plan_cmd(sql_cmd);
 
@@ -214,17 +680,116 @@ impl SparkSession {
         Ok(DataFrame::new(self.session(), logical_plan))
     }
 
+    /// Execute a [CommandExtension] handled by a server plugin, and return the
+    /// extension responses decoded with the registry of the session
+    ///
+    /// This is the only method that returns extension responses, the ones sent while
+    /// running a [DataFrame] action are discarded.
+    pub async fn execute_extension(
+        &self,
+        command: &impl CommandExtension,
+    ) -> Result<Vec<ExtensionResponse>, SparkError> {
+        let plan =
+            LogicalPlanBuilder::plan_cmd(spark::command::CommandType::Extension(command.to_any()));
+
+        let resp = self
+            .clone()
+            .client()
+            .execute_command_and_fetch(plan)
+            .await?;
+
+        Ok(resp.extensions)
+    }
+
+    /// Create a [DataFrame] from a local [RecordBatch], which is sent inline with the plan
+    ///
+    /// The data is never cached on the server, so it is sent again with every plan built
+    /// from the [DataFrame], whatever its size. Use [SparkSession::create_cached_dataframe]
+    /// for data larger than `spark.sql.session.localRelationCacheThreshold`.
     pub fn create_dataframe(&self, data: &RecordBatch) -> Result<DataFrame, SparkError> {
         let logical_plan = LogicalPlanBuilder::local_relation(data)?;
 
         Ok(DataFrame::new(self.session(), logical_plan))
     }
 
+    /// Create a [DataFrame] from a local [RecordBatch], caching large data on the server
+    ///
+    /// Data larger than `spark.sql.session.localRelationCacheThreshold` is uploaded once to
+    /// the session cache as an artifact and referenced with a `CachedLocalRelation`, instead of
+    /// being sent inline with every plan. The threshold is read from the server on the first
+    /// call, and reused for the lifetime of the session.
+    pub async fn create_cached_dataframe(
+        &self,
+        data: &RecordBatch,
+    ) -> Result<DataFrame, SparkError> {
+        let local_rel = spark::LocalRelation {
+            data: Some(serialize(data)?),
+            schema: None,
+        };
+
+        let threshold = self.local_relation_cache_threshold().await?;
+
+        let logical_plan = if local_rel.encoded_len() > threshold {
+            let hash = self
+                .artifacts()
+                .cache_artifact(local_rel.encode_to_vec())
+                .await?;
+
+            LogicalPlanBuilder::cached_local_relation(&hash)
+        } else {
+            LogicalPlanBuilder::from(spark::relation::RelType::LocalRelation(local_rel))
+        };
+
+        Ok(DataFrame::new(self.session(), logical_plan))
+    }
+
+    async fn local_relation_cache_threshold(&self) -> Result<usize, SparkError> {
+        if let Some(threshold) = *self.local_relation_cache_threshold.lock().unwrap() {
+            return Ok(threshold);
+        }
+
+        let value = self
+            .conf()
+            .get(
+                "spark.sql.session.localRelationCacheThreshold",
+                Some(DEFAULT_LOCAL_RELATION_CACHE_THRESHOLD),
+            )
+            .await?;
+
+        let threshold = parse_byte_size(&value).ok_or_else(|| {
+            SparkError::InvalidArgument(format!(
+                "Invalid value for spark.sql.session.localRelationCacheThreshold: {}",
+                value
+            ))
+        })?;
+
+        *self.local_relation_cache_threshold.lock().unwrap() = Some(threshold);
+
+        Ok(threshold)
+    }
+
     /// Return the session ID
     pub fn session_id(&self) -> &str {
         &self.session_id
     }
 
+    /// Returns a copy of the session where every execute and analyze call must complete
+    /// within `timeout`.
+    ///
+    /// Operations that exceed the deadline, or whose future is dropped, are interrupted on
+    /// the server and return [SparkError::DeadlineExceeded].
+    pub fn with_timeout(&self, timeout: Duration) -> SparkSession {
+        let mut session = self.clone();
+        session.client.set_timeout(Some(timeout));
+        session
+    }
+
+    /// Resume an [OperationHandle] serialized with `OperationHandle::serialize`,
+    /// e.g. in another process connected to this session
+    pub fn resume_operation(&self, serialized: &str) -> Result<OperationHandle, SparkError> {
+        OperationHandle::deserialize(self.client.clone(), serialized)
+    }
+
     /// Spark Connection gRPC client interface
     pub fn client(self) -> SparkClient {
         self.client
@@ -266,6 +831,17 @@ impl SparkSession {
         Ok(resp.interrupted_ids)
     }
 
+    /// Release this session on the server, which drops its temporary views, configs and
+    /// artifacts and interrupts its running operations.
+    ///
+    /// The session can not be used once it is stopped
+    #[cfg(feature = "spark-4")]
+    pub async fn stop(&self) -> Result<(), SparkError> {
+        self.client.release_session().await?;
+
+        Ok(())
+    }
+
     /// Add a tag to be assigned to all the operations started by this thread in this session.
     pub fn add_tag(&mut self, tag: &str) -> Result<(), SparkError> {
         self.client.add_tag(tag)
@@ -292,11 +868,51 @@ impl SparkSession {
             spark::analyze_plan_request::SparkVersion {},
         );
 
-        let mut client = self.client.clone();
+        let client = self.client.clone();
 
         client.analyze(version).await?.spark_version()
     }
 
+    /// Resources of the driver advertised by the cluster, e.g. GPUs, by resource name
+    pub async fn resources(
+        &self,
+    ) -> Result<HashMap<String, spark::ResourceInformation>, SparkError> {
+        let plan = LogicalPlanBuilder::plan_cmd(spark::command::CommandType::GetResourcesCommand(
+            spark::GetResourcesCommand {},
+        ));
+
+        let resp = self.client.execute_command_and_fetch(plan).await?;
+
+        let resources = resp
+            .get_resources_command_result
+            .map(|result| result.resources)
+            .unwrap_or_default();
+
+        Ok(resources)
+    }
+
+    /// Latest metrics observed with `DataFrame::observe` by the completed actions of
+    /// this session, by observation name
+    pub fn observed_metrics(&self) -> HashMap<String, ObservedMetrics> {
+        self.client.observed_metrics()
+    }
+
+    /// Register a handler that is called with the [ExecutionProgress] the server reports
+    /// while the actions of this session run
+    #[cfg(feature = "spark-4")]
+    pub fn register_progress_handler<F>(&self, handler: F)
+    where
+        F: Fn(&ExecutionProgress) + Send + Sync + 'static,
+    {
+        self.client.progress_handlers.register(Arc::new(handler))
+    }
+
+    /// Remove all of the progress handlers of this session
+    #[cfg(feature = "spark-4")]
+    pub fn clear_progress_handlers(&self) {
+        self.client.progress_handlers.clear()
+    }
+
     /// [RunTimeConfig] configuration interface for Spark.
     pub fn conf(&self) -> RunTimeConfig {
         RunTimeConfig::new(&self.client)
@@ -306,6 +922,21 @@ impl SparkSession {
     pub fn streams(&self) -> StreamingQueryManager {
         StreamingQueryManager::new(self)
     }
+
+    /// [ArtifactManager] interface to add and inspect artifacts of this session.
+    pub fn artifacts(&self) -> ArtifactManager {
+        ArtifactManager::new(&self.client)
+    }
+
+    /// Add one or more artifacts (jars, files, archives) to the session.
+    ///
+    /// Returns the names of the artifacts the server received
+    pub async fn add_artifacts<I>(&self, artifacts: I) -> Result<Vec<String>, SparkError>
+    where
+        I: IntoIterator<Item = Artifact>,
+    {
+        self.artifacts().add_artifacts(artifacts).await
+    }
 }
 
 #[cfg(test)]
@@ -313,12 +944,45 @@ mod tests {
     use super::*;
 
     use arrow::{
-        array::{ArrayRef, StringArray},
+        array::{ArrayRef, Int64Array, StringArray},
         record_batch::RecordBatch,
     };
 
+    use crate::functions::*;
+
     use regex::Regex;
 
+    type Literal = spark::expression::Literal;
+
+    /// Query and arguments of a SQL command
+    #[cfg(not(feature = "spark-4"))]
+    fn sql_args(cmd: spark::SqlCommand) -> (String, HashMap<String, Literal>, Vec<Literal>) {
+        (cmd.sql, cmd.args, cmd.pos_args)
+    }
+
+    /// Query and literal arguments of the SQL relation of a SQL command
+    #[cfg(feature = "spark-4")]
+    fn sql_args(cmd: spark::SqlCommand) -> (String, HashMap<String, Literal>, Vec<Literal>) {
+        use spark::expression::ExprType;
+
+        let literal = |expr: spark::Expression| match expr.expr_type {
+            Some(ExprType::Literal(literal)) => literal,
+            _ => panic!("expected a literal argument"),
+        };
+
+        match cmd.input.and_then(|input| input.rel_type) {
+            Some(spark::relation::RelType::Sql(sql)) => (
+                sql.query,
+                sql.named_arguments
+                    .into_iter()
+                    .map(|(name, arg)| (name, literal(arg)))
+                    .collect(),
+                sql.pos_arguments.into_iter().map(literal).collect(),
+            ),
+            _ => panic!("expected a SQL relation"),
+        }
+    }
+
     async fn setup() -> SparkSession {
         println!("SparkSession Setup");
 
@@ -342,6 +1006,39 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_spark_concurrent_queries() -> Result<(), SparkError> {
+        let spark = setup().await;
+
+        let df1 = spark.range(None, 100, 1, Some(8));
+        let df2 = spark.range(None, 1000, 1, Some(8));
+        let df3 = spark.range(None, 10000, 1, Some(8));
+
+        let (res1, res2, res3, version) =
+            tokio::join!(df1.count(), df2.count(), df3.count(), spark.version());
+
+        assert_eq!(100, res1?);
+        assert_eq!(1000, res2?);
+        assert_eq!(10000, res3?);
+        assert!(version.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_spark_timeout_interrupts_operation() -> Result<(), SparkError> {
+        let spark = setup().await;
+
+        let df = spark
+            .range(None, 1_000_000_000, 1, Some(1))
+            .select([sum(col("id") * col("id"))])
+            .with_timeout(Duration::from_millis(500));
+
+        let err = df.collect().await.unwrap_err();
+
+        assert!(matches!(err, SparkError::DeadlineExceeded(_)));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_spark_create_dataframe() -> Result<(), SparkError> {
         let spark = setup().await;
@@ -358,6 +1055,275 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_spark_create_cached_dataframe() -> Result<(), SparkError> {
+        let spark = setup().await;
+
+        spark
+            .conf()
+            .set("spark.sql.session.localRelationCacheThreshold", "1024")
+            .await?;
+
+        let a: ArrayRef = Arc::new(Int64Array::from_iter_values(0..10_000));
+
+        let record_batch = RecordBatch::try_from_iter(vec![("a", a)])?;
+
+        let df = spark.create_cached_dataframe(&record_batch).await?;
+        let df_again = spark.create_cached_dataframe(&record_batch).await?;
+
+        assert_eq!(record_batch, df.collect().await?);
+        assert_eq!(record_batch, df_again.collect().await?);
+
+        spark
+            .conf()
+            .unset("spark.sql.session.localRelationCacheThreshold")
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_spark_create_cached_dataframe_mock() -> Result<(), SparkError> {
+        use crate::testing::MockSparkServer;
+
+        let server = MockSparkServer::start().await?;
+
+        let spark = server.session().await?;
+
+        let a: ArrayRef = Arc::new(Int64Array::from_iter_values(0..10));
+
+        let record_batch = RecordBatch::try_from_iter(vec![("a", a)])?;
+
+        let df = spark.create_cached_dataframe(&record_batch).await?;
+        spark.create_cached_dataframe(&record_batch).await?;
+
+        // the threshold is only read once per session
+        assert_eq!(1, server.requests().config.len());
+
+        let rel_type = df.plan.clone().relation().rel_type;
+
+        assert!(matches!(
+            rel_type,
+            Some(spark::relation::RelType::LocalRelation(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_spark_create_cached_dataframe_upload_mock() -> Result<(), SparkError> {
+        use crate::testing::MockSparkServer;
+
+        let server = MockSparkServer::start().await?;
+
+        let spark = server
+            .builder()
+            .config("spark.sql.session.localRelationCacheThreshold", "100")
+            .build()
+            .await?;
+
+        let a: ArrayRef = Arc::new(Int64Array::from_iter_values(0..1000));
+
+        let record_batch = RecordBatch::try_from_iter(vec![("a", a)])?;
+
+        let df = spark.create_cached_dataframe(&record_batch).await?;
+
+        let hash = match df.plan.clone().relation().rel_type {
+            Some(spark::relation::RelType::CachedLocalRelation(rel)) => rel.hash,
+            _ => panic!("expected a CachedLocalRelation"),
+        };
+
+        assert!(server.artifacts().contains_key(&format!("cache/{}", hash)));
+        assert_eq!(1, server.requests().add_artifacts.len());
+
+        // the same data is not uploaded again
+        spark.create_cached_dataframe(&record_batch).await?;
+
+        let requests = server.requests();
+
+        assert_eq!(2, requests.artifact_status.len());
+        assert_eq!(1, requests.add_artifacts.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_spark_sql_with_args() -> Result<(), SparkError> {
+        use arrow::array::AsArray;
+        use arrow::datatypes::Int32Type;
+
+        let spark = setup().await;
+
+        let people = spark.range(None, 10, 1, None);
+
+        let df = spark
+            .sql_with_args(
+                "SELECT * FROM {people} WHERE id > :min_id",
+                [("min_id", SqlArgument::from(6)), ("people", people.into())],
+            )
+            .await?;
+
+        assert_eq!(3, df.count().await?);
+
+        let df = spark
+            .sql_with_pos_args("SELECT ? + ? AS total", [1, 2])
+            .await?;
+
+        let rows = df.collect().await?;
+
+        assert_eq!(3, rows.column(0).as_primitive::<Int32Type>().value(0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_spark_sql_with_args_mock() -> Result<(), SparkError> {
+        use crate::testing::{MockResponse, MockSparkServer};
+        use spark::execute_plan_response::{ResponseType, SqlCommandResult};
+
+        let server = MockSparkServer::start().await?;
+
+        let sql_result = || {
+            [MockResponse::Response(ResponseType::SqlCommandResult(
+                SqlCommandResult {
+                    relation: Some(spark::Relation::default()),
+                },
+            ))]
+        };
+
+        // the temporary view, followed by each query
+        server.push_execute([]);
+        server.push_execute(sql_result());
+        server.push_execute(sql_result());
+
+        let spark = server.session().await?;
+
+        let df = spark
+            .sql_with_args(
+                "SELECT * FROM {t} WHERE name = :name",
+                [
+                    ("name", SqlArgument::from("Alice")),
+                    ("t", spark.range(None, 1, 1, None).into()),
+                ],
+            )
+            .await?;
+
+        spark
+            .sql_with_pos_args("SELECT ?, ?", [SqlArgument::from(1_i64), true.into()])
+            .await?;
+
+        let requests = server.requests().execute;
+
+        let sql_command = |idx: usize| match requests[idx].plan.clone().unwrap().op_type {
+            Some(spark::plan::OpType::Command(spark::Command {
+                command_type: Some(spark::command::CommandType::SqlCommand(cmd)),
+            })) => sql_args(cmd),
+            _ => panic!("expected a SqlCommand"),
+        };
+
+        let view = match requests[0].plan.clone().unwrap().op_type {
+            Some(spark::plan::OpType::Command(spark::Command {
+                command_type: Some(spark::command::CommandType::CreateDataframeView(cmd)),
+            })) => cmd.name,
+            _ => panic!("expected a CreateDataframeView"),
+        };
+
+        let (query, args, _) = sql_command(1);
+
+        assert_eq!(format!("SELECT * FROM {} WHERE name = :name", view), query);
+        assert_eq!(spark::expression::Literal::from("Alice"), args["name"]);
+
+        let (query, _, pos_args) = sql_command(2);
+
+        assert_eq!("SELECT ?, ?", query);
+        assert_eq!(
+            vec![
+                spark::expression::Literal::from(1_i64),
+                spark::expression::Literal::from(true)
+            ],
+            pos_args
+        );
+
+        // the view is dropped with the last DataFrame of the query
+        drop(df);
+
+        for _ in 0..100 {
+            if server.requests().execute.len() > 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let dropped = match server.requests().execute[3].plan.clone().unwrap().op_type {
+            Some(spark::plan::OpType::Root(spark::Relation {
+                rel_type: Some(spark::relation::RelType::Catalog(catalog)),
+                ..
+            })) => catalog.cat_type,
+            _ => panic!("expected a Catalog relation"),
+        };
+
+        assert_eq!(
+            Some(spark::catalog::CatType::DropTempView(spark::DropTempView {
+                view_name: view
+            })),
+            dropped
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_spark_sql_unknown_placeholder_mock() -> Result<(), SparkError> {
+        use crate::testing::MockSparkServer;
+
+        let server = MockSparkServer::start().await?;
+        let spark = server.session().await?;
+
+        let err = spark
+            .sql_with_args(
+                "SELECT * FROM {people} JOIN {places}",
+                [("people", spark.range(None, 1, 1, None))],
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, SparkError::InvalidArgument(_)));
+        assert!(server.requests().execute.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_substitute_tables() -> Result<(), SparkError> {
+        let views = HashMap::from([
+            ("t".to_string(), "view_t".to_string()),
+            ("0".to_string(), "view_0".to_string()),
+        ]);
+
+        assert_eq!(
+            "SELECT * FROM view_t JOIN view_0",
+            substitute_tables("SELECT * FROM {t} JOIN {0}", &views)?
+        );
+
+        let quoted = "SELECT '{t}', 'it\\'s {t}', \"{t}\", `{t}`, ${t} -- {t}\n/* {t} */";
+        assert_eq!(quoted, substitute_tables(quoted, &views)?);
+
+        assert_eq!(
+            "SELECT map('a', 1), 1 - 2, 4 / 2, {a b} FROM view_t",
+            substitute_tables("SELECT map('a', 1), 1 - 2, 4 / 2, {a b} FROM {t}", &views)?
+        );
+
+        assert!(matches!(
+            substitute_tables("SELECT * FROM {missing}", &views),
+            Err(SparkError::InvalidArgument(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(Some(67108864), parse_byte_size("67108864"));
+        assert_eq!(Some(67108864), parse_byte_size("67108864b"));
+        assert_eq!(Some(67108864), parse_byte_size("64m"));
+        assert_eq!(Some(67108864), parse_byte_size("64MB"));
+        assert_eq!(None, parse_byte_size("64 lightyears"));
+    }
+
     #[tokio::test]
     async fn test_spark_session_create() {
         let connection =
@@ -421,6 +1387,33 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_session_resources() -> Result<(), SparkError> {
+        use crate::testing::{MockResponse, MockSparkServer};
+
+        let server = MockSparkServer::start().await?;
+
+        let gpu = spark::ResourceInformation {
+            name: "gpu".to_string(),
+            addresses: vec!["0".to_string(), "1".to_string()],
+        };
+
+        server.push_execute([MockResponse::Response(
+            spark::execute_plan_response::ResponseType::GetResourcesCommandResult(
+                spark::GetResourcesCommandResult {
+                    resources: HashMap::from([("gpu".to_string(), gpu.clone())]),
+                },
+            ),
+        )]);
+
+        let spark = server.session().await?;
+
+        let resources = spark.resources().await?;
+
+        assert_eq!(Some(&gpu), resources.get("gpu"));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_session_config() -> Result<(), SparkError> {
         let value = "rust-test-app";
@@ -19,7 +19,8 @@
 
 use std::collections::HashMap;
 
-use crate::plan::LogicalPlanBuilder;
+use crate::metrics::ObservedMetrics;
+use crate::plan::{relation_common, LogicalPlanBuilder};
 use crate::session::SparkSession;
 use crate::spark;
 pub use crate::spark::write_stream_operation_start::Trigger;
@@ -93,14 +94,13 @@ impl DataStreamReader {
                 options: self.read_options,
                 paths,
                 predicates: vec![],
+                #[cfg(feature = "spark-4")]
+                source_name: None,
             })),
         }));
 
         let relation = spark::Relation {
-            common: Some(spark::RelationCommon {
-                source_info: "NA".to_string(),
-                plan_id: Some(1),
-            }),
+            common: Some(relation_common(1)),
             rel_type: read_type,
         };
 
@@ -239,13 +239,15 @@ impl DataStreamWriter {
             foreach_writer: None,
             trigger: self.trigger,
             sink_destination: sink,
+            #[cfg(feature = "spark-4")]
+            clustering_column_names: vec![],
         };
 
         let cmd = spark::command::CommandType::WriteStreamOperationStart(ops);
 
         let plan = LogicalPlanBuilder::plan_cmd(cmd);
 
-        let mut client = self.dataframe.spark_session.clone().client();
+        let client = self.dataframe.spark_session.clone().client();
 
         let operation_start_resp = client
             .execute_command_and_fetch(plan)
@@ -318,7 +320,7 @@ impl StreamingQuery {
             spark::command::CommandType::StreamingQueryCommand(command),
         );
 
-        let mut client = self.spark_session.clone().client();
+        let client = self.spark_session.clone().client();
 
         client
             .execute_command_and_fetch(plan)
@@ -437,6 +439,14 @@ impl StreamingQuery {
         to_json_object(progress?.recent_progress_json)
     }
 
+    /// Metrics observed with `DataFrame::observe` in the last progress of the query,
+    /// by observation name
+    pub async fn observed_metrics(&self) -> Result<HashMap<String, ObservedMetrics>, SparkError> {
+        let progress = self.last_progress().await?;
+
+        Ok(ObservedMetrics::from_progress(&progress))
+    }
+
     pub async fn is_active(&self) -> Result<bool, SparkError> {
         let status = self.fetch_status().await?;
 
@@ -592,7 +602,7 @@ impl StreamingQueryManager {
             spark::command::CommandType::StreamingQueryManagerCommand(command),
         );
 
-        let mut client = self.spark_session.clone().client();
+        let client = self.spark_session.clone().client();
 
         client
             .execute_command_and_fetch(plan)
@@ -0,0 +1,984 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! In-process mock Spark Connect server for testing without a Spark cluster
+//!
+//! Requires the `testing` feature. [MockSparkServer] serves scripted responses, while
+//! [ReplaySparkServer] serves the fixtures recorded with `SparkSessionBuilder::record_fixtures`.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use arrow::record_batch::RecordBatch;
+
+use futures_util::stream::{self, BoxStream, StreamExt};
+
+use tokio::sync::oneshot;
+
+use tonic::{Request, Response, Status, Streaming};
+
+use uuid::Uuid;
+
+use crate::client::RetryPolicy;
+use crate::errors::SparkError;
+use crate::plan::serialize;
+use crate::server::spawn;
+use crate::session::{SparkSession, SparkSessionBuilder};
+use crate::spark;
+
+use spark::add_artifacts_request::{ArtifactChunk, Payload};
+use spark::add_artifacts_response::ArtifactSummary;
+use spark::artifact_statuses_response::ArtifactStatus;
+use spark::config_request::operation::OpType;
+use spark::execute_plan_response::ResponseType;
+use spark::spark_connect_service_server::SparkConnectService;
+
+mod replay;
+
+pub use replay::ReplaySparkServer;
+
+/// [SparkSessionBuilder] connected to a test server, which retries without backoff
+fn test_builder(addr: SocketAddr) -> SparkSessionBuilder {
+    let policy = RetryPolicy::default()
+        .max_retries(3)
+        .initial_backoff(Duration::ZERO)
+        .jitter(Duration::ZERO);
+
+    SparkSessionBuilder::remote(&connection_string(addr)).retry_policy(policy)
+}
+
+fn connection_string(addr: SocketAddr) -> String {
+    format!("sc://{}:{}/", addr.ip(), addr.port())
+}
+
+/// A scripted item of an ExecutePlan response stream
+#[derive(Clone, Debug)]
+pub enum MockResponse {
+    /// Arrow batch encoded as an `ArrowBatch` response
+    Batch(RecordBatch),
+    /// Any other response type, e.g. a `SqlCommandResult`
+    Response(ResponseType),
+    /// Execution metrics of the plan, sent without a response type
+    Metrics(spark::execute_plan_response::Metrics),
+    /// Metrics observed by a `CollectMetrics` relation, sent without a response type
+    ObservedMetrics(spark::execute_plan_response::ObservedMetrics),
+    /// Fail the stream with the status. The remaining responses are sent on reattach
+    Error(Status),
+    /// Close the stream before the operation is complete, so the client has to reattach
+    Disconnect,
+}
+
+/// Requests received by the mock server, in the order they arrived
+#[derive(Clone, Debug, Default)]
+pub struct MockRequests {
+    pub execute: Vec<spark::ExecutePlanRequest>,
+    pub analyze: Vec<spark::AnalyzePlanRequest>,
+    pub config: Vec<spark::ConfigRequest>,
+    pub interrupt: Vec<spark::InterruptRequest>,
+    pub reattach: Vec<spark::ReattachExecuteRequest>,
+    pub release: Vec<spark::ReleaseExecuteRequest>,
+    pub add_artifacts: Vec<spark::AddArtifactsRequest>,
+    pub artifact_status: Vec<spark::ArtifactStatusesRequest>,
+    #[cfg(feature = "spark-4")]
+    pub release_session: Vec<spark::ReleaseSessionRequest>,
+    #[cfg(feature = "spark-4")]
+    pub fetch_error_details: Vec<spark::FetchErrorDetailsRequest>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    execute: VecDeque<Vec<MockResponse>>,
+    analyze: VecDeque<Result<spark::analyze_plan_response::Result, Status>>,
+    operations: HashMap<String, VecDeque<MockResponse>>,
+    artifacts: HashMap<String, Vec<u8>>,
+    conf: HashMap<String, String>,
+    crc_failures: Vec<String>,
+    #[cfg(feature = "spark-4")]
+    server_side_session_id: String,
+    #[cfg(feature = "spark-4")]
+    error_details: HashMap<String, spark::FetchErrorDetailsResponse>,
+    requests: MockRequests,
+}
+
+#[derive(Clone, Debug, Default)]
+struct MockService {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockService {
+    fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+
+    #[cfg(feature = "spark-4")]
+    fn server_side_session_id(&self) -> String {
+        self.state().server_side_session_id.clone()
+    }
+
+    #[cfg(feature = "spark-4")]
+    fn restart_session(&self) {
+        self.state().server_side_session_id = Uuid::new_v4().to_string();
+    }
+
+    /// Stream the remaining responses of an operation, up to the next error or disconnect
+    #[allow(clippy::result_large_err)]
+    fn respond(
+        &self,
+        session_id: &str,
+        operation_id: &str,
+    ) -> Result<BoxStream<'static, Result<spark::ExecutePlanResponse, Status>>, Status> {
+        let mut state = self.state();
+
+        #[cfg(feature = "spark-4")]
+        let server_side_session_id = state.server_side_session_id.clone();
+
+        let remaining = state.operations.get_mut(operation_id).ok_or_else(|| {
+            Status::not_found(format!(
+                "INVALID_HANDLE.OPERATION_NOT_FOUND {}",
+                operation_id
+            ))
+        })?;
+
+        let mut responses = vec![];
+        let mut error = None;
+
+        while let Some(item) = remaining.pop_front() {
+            let mut response = spark::ExecutePlanResponse {
+                session_id: session_id.to_string(),
+                #[cfg(feature = "spark-4")]
+                server_side_session_id: server_side_session_id.clone(),
+                operation_id: operation_id.to_string(),
+                response_id: Uuid::new_v4().to_string(),
+                ..Default::default()
+            };
+
+            match item {
+                MockResponse::Batch(batch) => {
+                    response.response_type = Some(ResponseType::ArrowBatch(
+                        spark::execute_plan_response::ArrowBatch {
+                            row_count: batch.num_rows() as i64,
+                            data: serialize(&batch)
+                                .map_err(|err| Status::internal(err.to_string()))?,
+                            #[cfg(feature = "spark-4")]
+                            start_offset: None,
+                            #[cfg(feature = "spark-4")]
+                            chunk_index: None,
+                            #[cfg(feature = "spark-4")]
+                            num_chunks_in_batch: None,
+                        },
+                    ))
+                }
+                MockResponse::Response(response_type) => {
+                    response.response_type = Some(response_type)
+                }
+                MockResponse::Metrics(metrics) => response.metrics = Some(metrics),
+                MockResponse::ObservedMetrics(metrics) => response.observed_metrics = vec![metrics],
+                MockResponse::Error(status) => {
+                    error = Some(status);
+                    break;
+                }
+                MockResponse::Disconnect => break,
+            };
+
+            responses.push(Ok(response));
+        }
+
+        // tonic discards the responses it buffered when the stream fails, so the error is
+        // only yielded after the preceding responses have been flushed to the client
+        let error = stream::iter(error).then(|status| async move {
+            tokio::task::yield_now().await;
+            Err(status)
+        });
+
+        Ok(stream::iter(responses).chain(error).boxed())
+    }
+
+    /// Store an artifact received by the server, unless its CRC check fails
+    fn add_artifact(&self, name: String, data: Vec<u8>, crc_ok: bool) -> ArtifactSummary {
+        let mut state = self.state();
+
+        let is_crc_successful = crc_ok && !state.crc_failures.contains(&name);
+
+        if is_crc_successful {
+            state.artifacts.insert(name.clone(), data);
+        }
+
+        ArtifactSummary {
+            name,
+            is_crc_successful,
+        }
+    }
+}
+
+fn crc_matches(chunk: &ArtifactChunk) -> bool {
+    crc32fast::hash(&chunk.data) as i64 == chunk.crc
+}
+
+#[tonic::async_trait]
+impl SparkConnectService for MockService {
+    type ExecutePlanStream = BoxStream<'static, Result<spark::ExecutePlanResponse, Status>>;
+    type ReattachExecuteStream = BoxStream<'static, Result<spark::ExecutePlanResponse, Status>>;
+
+    async fn execute_plan(
+        &self,
+        request: Request<spark::ExecutePlanRequest>,
+    ) -> Result<Response<Self::ExecutePlanStream>, Status> {
+        let req = request.into_inner();
+
+        let operation_id = req
+            .operation_id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        {
+            let mut state = self.state();
+
+            // like Spark, an operation id can only be executed once
+            let exists = state
+                .requests
+                .execute
+                .iter()
+                .any(|prev| prev.operation_id.as_ref() == Some(&operation_id));
+
+            if exists {
+                return Err(Status::already_exists(format!(
+                    "INVALID_HANDLE.OPERATION_ALREADY_EXISTS {}",
+                    operation_id
+                )));
+            }
+
+            let mut responses: VecDeque<MockResponse> =
+                state.execute.pop_front().unwrap_or_default().into();
+
+            responses.push_back(MockResponse::Response(ResponseType::ResultComplete(
+                spark::execute_plan_response::ResultComplete {},
+            )));
+
+            state.operations.insert(operation_id.clone(), responses);
+            state.requests.execute.push(req.clone());
+        }
+
+        Ok(Response::new(self.respond(&req.session_id, &operation_id)?))
+    }
+
+    async fn analyze_plan(
+        &self,
+        request: Request<spark::AnalyzePlanRequest>,
+    ) -> Result<Response<spark::AnalyzePlanResponse>, Status> {
+        let req = request.into_inner();
+
+        let mut state = self.state();
+
+        state.requests.analyze.push(req.clone());
+
+        let result = match state.analyze.pop_front() {
+            Some(result) => result?,
+            None => return Err(Status::unimplemented("no analyze response was scripted")),
+        };
+
+        Ok(Response::new(spark::AnalyzePlanResponse {
+            session_id: req.session_id,
+            #[cfg(feature = "spark-4")]
+            server_side_session_id: state.server_side_session_id.clone(),
+            result: Some(result),
+        }))
+    }
+
+    async fn config(
+        &self,
+        request: Request<spark::ConfigRequest>,
+    ) -> Result<Response<spark::ConfigResponse>, Status> {
+        let req = request.into_inner();
+
+        let mut state = self.state();
+
+        state.requests.config.push(req.clone());
+
+        // keys that were never set have their default value
+        let pairs = match req.operation.as_ref().and_then(|op| op.op_type.as_ref()) {
+            Some(OpType::Set(set)) => {
+                for pair in &set.pairs {
+                    let value = pair.value.clone().unwrap_or_default();
+                    state.conf.insert(pair.key.clone(), value);
+                }
+                vec![]
+            }
+            Some(OpType::GetWithDefault(get)) => get
+                .pairs
+                .iter()
+                .map(|pair| spark::KeyValue {
+                    key: pair.key.clone(),
+                    value: state.conf.get(&pair.key).cloned().or(pair.value.clone()),
+                })
+                .collect(),
+            _ => vec![],
+        };
+
+        Ok(Response::new(spark::ConfigResponse {
+            session_id: req.session_id,
+            #[cfg(feature = "spark-4")]
+            server_side_session_id: state.server_side_session_id.clone(),
+            pairs,
+            warnings: vec![],
+        }))
+    }
+
+    async fn add_artifacts(
+        &self,
+        request: Request<Streaming<spark::AddArtifactsRequest>>,
+    ) -> Result<Response<spark::AddArtifactsResponse>, Status> {
+        let mut stream = request.into_inner();
+
+        let mut artifacts = vec![];
+
+        // name, data, expected number of chunks and CRC state of the chunked artifact
+        let mut chunked: Option<(String, Vec<u8>, i64, bool)> = None;
+        let mut received = 0;
+
+        #[cfg(feature = "spark-4")]
+        let mut session_id = None;
+
+        while let Some(req) = stream.message().await? {
+            self.state().requests.add_artifacts.push(req.clone());
+
+            #[cfg(feature = "spark-4")]
+            session_id.get_or_insert(req.session_id);
+
+            match req.payload {
+                Some(Payload::Batch(batch)) => {
+                    for artifact in batch.artifacts {
+                        let chunk = artifact.data.unwrap_or_default();
+                        let crc_ok = crc_matches(&chunk);
+
+                        artifacts.push(self.add_artifact(artifact.name, chunk.data, crc_ok));
+                    }
+                }
+                Some(Payload::BeginChunk(begin)) => {
+                    let chunk = begin.initial_chunk.unwrap_or_default();
+                    let crc_ok = crc_matches(&chunk);
+
+                    received = 1;
+                    chunked = Some((begin.name, chunk.data, begin.num_chunks, crc_ok));
+                }
+                Some(Payload::Chunk(chunk)) => {
+                    let Some((_, data, _, crc_ok)) = chunked.as_mut() else {
+                        return Err(Status::invalid_argument("chunk without a begin chunk"));
+                    };
+
+                    received += 1;
+                    data.extend_from_slice(&chunk.data);
+                    *crc_ok &= crc_matches(&chunk);
+                }
+                None => {}
+            }
+
+            if let Some((_, _, num_chunks, _)) = &chunked {
+                if received == *num_chunks {
+                    let (name, data, _, crc_ok) = chunked.take().unwrap();
+                    artifacts.push(self.add_artifact(name, data, crc_ok));
+                }
+            }
+        }
+
+        Ok(Response::new(spark::AddArtifactsResponse {
+            #[cfg(feature = "spark-4")]
+            session_id: session_id.unwrap_or_default(),
+            #[cfg(feature = "spark-4")]
+            server_side_session_id: self.server_side_session_id(),
+            artifacts,
+        }))
+    }
+
+    async fn artifact_status(
+        &self,
+        request: Request<spark::ArtifactStatusesRequest>,
+    ) -> Result<Response<spark::ArtifactStatusesResponse>, Status> {
+        let req = request.into_inner();
+
+        let mut state = self.state();
+
+        state.requests.artifact_status.push(req.clone());
+
+        // like Spark 3.5, only the artifacts of the session cache are reported
+        let statuses = req
+            .names
+            .into_iter()
+            .map(|name| {
+                let exists = name.starts_with("cache/") && state.artifacts.contains_key(&name);
+                (name, ArtifactStatus { exists })
+            })
+            .collect();
+
+        Ok(Response::new(spark::ArtifactStatusesResponse {
+            #[cfg(feature = "spark-4")]
+            session_id: req.session_id,
+            #[cfg(feature = "spark-4")]
+            server_side_session_id: state.server_side_session_id.clone(),
+            statuses,
+        }))
+    }
+
+    async fn interrupt(
+        &self,
+        request: Request<spark::InterruptRequest>,
+    ) -> Result<Response<spark::InterruptResponse>, Status> {
+        let req = request.into_inner();
+
+        let mut state = self.state();
+
+        let interrupted_ids = match &req.interrupt {
+            Some(spark::interrupt_request::Interrupt::OperationId(id)) => state
+                .operations
+                .remove(id)
+                .map(|_| vec![id.clone()])
+                .unwrap_or_default(),
+            _ => state.operations.drain().map(|(id, _)| id).collect(),
+        };
+
+        state.requests.interrupt.push(req.clone());
+
+        Ok(Response::new(spark::InterruptResponse {
+            session_id: req.session_id,
+            #[cfg(feature = "spark-4")]
+            server_side_session_id: state.server_side_session_id.clone(),
+            interrupted_ids,
+        }))
+    }
+
+    async fn reattach_execute(
+        &self,
+        request: Request<spark::ReattachExecuteRequest>,
+    ) -> Result<Response<Self::ReattachExecuteStream>, Status> {
+        let req = request.into_inner();
+
+        self.state().requests.reattach.push(req.clone());
+
+        Ok(Response::new(
+            self.respond(&req.session_id, &req.operation_id)?,
+        ))
+    }
+
+    async fn release_execute(
+        &self,
+        request: Request<spark::ReleaseExecuteRequest>,
+    ) -> Result<Response<spark::ReleaseExecuteResponse>, Status> {
+        let req = request.into_inner();
+
+        let mut state = self.state();
+
+        if let Some(spark::release_execute_request::Release::ReleaseAll(_)) = req.release {
+            state.operations.remove(&req.operation_id);
+        }
+
+        state.requests.release.push(req.clone());
+
+        Ok(Response::new(spark::ReleaseExecuteResponse {
+            session_id: req.session_id,
+            #[cfg(feature = "spark-4")]
+            server_side_session_id: state.server_side_session_id.clone(),
+            operation_id: Some(req.operation_id),
+        }))
+    }
+
+    #[cfg(feature = "spark-4")]
+    async fn release_session(
+        &self,
+        request: Request<spark::ReleaseSessionRequest>,
+    ) -> Result<Response<spark::ReleaseSessionResponse>, Status> {
+        let req = request.into_inner();
+
+        let mut state = self.state();
+
+        state.operations.clear();
+        state.conf.clear();
+        state.requests.release_session.push(req.clone());
+
+        Ok(Response::new(spark::ReleaseSessionResponse {
+            session_id: req.session_id,
+            server_side_session_id: state.server_side_session_id.clone(),
+        }))
+    }
+
+    #[cfg(feature = "spark-4")]
+    async fn fetch_error_details(
+        &self,
+        request: Request<spark::FetchErrorDetailsRequest>,
+    ) -> Result<Response<spark::FetchErrorDetailsResponse>, Status> {
+        let req = request.into_inner();
+
+        let mut state = self.state();
+
+        state.requests.fetch_error_details.push(req.clone());
+
+        // like Spark, unknown error ids have no details
+        let details = state
+            .error_details
+            .get(&req.error_id)
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(Response::new(spark::FetchErrorDetailsResponse {
+            session_id: req.session_id,
+            server_side_session_id: state.server_side_session_id.clone(),
+            ..details
+        }))
+    }
+
+    #[cfg(feature = "spark-4")]
+    async fn clone_session(
+        &self,
+        _request: Request<spark::CloneSessionRequest>,
+    ) -> Result<Response<spark::CloneSessionResponse>, Status> {
+        Err(Status::unimplemented("CloneSession is not supported"))
+    }
+
+    #[cfg(feature = "spark-4")]
+    async fn get_status(
+        &self,
+        _request: Request<spark::GetStatusRequest>,
+    ) -> Result<Response<spark::GetStatusResponse>, Status> {
+        Err(Status::unimplemented("GetStatus is not supported"))
+    }
+}
+
+/// Spark Connect server running in-process on an ephemeral port
+///
+/// Responses are scripted per RPC, and every received request is recorded so tests can
+/// assert on the plans sent by the client. ExecutePlan streams end with a `ResultComplete`
+/// response after the scripted responses. The server shuts down when it is dropped.
+///
+/// # Example
+/// ```rust
+/// let server = MockSparkServer::start().await?;
+///
+/// server.push_execute([MockResponse::Batch(batch.clone()), MockResponse::Disconnect]);
+///
+/// let spark = server.session().await?;
+///
+/// let records = spark.range(None, 3, 1, None).collect().await?;
+///
+/// assert_eq!(1, server.requests().reattach.len());
+/// ```
+#[derive(Debug)]
+pub struct MockSparkServer {
+    addr: SocketAddr,
+    service: MockService,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockSparkServer {
+    /// Start the server on an ephemeral port of the loopback interface
+    pub async fn start() -> Result<MockSparkServer, SparkError> {
+        let service = MockService::default();
+
+        #[cfg(feature = "spark-4")]
+        service.restart_session();
+
+        let (addr, shutdown) = spawn(service.clone()).await?;
+
+        Ok(MockSparkServer {
+            addr,
+            service,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// Connection string of the server
+    pub fn connection_string(&self) -> String {
+        connection_string(self.addr)
+    }
+
+    /// [SparkSessionBuilder] connected to the server, which retries without backoff
+    pub fn builder(&self) -> SparkSessionBuilder {
+        test_builder(self.addr)
+    }
+
+    /// Connect a new [SparkSession] to the server
+    pub async fn session(&self) -> Result<SparkSession, SparkError> {
+        self.builder().build().await
+    }
+
+    /// Script the responses to the next ExecutePlan request
+    pub fn push_execute(&self, responses: impl IntoIterator<Item = MockResponse>) {
+        self.service
+            .state()
+            .execute
+            .push_back(responses.into_iter().collect());
+    }
+
+    /// Script the result of the next AnalyzePlan request
+    pub fn push_analyze(&self, result: spark::analyze_plan_response::Result) {
+        self.service.state().analyze.push_back(Ok(result));
+    }
+
+    /// Fail the next AnalyzePlan request with the status
+    pub fn push_analyze_error(&self, status: Status) {
+        self.service.state().analyze.push_back(Err(status));
+    }
+
+    /// Details of the error with the id returned by FetchErrorDetails
+    #[cfg(feature = "spark-4")]
+    pub fn push_error_details(
+        &self,
+        error_id: impl Into<String>,
+        details: spark::FetchErrorDetailsResponse,
+    ) {
+        self.service
+            .state()
+            .error_details
+            .insert(error_id.into(), details);
+    }
+
+    /// Replace the server side session id, like a server that restarted and lost the
+    /// state of its sessions
+    #[cfg(feature = "spark-4")]
+    pub fn restart_session(&self) {
+        self.service.restart_session();
+    }
+
+    /// Fail the CRC check of the artifact with the name, so it is not stored
+    pub fn fail_artifact_crc(&self, name: impl Into<String>) {
+        self.service.state().crc_failures.push(name.into());
+    }
+
+    /// Artifacts stored by the server, by name
+    pub fn artifacts(&self) -> HashMap<String, Vec<u8>> {
+        self.service.state().artifacts.clone()
+    }
+
+    /// Requests received so far
+    pub fn requests(&self) -> MockRequests {
+        self.service.state().requests.clone()
+    }
+
+    /// Plans of the ExecutePlan requests received so far
+    pub fn plans(&self) -> Vec<spark::Plan> {
+        self.requests()
+            .execute
+            .into_iter()
+            .filter_map(|req| req.plan)
+            .collect()
+    }
+}
+
+impl Drop for MockSparkServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use arrow::array::{ArrayRef, Int64Array};
+
+    use spark::release_execute_request::Release;
+
+    fn mock_batch(values: Vec<i64>) -> RecordBatch {
+        let id: ArrayRef = Arc::new(Int64Array::from(values));
+
+        RecordBatch::try_from_iter(vec![("id", id)]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_mock_collect() -> Result<(), SparkError> {
+        let server = MockSparkServer::start().await?;
+        let spark = server.session().await?;
+
+        server.push_execute([MockResponse::Batch(mock_batch(vec![0, 1, 2]))]);
+
+        let records = spark.range(None, 3, 1, None).collect().await?;
+
+        assert_eq!(mock_batch(vec![0, 1, 2]), records);
+
+        let requests = server.requests();
+
+        assert_eq!(1, server.plans().len());
+        assert!(matches!(
+            requests.release[0].release,
+            Some(Release::ReleaseAll(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_reattach_after_disconnect() -> Result<(), SparkError> {
+        let server = MockSparkServer::start().await?;
+        let spark = server.session().await?;
+
+        server.push_execute([
+            MockResponse::Batch(mock_batch(vec![0, 1])),
+            MockResponse::Disconnect,
+            MockResponse::Batch(mock_batch(vec![2])),
+        ]);
+
+        let records = spark.range(None, 3, 1, None).collect().await?;
+
+        assert_eq!(mock_batch(vec![0, 1, 2]), records);
+
+        let requests = server.requests();
+
+        assert_eq!(1, requests.execute.len());
+        assert_eq!(1, requests.reattach.len());
+        assert!(requests.reattach[0].last_response_id.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_resume_after_retryable_error() -> Result<(), SparkError> {
+        let server = MockSparkServer::start().await?;
+        let spark = server.session().await?;
+
+        server.push_execute([
+            MockResponse::Batch(mock_batch(vec![0])),
+            MockResponse::Error(Status::unavailable("connection reset")),
+            MockResponse::Batch(mock_batch(vec![1])),
+        ]);
+
+        let records = spark.range(None, 2, 1, None).collect().await?;
+
+        assert_eq!(mock_batch(vec![0, 1]), records);
+        assert_eq!(1, server.requests().reattach.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_reattach_after_first_response() -> Result<(), SparkError> {
+        let server = MockSparkServer::start().await?;
+        let spark = server.session().await?;
+
+        server.push_execute([
+            MockResponse::Batch(mock_batch(vec![0])),
+            MockResponse::Error(Status::unavailable("connection reset")),
+            MockResponse::Error(Status::unavailable("connection reset")),
+            MockResponse::Batch(mock_batch(vec![1, 2])),
+        ]);
+
+        let records = spark.range(None, 3, 1, None).collect().await?;
+
+        assert_eq!(mock_batch(vec![0, 1, 2]), records);
+
+        // the plan is executed once, and resumed after the first response
+        let requests = server.requests();
+        let operation_id = requests.execute[0].operation_id.clone().unwrap();
+
+        assert_eq!(1, requests.execute.len());
+        assert_eq!(2, requests.reattach.len());
+
+        for reattach in &requests.reattach {
+            assert_eq!(operation_id, reattach.operation_id);
+            assert!(reattach.last_response_id.is_some());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_non_retryable_error() -> Result<(), SparkError> {
+        let server = MockSparkServer::start().await?;
+        let spark = server.session().await?;
+
+        server.push_execute([
+            MockResponse::Batch(mock_batch(vec![0])),
+            MockResponse::Error(Status::invalid_argument("bad plan")),
+        ]);
+
+        let result = spark.range(None, 2, 1, None).collect().await;
+
+        assert!(matches!(result, Err(SparkError::InvalidArgument(_))));
+
+        let requests = server.requests();
+
+        assert!(requests.reattach.is_empty());
+        assert!(matches!(
+            requests.release[0].release,
+            Some(Release::ReleaseUntil(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_analyze() -> Result<(), SparkError> {
+        let server = MockSparkServer::start().await?;
+        let spark = server.session().await?;
+
+        server.push_analyze(spark::analyze_plan_response::Result::SparkVersion(
+            spark::analyze_plan_response::SparkVersion {
+                version: "3.5.1".to_string(),
+            },
+        ));
+
+        assert_eq!("3.5.1", spark.version().await?);
+
+        server.push_analyze_error(Status::internal("analysis failed"));
+
+        assert!(spark.version().await.is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "spark-4")]
+    #[tokio::test]
+    async fn test_mock_progress_handler() -> Result<(), SparkError> {
+        use crate::metrics::ExecutionProgress;
+        use spark::execute_plan_response::execution_progress::StageInfo;
+
+        let server = MockSparkServer::start().await?;
+        let spark = server.session().await?;
+
+        let stage = |num_completed_tasks| StageInfo {
+            stage_id: 1,
+            num_tasks: 4,
+            num_completed_tasks,
+            input_bytes_read: 0,
+            done: num_completed_tasks == 4,
+        };
+
+        let progress = |num_completed_tasks| {
+            MockResponse::Response(ResponseType::ExecutionProgress(
+                spark::execute_plan_response::ExecutionProgress {
+                    stages: vec![stage(num_completed_tasks)],
+                    num_inflight_tasks: 4 - num_completed_tasks,
+                },
+            ))
+        };
+
+        server.push_execute([
+            progress(1),
+            progress(4),
+            MockResponse::Batch(mock_batch(vec![0])),
+        ]);
+
+        let received: Arc<Mutex<Vec<ExecutionProgress>>> = Arc::default();
+        let handler_received = received.clone();
+
+        spark.register_progress_handler(move |progress| {
+            handler_received.lock().unwrap().push(progress.clone())
+        });
+
+        spark.range(None, 1, 1, None).collect().await?;
+
+        let received = received.lock().unwrap().clone();
+        let operation_id = server.requests().execute[0].operation_id.clone().unwrap();
+
+        assert_eq!(2, received.len());
+        assert_eq!(operation_id, received[0].operation_id);
+        assert_eq!((4, 1), received[0].tasks());
+        assert_eq!(0, received[1].num_inflight_tasks);
+        assert!(received[1].stages[0].done);
+
+        spark.clear_progress_handlers();
+        server.push_execute([progress(4)]);
+
+        spark.range(None, 1, 1, None).collect().await?;
+
+        assert_eq!(2, received.len());
+        Ok(())
+    }
+
+    #[cfg(feature = "spark-4")]
+    #[tokio::test]
+    async fn test_mock_release_session() -> Result<(), SparkError> {
+        let server = MockSparkServer::start().await?;
+        let spark = server.session().await?;
+
+        spark.stop().await?;
+
+        let requests = server.requests().release_session;
+
+        assert_eq!(1, requests.len());
+        assert_eq!(spark.session_id(), requests[0].session_id);
+        Ok(())
+    }
+
+    #[cfg(feature = "spark-4")]
+    #[tokio::test]
+    async fn test_mock_server_side_session_changed() -> Result<(), SparkError> {
+        let server = MockSparkServer::start().await?;
+        let spark = server.session().await?;
+
+        let version = || {
+            spark::analyze_plan_response::Result::SparkVersion(
+                spark::analyze_plan_response::SparkVersion {
+                    version: "4.0.0".to_string(),
+                },
+            )
+        };
+
+        server.push_analyze(version());
+
+        assert_eq!("4.0.0", spark.version().await?);
+
+        // the next request carries the server side session id observed by the client
+        server.restart_session();
+        server.push_analyze(version());
+
+        let result = spark.version().await;
+
+        assert!(matches!(
+            result,
+            Err(SparkError::SessionNotSameException(_))
+        ));
+        assert!(server.requests().analyze[1]
+            .client_observed_server_side_session_id
+            .is_some());
+        Ok(())
+    }
+
+    #[cfg(feature = "spark-4")]
+    #[tokio::test]
+    async fn test_mock_fetch_error_details() -> Result<(), SparkError> {
+        use crate::errors::status_with_error_info;
+        use spark::fetch_error_details_response::Error;
+
+        let server = MockSparkServer::start().await?;
+        let spark = server.session().await?;
+
+        let metadata = HashMap::from([
+            (
+                "errorClass".to_string(),
+                "TABLE_OR_VIEW_NOT_FOUND".to_string(),
+            ),
+            ("errorId".to_string(), "e1".to_string()),
+        ]);
+
+        server.push_analyze_error(status_with_error_info(metadata));
+        server.push_error_details(
+            "e1",
+            spark::FetchErrorDetailsResponse {
+                root_error_idx: Some(0),
+                errors: vec![Error {
+                    message: "full message".to_string(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+
+        let err = spark.version().await.unwrap_err();
+
+        assert!(matches!(err, SparkError::Server { ref message, .. } if message == "full message"));
+        assert_eq!(Some("TABLE_OR_VIEW_NOT_FOUND"), err.error_class());
+        assert_eq!("e1", server.requests().fetch_error_details[0].error_id);
+        Ok(())
+    }
+}
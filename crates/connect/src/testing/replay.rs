@@ -0,0 +1,538 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Spark Connect server that replays recorded fixtures
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use futures_util::stream::{self, BoxStream, StreamExt};
+
+use prost::Message;
+
+use tokio::sync::oneshot;
+
+use tonic::{Code, Request, Response, Status, Streaming};
+
+use crate::client::fixtures::{read_fixtures, Fixture, FixtureRequest};
+use crate::errors::SparkError;
+use crate::server::spawn;
+use crate::session::{SparkSession, SparkSessionBuilder};
+use crate::spark;
+
+use spark::spark_connect_service_server::SparkConnectService;
+
+use super::{connection_string, test_builder};
+
+#[derive(Debug)]
+struct ReplayEntry {
+    requests: Vec<FixtureRequest>,
+    fixture: Fixture,
+    replayed: bool,
+}
+
+#[derive(Clone, Debug)]
+struct ReplayService {
+    entries: Arc<Mutex<Vec<ReplayEntry>>>,
+}
+
+impl ReplayService {
+    fn new(fixtures: Vec<Fixture>) -> Result<Self, SparkError> {
+        let entries = fixtures
+            .into_iter()
+            .map(|fixture| {
+                let requests = fixture
+                    .requests
+                    .iter()
+                    .map(|msg| FixtureRequest::decode(&fixture.method, msg))
+                    .collect::<Result<_, _>>()?;
+
+                Ok(ReplayEntry {
+                    requests,
+                    fixture,
+                    replayed: false,
+                })
+            })
+            .collect::<Result<_, SparkError>>()?;
+
+        Ok(ReplayService {
+            entries: Arc::new(Mutex::new(entries)),
+        })
+    }
+
+    /// Find the recording of the call, preferring the ones that were not replayed yet
+    ///
+    /// Identical calls are replayed in the order they were recorded, and the last
+    /// recording is repeated once they are all used.
+    #[allow(clippy::result_large_err)]
+    fn replay(&self, method: &str, mut requests: Vec<FixtureRequest>) -> Result<Fixture, Status> {
+        requests.iter_mut().for_each(FixtureRequest::normalize);
+
+        let mut entries = self.entries.lock().unwrap();
+
+        let matches =
+            |entry: &ReplayEntry| entry.fixture.method == method && entry.requests == requests;
+
+        let idx = entries
+            .iter()
+            .position(|entry| matches(entry) && !entry.replayed)
+            .or_else(|| entries.iter().rposition(matches))
+            .ok_or_else(|| {
+                Status::not_found(format!(
+                    "No recorded fixture matches the {} request",
+                    method
+                ))
+            })?;
+
+        let entry = &mut entries[idx];
+        entry.replayed = true;
+
+        Ok(entry.fixture.clone())
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn responses<M: Message + Default>(fixture: &Fixture) -> Result<Vec<M>, Status> {
+        fixture
+            .responses
+            .iter()
+            .map(|msg| M::decode(msg.as_slice()).map_err(|err| Status::internal(err.to_string())))
+            .collect()
+    }
+
+    fn status(fixture: &Fixture) -> Option<Status> {
+        match Code::from_i32(fixture.code) {
+            Code::Ok => None,
+            code => Some(Status::with_details(
+                code,
+                fixture.message.clone(),
+                fixture.details.clone().into(),
+            )),
+        }
+    }
+
+    /// Replay a unary call, with the session id of the request
+    #[allow(clippy::result_large_err)]
+    fn unary<M: Message + Default>(
+        &self,
+        method: &str,
+        request: FixtureRequest,
+        session_id: &str,
+        set_session: impl FnOnce(&mut M, String),
+    ) -> Result<Response<M>, Status> {
+        let fixture = self.replay(method, vec![request])?;
+
+        if let Some(status) = Self::status(&fixture) {
+            return Err(status);
+        }
+
+        let mut response = Self::responses::<M>(&fixture)?
+            .pop()
+            .ok_or_else(|| Status::internal(format!("The {} fixture has no response", method)))?;
+
+        set_session(&mut response, session_id.to_string());
+
+        Ok(Response::new(response))
+    }
+
+    /// Replay a response stream, with the session and operation id of the request
+    #[allow(clippy::result_large_err)]
+    fn stream(
+        &self,
+        method: &str,
+        request: FixtureRequest,
+        session_id: &str,
+        operation_id: Option<String>,
+    ) -> Result<BoxStream<'static, Result<spark::ExecutePlanResponse, Status>>, Status> {
+        let fixture = self.replay(method, vec![request])?;
+
+        let responses = Self::responses::<spark::ExecutePlanResponse>(&fixture)?
+            .into_iter()
+            .map(|mut response| {
+                response.session_id = session_id.to_string();
+                if let Some(operation_id) = &operation_id {
+                    response.operation_id = operation_id.clone();
+                }
+                Ok(response)
+            })
+            .collect::<Vec<_>>();
+
+        // same as the mock server, the error follows once the responses are flushed
+        let error = stream::iter(Self::status(&fixture)).then(|status| async move {
+            tokio::task::yield_now().await;
+            Err(status)
+        });
+
+        Ok(stream::iter(responses).chain(error).boxed())
+    }
+}
+
+#[tonic::async_trait]
+impl SparkConnectService for ReplayService {
+    type ExecutePlanStream = BoxStream<'static, Result<spark::ExecutePlanResponse, Status>>;
+    type ReattachExecuteStream = BoxStream<'static, Result<spark::ExecutePlanResponse, Status>>;
+
+    async fn execute_plan(
+        &self,
+        request: Request<spark::ExecutePlanRequest>,
+    ) -> Result<Response<Self::ExecutePlanStream>, Status> {
+        let req = request.into_inner();
+
+        let session_id = req.session_id.clone();
+        let operation_id = req.operation_id.clone();
+
+        let stream = self.stream(
+            "ExecutePlan",
+            FixtureRequest::ExecutePlan(Box::new(req)),
+            &session_id,
+            operation_id,
+        )?;
+
+        Ok(Response::new(stream))
+    }
+
+    async fn analyze_plan(
+        &self,
+        request: Request<spark::AnalyzePlanRequest>,
+    ) -> Result<Response<spark::AnalyzePlanResponse>, Status> {
+        let req = request.into_inner();
+        let session_id = req.session_id.clone();
+
+        self.unary(
+            "AnalyzePlan",
+            FixtureRequest::AnalyzePlan(Box::new(req)),
+            &session_id,
+            |resp: &mut spark::AnalyzePlanResponse, id| resp.session_id = id,
+        )
+    }
+
+    async fn config(
+        &self,
+        request: Request<spark::ConfigRequest>,
+    ) -> Result<Response<spark::ConfigResponse>, Status> {
+        let req = request.into_inner();
+        let session_id = req.session_id.clone();
+
+        self.unary(
+            "Config",
+            FixtureRequest::Config(req),
+            &session_id,
+            |resp: &mut spark::ConfigResponse, id| resp.session_id = id,
+        )
+    }
+
+    async fn add_artifacts(
+        &self,
+        request: Request<Streaming<spark::AddArtifactsRequest>>,
+    ) -> Result<Response<spark::AddArtifactsResponse>, Status> {
+        let mut stream = request.into_inner();
+
+        let mut requests = vec![];
+        while let Some(req) = stream.message().await? {
+            requests.push(FixtureRequest::AddArtifacts(req));
+        }
+
+        let fixture = self.replay("AddArtifacts", requests)?;
+
+        if let Some(status) = Self::status(&fixture) {
+            return Err(status);
+        }
+
+        Self::responses(&fixture)?
+            .pop()
+            .map(Response::new)
+            .ok_or_else(|| Status::internal("The AddArtifacts fixture has no response"))
+    }
+
+    async fn artifact_status(
+        &self,
+        request: Request<spark::ArtifactStatusesRequest>,
+    ) -> Result<Response<spark::ArtifactStatusesResponse>, Status> {
+        let req = request.into_inner();
+        let session_id = req.session_id.clone();
+
+        self.unary(
+            "ArtifactStatus",
+            FixtureRequest::ArtifactStatus(req),
+            &session_id,
+            |_: &mut spark::ArtifactStatusesResponse, _| {},
+        )
+    }
+
+    async fn interrupt(
+        &self,
+        request: Request<spark::InterruptRequest>,
+    ) -> Result<Response<spark::InterruptResponse>, Status> {
+        let req = request.into_inner();
+        let session_id = req.session_id.clone();
+
+        self.unary(
+            "Interrupt",
+            FixtureRequest::Interrupt(req),
+            &session_id,
+            |resp: &mut spark::InterruptResponse, id| resp.session_id = id,
+        )
+    }
+
+    async fn reattach_execute(
+        &self,
+        request: Request<spark::ReattachExecuteRequest>,
+    ) -> Result<Response<Self::ReattachExecuteStream>, Status> {
+        let req = request.into_inner();
+
+        let session_id = req.session_id.clone();
+        let operation_id = Some(req.operation_id.clone());
+
+        let stream = self.stream(
+            "ReattachExecute",
+            FixtureRequest::ReattachExecute(req),
+            &session_id,
+            operation_id,
+        )?;
+
+        Ok(Response::new(stream))
+    }
+
+    async fn release_execute(
+        &self,
+        request: Request<spark::ReleaseExecuteRequest>,
+    ) -> Result<Response<spark::ReleaseExecuteResponse>, Status> {
+        let req = request.into_inner();
+
+        // the responses of a replayed operation are not held, so any release succeeds
+        Ok(Response::new(spark::ReleaseExecuteResponse {
+            session_id: req.session_id,
+            // an empty id is not validated by the client
+            #[cfg(feature = "spark-4")]
+            server_side_session_id: String::new(),
+            operation_id: Some(req.operation_id),
+        }))
+    }
+
+    #[cfg(feature = "spark-4")]
+    async fn release_session(
+        &self,
+        request: Request<spark::ReleaseSessionRequest>,
+    ) -> Result<Response<spark::ReleaseSessionResponse>, Status> {
+        let req = request.into_inner();
+        let session_id = req.session_id.clone();
+
+        self.unary(
+            "ReleaseSession",
+            FixtureRequest::ReleaseSession(req),
+            &session_id,
+            |resp: &mut spark::ReleaseSessionResponse, id| resp.session_id = id,
+        )
+    }
+
+    #[cfg(feature = "spark-4")]
+    async fn fetch_error_details(
+        &self,
+        request: Request<spark::FetchErrorDetailsRequest>,
+    ) -> Result<Response<spark::FetchErrorDetailsResponse>, Status> {
+        let req = request.into_inner();
+        let session_id = req.session_id.clone();
+
+        self.unary(
+            "FetchErrorDetails",
+            FixtureRequest::FetchErrorDetails(req),
+            &session_id,
+            |resp: &mut spark::FetchErrorDetailsResponse, id| resp.session_id = id,
+        )
+    }
+
+    #[cfg(feature = "spark-4")]
+    async fn clone_session(
+        &self,
+        _request: Request<spark::CloneSessionRequest>,
+    ) -> Result<Response<spark::CloneSessionResponse>, Status> {
+        Err(Status::unimplemented("CloneSession can not be replayed"))
+    }
+
+    #[cfg(feature = "spark-4")]
+    async fn get_status(
+        &self,
+        _request: Request<spark::GetStatusRequest>,
+    ) -> Result<Response<spark::GetStatusResponse>, Status> {
+        Err(Status::unimplemented("GetStatus can not be replayed"))
+    }
+}
+
+/// Spark Connect server that serves back the calls recorded with
+/// `SparkSessionBuilder::record_fixtures`, running in-process on an ephemeral port
+///
+/// Requests are matched to the recording after normalizing their plans, so the random
+/// plan ids, session ids and operation ids of the replaying run do not matter.
+/// Requests without a recording fail with a `NotFound` status.
+///
+/// # Example
+/// ```rust
+/// // record against a live cluster
+/// let spark = SparkSessionBuilder::remote("sc://127.0.0.1:15002/")
+///     .record_fixtures("tests/fixtures/range.jsonl")
+///     .build()
+///     .await?;
+///
+/// spark.range(None, 10, 1, None).collect().await?;
+///
+/// // replay on CI
+/// let server = ReplaySparkServer::start("tests/fixtures/range.jsonl").await?;
+///
+/// let spark = server.session().await?;
+///
+/// spark.range(None, 10, 1, None).collect().await?;
+/// ```
+#[derive(Debug)]
+pub struct ReplaySparkServer {
+    addr: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl ReplaySparkServer {
+    /// Start the server with the fixtures of the file
+    pub async fn start(path: impl AsRef<Path>) -> Result<ReplaySparkServer, SparkError> {
+        let service = ReplayService::new(read_fixtures(path)?)?;
+
+        let (addr, shutdown) = spawn(service).await?;
+
+        Ok(ReplaySparkServer {
+            addr,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// Connection string of the server
+    pub fn connection_string(&self) -> String {
+        connection_string(self.addr)
+    }
+
+    /// [SparkSessionBuilder] connected to the server, which retries without backoff
+    pub fn builder(&self) -> SparkSessionBuilder {
+        test_builder(self.addr)
+    }
+
+    /// Connect a new [SparkSession] to the server
+    pub async fn session(&self) -> Result<SparkSession, SparkError> {
+        self.builder().build().await
+    }
+}
+
+impl Drop for ReplaySparkServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use arrow::array::{ArrayRef, Int64Array};
+    use arrow::record_batch::RecordBatch;
+
+    use crate::errors::status_with_error_info;
+    use crate::testing::{MockResponse, MockSparkServer};
+
+    fn fixture_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "spark-connect-fixtures-{}.jsonl",
+            uuid::Uuid::new_v4()
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() -> Result<(), SparkError> {
+        let path = fixture_path();
+
+        let id: ArrayRef = Arc::new(Int64Array::from(vec![2, 3, 4]));
+        let batch = RecordBatch::try_from_iter(vec![("id", id)])?;
+
+        let recorded = {
+            let mock = MockSparkServer::start().await?;
+            mock.push_execute([MockResponse::Batch(batch.clone())]);
+
+            let spark = mock.builder().record_fixtures(&path).build().await?;
+
+            spark
+                .range(None, 5, 1, None)
+                .filter("id > 1")
+                .collect()
+                .await?
+        };
+
+        assert_eq!(batch, recorded);
+
+        let server = ReplaySparkServer::start(&path).await?;
+        let spark = server.session().await?;
+
+        // the plan ids of this plan differ from the recorded one
+        let replayed = spark
+            .range(None, 5, 1, None)
+            .filter("id > 1")
+            .collect()
+            .await?;
+
+        assert_eq!(batch, replayed);
+
+        let unknown = spark.range(None, 7, 1, None).collect().await;
+
+        assert!(matches!(unknown, Err(SparkError::NotFound(_))));
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replay_server_error() -> Result<(), SparkError> {
+        let path = fixture_path();
+
+        let metadata = HashMap::from([
+            (
+                "errorClass".to_string(),
+                "TABLE_OR_VIEW_NOT_FOUND".to_string(),
+            ),
+            ("sqlState".to_string(), "42P01".to_string()),
+        ]);
+
+        {
+            let mock = MockSparkServer::start().await?;
+            mock.push_execute([MockResponse::Error(status_with_error_info(metadata))]);
+
+            let spark = mock.builder().record_fixtures(&path).build().await?;
+            let recorded = spark.table("missing")?.collect().await;
+
+            assert_eq!(Some("42P01"), recorded.unwrap_err().sql_state());
+        }
+
+        let server = ReplaySparkServer::start(&path).await?;
+        let spark = server.session().await?;
+
+        let err = spark.table("missing")?.collect().await.unwrap_err();
+
+        assert!(matches!(err, SparkError::Server { .. }));
+        assert_eq!(Some("TABLE_OR_VIEW_NOT_FOUND"), err.error_class());
+        assert_eq!(Some("42P01"), err.sql_state());
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
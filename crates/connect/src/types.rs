@@ -347,6 +347,8 @@ impl DataType {
             Self::String => spark::DataType {
                 kind: Some(spark::data_type::Kind::String(spark::data_type::String {
                     type_variation_reference,
+                    #[cfg(feature = "spark-4")]
+                    collation: String::new(),
                 })),
             },
             Self::Date => spark::DataType {
@@ -565,9 +567,7 @@ macro_rules! impl_to_proto_type {
             fn from(_value: $type) -> spark::DataType {
                 spark::DataType {
                     kind: Some(spark::data_type::Kind::$inner_type(
-                        spark::data_type::$inner_type {
-                            type_variation_reference: 0,
-                        },
+                        spark::data_type::$inner_type::default(),
                     )),
                 }
             }
@@ -0,0 +1,34 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+// This example starts a local Spark Connect server backed by DataFusion
+// on the default Spark Connect port, so the other examples can run without Spark
+//
+// cargo run --bin spark-connect-local --features local
+
+use spark_connect_rs::local::LocalSparkServer;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = "127.0.0.1:15002".parse()?;
+
+    println!("Serving Spark Connect on sc://{}", addr);
+
+    LocalSparkServer::serve(addr).await?;
+
+    Ok(())
+}